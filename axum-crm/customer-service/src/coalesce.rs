@@ -0,0 +1,62 @@
+//! Single-flight for hot reads: concurrent requests for the same key share one in-flight
+//! DB query instead of each taking a pooled connection. Only in-process, so it helps the
+//! long-lived Axum services; Spin instances share nothing to coalesce across.
+
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use futures::future::{BoxFuture, FutureExt, Shared};
+use std::future::Future;
+use std::hash::Hash;
+
+type Pending<V> = Shared<BoxFuture<'static, V>>;
+
+pub struct Coalescer<K, V> {
+    inflight: DashMap<K, Pending<V>>,
+}
+
+impl<K, V> Coalescer<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone + Send + Sync + 'static,
+{
+    pub fn new() -> Self {
+        Self {
+            inflight: DashMap::new(),
+        }
+    }
+
+    /// Awaits `fetch`, or the fetch already in flight for `key` if there is one. The flag
+    /// is `true` when this caller piggybacked on another request's query.
+    pub async fn run<F>(&self, key: K, fetch: F) -> (V, bool)
+    where
+        F: Future<Output = V> + Send + 'static,
+    {
+        let (pending, leader) = match self.inflight.entry(key.clone()) {
+            Entry::Occupied(e) => (e.get().clone(), None),
+            Entry::Vacant(e) => {
+                let pending = fetch.boxed().shared();
+                e.insert(pending.clone());
+                let leader = Leader {
+                    inflight: &self.inflight,
+                    key,
+                };
+                (pending, Some(leader))
+            }
+        };
+        let value = pending.await;
+        (value, leader.is_none())
+    }
+}
+
+/// Removes the in-flight entry once the leading request finishes or is cancelled, so a
+/// disconnected client never leaves a stale result behind for later readers.
+struct Leader<'a, K: Eq + Hash, V> {
+    inflight: &'a DashMap<K, Pending<V>>,
+    key: K,
+}
+
+impl<K: Eq + Hash, V> Drop for Leader<'_, K, V> {
+    fn drop(&mut self) {
+        self.inflight.remove(&self.key);
+    }
+}
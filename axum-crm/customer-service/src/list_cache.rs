@@ -0,0 +1,54 @@
+//! In-process cache for the serialized `GET /customers` body.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+/// Holds one serialized list body for `ttl`. Every write bumps `generation`, and a fill
+/// is only stored if no invalidation happened since its read started, so a slow reader
+/// can't repopulate the cache with pre-write data.
+pub struct ListCache {
+    ttl: Duration,
+    entry: RwLock<Option<(String, Instant)>>,
+    generation: AtomicU64,
+}
+
+impl ListCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entry: RwLock::new(None),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.ttl.is_zero()
+    }
+
+    /// Returns the cached body if it is still within the TTL.
+    pub fn get(&self) -> Option<String> {
+        let entry = self.entry.read().unwrap();
+        entry
+            .as_ref()
+            .filter(|(_, stored_at)| stored_at.elapsed() < self.ttl)
+            .map(|(body, _)| body.clone())
+    }
+
+    /// Snapshot to take before reading from the DB and hand to [`ListCache::fill`].
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    pub fn fill(&self, generation: u64, body: &str) {
+        let mut entry = self.entry.write().unwrap();
+        if self.generation.load(Ordering::Acquire) == generation {
+            *entry = Some((body.to_string(), Instant::now()));
+        }
+    }
+
+    pub fn invalidate(&self) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        *self.entry.write().unwrap() = None;
+    }
+}
@@ -0,0 +1,98 @@
+//! Create / get / list round-trips through [`build_app`] against a `#[sqlx::test]`
+//! database. Needs `DATABASE_URL` pointing at a server where the user may create
+//! databases, e.g. `postgres://crm@localhost/crm_containers`.
+
+use super::*;
+use axum::body::{to_bytes, Body};
+use axum::http::Request;
+use sqlx::postgres::PgPoolOptions;
+use tower::ServiceExt;
+
+async fn app(pool: PgPool) -> Router {
+    let replicas = Arc::new(ReadReplicas::connect("", PgPoolOptions::new()).await);
+    build_app(AppState::from_env(pool, replicas))
+}
+
+async fn send(app: &Router, method: &str, uri: &str, body: &str) -> (StatusCode, Value) {
+    let req = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let bytes = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+}
+
+#[sqlx::test(fixtures(path = "../../fixtures", scripts("schema")))]
+async fn create_then_get_customer(pool: PgPool) {
+    let app = app(pool).await;
+    let (status, created) =
+        send(&app, "POST", "/customers", r#"{"name":"Ada","email":"ada@example.com"}"#).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(created["name"], "Ada");
+    assert_eq!(created["email"], "ada@example.com");
+
+    let id = created["id"].as_i64().unwrap();
+    let (status, fetched) = send(&app, "GET", &format!("/customers/{}", id), "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(fetched, created);
+}
+
+#[sqlx::test(fixtures(path = "../../fixtures", scripts("schema")))]
+async fn list_returns_created_customers_in_id_order(pool: PgPool) {
+    let app = app(pool).await;
+    for name in ["Ada", "Grace"] {
+        let body = format!(r#"{{"name":"{}","email":"{}@example.com"}}"#, name, name.to_lowercase());
+        assert_eq!(send(&app, "POST", "/customers", &body).await.0, StatusCode::CREATED);
+    }
+
+    let (status, list) = send(&app, "GET", "/customers", "").await;
+    assert_eq!(status, StatusCode::OK);
+    let names: Vec<_> = list.as_array().unwrap().iter().map(|c| c["name"].clone()).collect();
+    assert_eq!(names, ["Ada", "Grace"]);
+}
+
+#[sqlx::test(fixtures(path = "../../fixtures", scripts("schema")))]
+async fn missing_customer_is_404(pool: PgPool) {
+    let app = app(pool).await;
+    let (status, body) = send(&app, "GET", "/customers/42", "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(body["error"].is_string());
+}
+
+#[sqlx::test(fixtures(path = "../../fixtures", scripts("schema")))]
+async fn bad_flag_is_json_400(pool: PgPool) {
+    let app = app(pool).await;
+    let (status, body) = send(&app, "GET", "/customers?envelope=maybe", "").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body, serde_json::json!({"error": "envelope must be true/false/1/0/yes/no"}));
+}
+
+/// `x-db-healthy` comes from the request's database work, not from the response builder:
+/// present on a DB-backed 404, absent on a 400 that never reached the database.
+#[sqlx::test(fixtures(path = "../../fixtures", scripts("schema")))]
+async fn db_health_header_follows_database_use(pool: PgPool) {
+    let app = app(pool).await;
+    let header = |uri: &str| {
+        let app = app.clone();
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        async move {
+            let resp = app.oneshot(req).await.unwrap();
+            let value = resp.headers().get("x-db-healthy").map(|v| v.to_str().unwrap().to_string());
+            (resp.status(), value)
+        }
+    };
+    assert_eq!(header("/customers/42").await, (StatusCode::NOT_FOUND, Some("true".into())));
+    assert_eq!(header("/customers/abc").await, (StatusCode::BAD_REQUEST, None));
+}
+
+/// Keys repeated across the elements of a bulk body are reported once.
+#[test]
+fn unknown_fields_are_listed_once() {
+    let body = br#"[{"nmae":"a","zz":1},{"nmae":"b"}]"#;
+    let err = parse_body::<Value>(body, CREATE_CUSTOMER_FIELDS, true).unwrap_err();
+    assert_eq!(err, "unknown fields: nmae, zz");
+}
@@ -0,0 +1,8 @@
+-- Tables for the `#[sqlx::test]` databases; same as the crm_containers part of k8s/postgres.yaml.
+CREATE TABLE customers (id BIGSERIAL PRIMARY KEY, name TEXT NOT NULL, email TEXT NOT NULL);
+CREATE TABLE orders (id BIGSERIAL PRIMARY KEY, customer_id BIGINT NOT NULL, product TEXT NOT NULL, quantity BIGINT NOT NULL);
+CREATE TABLE products (name TEXT PRIMARY KEY);
+CREATE TABLE order_audit (id BIGSERIAL PRIMARY KEY, order_id BIGINT NOT NULL, changed_at TIMESTAMPTZ NOT NULL DEFAULT now(), change TEXT NOT NULL);
+CREATE INDEX order_audit_order_id ON order_audit (order_id);
+CREATE TABLE schema_version (version BIGINT NOT NULL);
+INSERT INTO schema_version VALUES (2);
@@ -0,0 +1,35 @@
+//! Optional LRU of `/compute` results, sized by `COMPUTE_CACHE_SIZE` (0 or unset = off).
+
+use lru::LruCache;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+
+/// Fibonacci results keyed by `n`. Only the number is cached, so `format`, `raw` and
+/// `mem` are still applied per request. The cache is per process; Spin has no
+/// equivalent beyond whatever an individual instance keeps while it is reused.
+pub struct ComputeCache {
+    entries: Option<Mutex<LruCache<u64, u64>>>,
+}
+
+impl ComputeCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: NonZeroUsize::new(capacity).map(|c| Mutex::new(LruCache::new(c))),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.entries.is_some()
+    }
+
+    /// Looks up `n`, marking it most recently used.
+    pub fn get(&self, n: u64) -> Option<u64> {
+        self.entries.as_ref()?.lock().unwrap().get(&n).copied()
+    }
+
+    pub fn put(&self, n: u64, result: u64) {
+        if let Some(entries) = &self.entries {
+            entries.lock().unwrap().put(n, result);
+        }
+    }
+}
@@ -1,66 +1,347 @@
 use axum::{
     body::Bytes,
-    extract::{Query, State},
-    http::{Method, StatusCode},
+    extract::State,
+    http::{header::{ACCEPT, CONTENT_ENCODING, CONTENT_TYPE, RANGE}, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware,
     response::{IntoResponse, Response},
-    routing::get,
+    routing::{get, post},
     Router,
 };
+use compute_cache::ComputeCache;
+use crm_common::axum::query::ApiQuery;
+use crm_common::axum::telemetry::{self, Phase};
+use crm_common::axum::{catch_panic, concurrency, error_format, request_count, server, slo};
+use crm_common::compute;
+use crm_common::deadline;
+use crm_common::hops;
+use crm_common::path::{parse_bool, BOOL_EXPECTED};
+use crm_common::routes::{self, Route};
+use crm_common::secrets::mask_secrets;
+use crm_common::timing::merge_timings;
 use serde::Deserialize;
+use serde_json::{json, Map, Value};
 use std::env;
-use std::time::Instant;
+use std::net::SocketAddr;
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+
+mod compute_cache;
+#[cfg(test)]
+mod tests;
 
 #[derive(Clone)]
 struct AppState {
     client: reqwest::Client,
     customer_service_url: String,
     order_service_url: String,
+    debug_endpoints: bool,
+    max_retries: u32,
+    drain_retries: u32,
+    /// `MAX_HOPS`: forwards allowed before answering 508, see [`hops`].
+    max_hops: u32,
+    /// Requests served by this process, see [`request_count`].
+    request_count: Arc<AtomicU64>,
+    /// Leading requests flagged `x-warmup: true` (`WARMUP_REQUESTS`).
+    warmup_requests: u64,
+    /// `SLO_MS`, see [`slo`].
+    slo_budget: Duration,
+    /// `/compute` results when `COMPUTE_CACHE_SIZE` is set, see [`compute_cache`].
+    compute_cache: Arc<ComputeCache>,
+}
+
+impl AppState {
+    /// Reads every setting from the environment.
+    fn from_env() -> Self {
+        AppState {
+            client: build_client(),
+            customer_service_url: env::var("CUSTOMER_SERVICE_URL")
+                .unwrap_or_else(|_| "http://localhost:8001".to_string()),
+            order_service_url: env::var("ORDER_SERVICE_URL")
+                .unwrap_or_else(|_| "http://localhost:8002".to_string()),
+            debug_endpoints: env::var("DEBUG_ENDPOINTS")
+                .map(|v| v == "true")
+                .unwrap_or(false),
+            max_retries: env::var("UPSTREAM_MAX_RETRIES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(0),
+            drain_retries: env::var("UPSTREAM_DRAIN_RETRIES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(2),
+            max_hops: env::var("MAX_HOPS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(hops::DEFAULT_MAX),
+            request_count: Arc::new(AtomicU64::new(0)),
+            warmup_requests: request_count::warmup_from_env(),
+            slo_budget: slo::budget_from_env(),
+            compute_cache: Arc::new(ComputeCache::new(
+                env::var("COMPUTE_CACHE_SIZE")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(0),
+            )),
+        }
+    }
 }
 
+/// Headers whose values are never echoed back by `POST /echo`.
+const REDACTED_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "x-admin-token",
+    "x-signature",
+];
+/// Pause before resending a request that a draining upstream turned away.
+const DRAIN_RETRY_DELAY: Duration = Duration::from_millis(200);
+/// Upstream response headers passed through to the client unchanged.
+const FORWARDED_HEADERS: &[&str] = &[
+    "x-db-healthy",
+    "link",
+    "x-truncated",
+    "x-runtime",
+    "x-verify-outcome",
+    "content-range",
+    "x-debug-sql",
+    "content-disposition",
+    "preference-applied",
+];
+
 #[tokio::main]
 async fn main() {
-    let state = AppState {
-        client: reqwest::Client::new(),
-        customer_service_url: env::var("CUSTOMER_SERVICE_URL")
-            .unwrap_or_else(|_| "http://localhost:8001".to_string()),
-        order_service_url: env::var("ORDER_SERVICE_URL")
-            .unwrap_or_else(|_| "http://localhost:8002".to_string()),
-    };
+    let tracer_provider = telemetry::init("gateway");
+
+    let app = build_app(AppState::from_env());
+
+    server::serve(
+        concurrency::limit(app),
+        SocketAddr::from(([0, 0, 0, 0], 8000)),
+        server::stamp_gateway,
+    )
+    .await;
+    if let Some(provider) = tracer_provider {
+        let _ = provider.shutdown();
+    }
+}
+
+/// Served by `GET /routez` alongside the upstreams' own lists. `/echo` is listed only
+/// while debug endpoints are enabled.
+const ROUTES: &[Route] = &[
+    Route { path: "/healthz", methods: &["GET"] },
+    Route { path: "/compute", methods: &["GET"] },
+    Route { path: "/echo", methods: &["POST"] },
+    Route { path: "/schemaz", methods: &["GET"] },
+    Route { path: "/routez", methods: &["GET"] },
+];
 
-    let app = Router::new()
+/// Path prefixes proxied upstream, most specific first, as reported by `GET /routez`.
+const UPSTREAM_PREFIXES: &[(&str, &str)] = &[
+    ("/customers/{id}/orders", "order-service"),
+    ("/customers", "customer-service"),
+    ("/orders", "order-service"),
+    ("/products", "order-service"),
+    ("/admin/orders", "order-service"),
+];
+
+/// All routes and per-request middleware, without the process-level pieces (`serve`'s
+/// drain and runtime layers, the concurrency limit) so the app can also be driven
+/// in-process, e.g. with `tower::ServiceExt::oneshot` and the service URLs pointed at a
+/// stub upstream.
+fn build_app(state: AppState) -> Router {
+    Router::new()
         .route("/healthz", get(healthz))
+        // `get` also answers HEAD with the same headers and the body stripped
         .route("/compute", get(compute_handler))
+        .route("/echo", post(echo_handler))
+        .route("/schemaz", get(schemaz_handler))
+        .route("/routez", get(routez_handler))
         .fallback(proxy_handler)
-        .with_state(state);
+        .layer(catch_panic::layer())
+        .layer(middleware::from_fn(error_format::render_text_errors))
+        .layer(middleware::from_fn_with_state(state.slo_budget, slo::flag_violations))
+        .layer(middleware::from_fn(telemetry::trace_request))
+        .layer(middleware::from_fn_with_state(
+            (state.request_count.clone(), state.warmup_requests),
+            request_count::count_requests,
+        ))
+        .with_state(state)
+}
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:8000").await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+/// Builds the upstream HTTP client. `TLS_INSECURE=true` accepts self-signed certificates
+/// for `https://` service URLs in dev.
+fn build_client() -> reqwest::Client {
+    let insecure = env::var("TLS_INSECURE").map(|v| v == "true").unwrap_or(false);
+    reqwest::Client::builder()
+        .danger_accept_invalid_certs(insecure)
+        .build()
+        .expect("Failed to build HTTP client")
 }
 
+/// Kept as strings so every rejection can echo what was sent, see [`compute::error_body`].
 #[derive(Deserialize)]
 struct ComputeParams {
-    n: Option<u64>,
+    n: Option<String>,
+    format: Option<String>,
+    raw: Option<String>,
+    mem: Option<String>,
+}
+
+/// Resident set size (`VmRSS` in `/proc/self/status`), for comparison with Spin's linear
+/// memory. Reported in bytes whatever the page size; `None` off Linux.
+fn memory_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    vm_rss_bytes(&status)
+}
+
+/// `VmRSS:   1234 kB` as bytes.
+fn vm_rss_bytes(status: &str) -> Option<u64> {
+    let line = status.lines().find_map(|l| l.strip_prefix("VmRSS:"))?;
+    let kib: u64 = line.trim().strip_suffix("kB")?.trim().parse().ok()?;
+    Some(kib * 1024)
 }
 
 async fn healthz() -> impl IntoResponse {
     json_response(StatusCode::OK, r#"{"status":"ok"}"#)
 }
 
-async fn compute_handler(Query(params): Query<ComputeParams>) -> Response {
-    let n = params.n.unwrap_or(1000);
-    let t = Instant::now();
-    let result = fibonacci(n);
-    let compute_ms = t.elapsed().as_secs_f64() * 1000.0;
-    let body = format!(
-        r#"{{"n":{},"result":"{}","compute_ms":{:.3}}}"#,
-        n, result, compute_ms
+/// Aggregates the upstreams' `/schemaz`. An unreachable upstream reports `null` and
+/// counts as a mismatch.
+async fn schemaz_handler(State(state): State<AppState>) -> Response {
+    let (customer, order) = tokio::join!(
+        fetch_schemaz(&state.client, &state.customer_service_url),
+        fetch_schemaz(&state.client, &state.order_service_url),
     );
-    Response::builder()
+    let mismatch = [&customer, &order]
+        .iter()
+        .any(|v| v.get("mismatch") != Some(&Value::Bool(false)));
+    let body = json!({
+        "customer-service": customer,
+        "order-service": order,
+        "mismatch": mismatch,
+    });
+    json_response(StatusCode::OK, &body.to_string())
+}
+
+async fn fetch_schemaz(client: &reqwest::Client, base: &str) -> Value {
+    let resp = match client.get(format!("{}/schemaz", base)).send().await {
+        Ok(r) => r,
+        Err(_) => return Value::Null,
+    };
+    let body = resp.bytes().await.unwrap_or_default();
+    serde_json::from_slice(&body).unwrap_or(Value::Null)
+}
+
+/// Own routes, proxied prefixes and each upstream's `/routez`; an unreachable upstream
+/// reports `null`.
+async fn routez_handler(State(state): State<AppState>) -> Response {
+    let (customer, order) = tokio::join!(
+        fetch_routez(&state.client, &state.customer_service_url),
+        fetch_routez(&state.client, &state.order_service_url),
+    );
+    let own = ROUTES
+        .iter()
+        .filter(|r| r.path != "/echo" || state.debug_endpoints);
+    let body = routes::gateway_body(
+        &routes::routez_body(own),
+        UPSTREAM_PREFIXES,
+        customer.as_deref(),
+        order.as_deref(),
+    );
+    json_response(StatusCode::OK, &body)
+}
+
+async fn fetch_routez(client: &reqwest::Client, base: &str) -> Option<String> {
+    let resp = client.get(format!("{}/routez", base)).send().await.ok()?;
+    routes::upstream_list(&resp.bytes().await.ok()?)
+}
+
+async fn compute_handler(
+    State(state): State<AppState>,
+    ApiQuery(params): ApiQuery<ComputeParams>,
+) -> Response {
+    let raw_n = params.n.as_deref();
+    let hex = match params.format.as_deref() {
+        None | Some("dec") => false,
+        Some("hex") => true,
+        Some(format) => {
+            let extra = [("format", format.into())];
+            let body = compute::error_body("format must be dec or hex", raw_n, &extra);
+            return json_response(StatusCode::BAD_REQUEST, &body);
+        }
+    };
+    let flags = (
+        compute_flag("raw", params.raw.as_deref()),
+        compute_flag("mem", params.mem.as_deref()),
+    );
+    let (raw, mem) = match flags {
+        (Ok(raw), Ok(mem)) => (raw, mem),
+        (Err(msg), _) | (_, Err(msg)) => {
+            return json_response(StatusCode::BAD_REQUEST, &compute::error_body(&msg, raw_n, &[]))
+        }
+    };
+    let n = match compute::parse_n(raw_n) {
+        Ok(n) => n,
+        Err(body) => return json_response(StatusCode::BAD_REQUEST, &body),
+    };
+    // A hit reports zero compute time, so cached runs stand out in `server-timing`
+    let cached = state.compute_cache.get(n);
+    let (result, compute_ms) = match cached {
+        Some(result) => (result, 0.0),
+        None => {
+            let t_compute = Phase::start("compute");
+            let result = fibonacci(n);
+            let compute_ms = t_compute.finish();
+            state.compute_cache.put(n, result);
+            (result, compute_ms)
+        }
+    };
+    let result = if hex {
+        format!("{:x}", result)
+    } else {
+        result.to_string()
+    };
+    // raw=true skips the JSON envelope so benchmarks measure the loop, not serialization
+    let (content_type, body) = if raw {
+        ("text/plain", result)
+    } else {
+        let mut body = format!(
+            r#"{{"n":{},"result":"{}","compute_ms":{:.3}"#,
+            n, result, compute_ms
+        );
+        if mem {
+            let memory = memory_bytes().map_or("null".to_string(), |b| b.to_string());
+            body.push_str(&format!(r#","memory_bytes":{}"#, memory));
+        }
+        body.push('}');
+        ("application/json", body)
+    };
+    let mut resp = Response::builder()
         .status(StatusCode::OK)
-        .header("content-type", "application/json")
-        .header("server-timing", format!("compute;dur={:.3}", compute_ms))
+        .header("content-type", content_type)
+        .header(
+            "server-timing",
+            format!("compute;dur={:.3}, body;size={}", compute_ms, body.len()),
+        )
         .body(axum::body::Body::from(body))
-        .unwrap()
+        .unwrap();
+    if state.compute_cache.is_enabled() {
+        let status = if cached.is_some() { "HIT" } else { "MISS" };
+        resp.headers_mut()
+            .insert("x-cache", HeaderValue::from_static(status));
+    }
+    resp
+}
+
+/// `raw` / `mem`: absent is false, otherwise one of the [`parse_bool`] spellings.
+fn compute_flag(name: &str, value: Option<&str>) -> Result<bool, String> {
+    match value {
+        None => Ok(false),
+        Some(v) => parse_bool(v).ok_or_else(|| format!("{} {}", name, BOOL_EXPECTED)),
+    }
 }
 
 fn fibonacci(n: u64) -> u64 {
@@ -76,33 +357,133 @@ fn fibonacci(n: u64) -> u64 {
     b
 }
 
+/// Debug aid: returns what the gateway received without proxying. Disabled (404)
+/// unless `DEBUG_ENDPOINTS=true`.
+async fn echo_handler(
+    State(state): State<AppState>,
+    method: Method,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    if !state.debug_endpoints {
+        return json_response(StatusCode::NOT_FOUND, r#"{"error":"Not found"}"#);
+    }
+    let mut echoed = Map::new();
+    for (name, value) in &headers {
+        let value = if REDACTED_HEADERS.contains(&name.as_str()) {
+            "[redacted]".to_string()
+        } else {
+            String::from_utf8_lossy(value.as_bytes()).into_owned()
+        };
+        echoed.insert(name.as_str().to_string(), Value::String(value));
+    }
+    let body = json!({
+        "method": method.as_str(),
+        "headers": echoed,
+        "body": String::from_utf8_lossy(&body),
+    });
+    json_response(StatusCode::OK, &body.to_string())
+}
+
 async fn proxy_handler(
     State(state): State<AppState>,
     method: Method,
     uri: axum::http::Uri,
+    headers: HeaderMap,
     body: Bytes,
 ) -> Response {
     let path = uri.path();
 
-    let upstream_base = if path.starts_with("/customers") {
+    let upstream_base = if is_customer_orders_path(path) {
+        &state.order_service_url
+    } else if path.starts_with("/customers") {
         &state.customer_service_url
-    } else if path.starts_with("/orders") {
+    } else if path.starts_with("/orders")
+        || path.starts_with("/products")
+        || path.starts_with("/admin/orders")
+    {
         &state.order_service_url
     } else {
         return json_response(StatusCode::NOT_FOUND, r#"{"error":"Not found"}"#);
     };
 
-    let url = format!("{}{}", upstream_base, path);
+    let path_and_query = uri.path_and_query().map(|pq| pq.as_str()).unwrap_or(path);
+    let url = format!("{}{}", upstream_base, path_and_query);
 
-    let resp = state
-        .client
-        .request(method, &url)
-        .header("content-type", "application/json")
-        .body(body)
-        .send()
-        .await;
+    let hop = hops::next(headers.get(hops::HEADER).and_then(|v| v.to_str().ok()));
+    if hop > state.max_hops {
+        warn!(%method, path, hop, "loop detected: upstream URL points back at a gateway");
+        return json_response(StatusCode::LOOP_DETECTED, hops::LOOP_DETECTED_BODY);
+    }
+
+    // Only idempotent requests are retried, and only on transport errors
+    let max_retries = if is_idempotent(&method) {
+        retry_budget(&headers, state.max_retries)
+    } else {
+        0
+    };
+    let mut retries = 0;
+    let mut drain_retries = 0;
+    // The body keeps its media type, compressed bodies are passed through for the upstream
+    // to decode, `accept` and `prefer` let it negotiate e.g. CSV or a DELETE body, and the
+    // deadline is forwarded as-is so each hop computes its own remaining budget
+    let mut passthrough = HeaderMap::new();
+    let names = [
+        CONTENT_TYPE.as_str(),
+        CONTENT_ENCODING.as_str(),
+        RANGE.as_str(),
+        ACCEPT.as_str(),
+        "prefer",
+        deadline::HEADER,
+    ];
+    for name in names {
+        if let Some(v) = headers.get(name) {
+            passthrough.insert(name, v.clone());
+        }
+    }
+    passthrough.insert(hops::HEADER, hop.into());
+    let budget = deadline::remaining(headers.get(deadline::HEADER).and_then(|v| v.to_str().ok()));
+    if budget.is_some_and(|left| left.is_zero()) {
+        return json_response(StatusCode::GATEWAY_TIMEOUT, deadline::EXCEEDED_BODY);
+    }
 
-    match resp {
+    let t_upstream = Phase::start("upstream");
+    // Retries and drain pauses all count against the deadline
+    let attempts = async {
+        loop {
+            let result = state
+                .client
+                .request(method.clone(), &url)
+                .header("content-type", "application/json")
+                .headers(passthrough.clone())
+                .body(body.clone())
+                .send()
+                .await;
+            match result {
+                Err(_) if retries < max_retries => retries += 1,
+                // The request never reached a handler, so any method is safe to resend
+                Ok(r) if is_draining(&r) && drain_retries < state.drain_retries => {
+                    drain_retries += 1;
+                    tokio::time::sleep(DRAIN_RETRY_DELAY).await;
+                }
+                other => break other,
+            }
+        }
+    };
+    let resp = match budget {
+        None => attempts.await,
+        Some(left) => match tokio::time::timeout(left, attempts).await {
+            Ok(r) => r,
+            Err(_) => {
+                let mut resp = json_response(StatusCode::GATEWAY_TIMEOUT, deadline::EXCEEDED_BODY);
+                resp.headers_mut()
+                    .insert("x-retry-count", (retries + drain_retries).into());
+                return resp;
+            }
+        },
+    };
+
+    let mut resp = match resp {
         Ok(r) => {
             let status =
                 StatusCode::from_u16(r.status().as_u16()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
@@ -111,22 +492,89 @@ async fn proxy_handler(
                 .get("server-timing")
                 .and_then(|v| v.to_str().ok())
                 .map(|s| s.to_string());
-            let body = r.bytes().await.unwrap_or_default();
+            // Non-JSON bodies (e.g. NDJSON from `/orders/export`) keep their media type
+            let content_type = r
+                .headers()
+                .get("content-type")
+                .cloned()
+                .unwrap_or_else(|| HeaderValue::from_static("application/json"));
+            // The upstream replica's own counter, renamed so it doesn't clash with ours
+            let upstream_count = r.headers().get("x-request-count").cloned();
+            let forwarded: Vec<_> = FORWARDED_HEADERS
+                .iter()
+                .filter_map(|name| r.headers().get(*name).map(|v| (*name, v.clone())))
+                .collect();
+            // NDJSON exports and other chunked bodies are relayed as they arrive instead of
+            // being held in memory; `gateway` then covers the upstream's time to headers
+            let body = if is_streamed(&r) {
+                axum::body::Body::from_stream(r.bytes_stream())
+            } else {
+                axum::body::Body::from(r.bytes().await.unwrap_or_default())
+            };
+            let gateway_ms = t_upstream.finish();
+            let timing = merge_timings(
+                server_timing.as_deref().unwrap_or_default(),
+                &format!("gateway;dur={:.1}", gateway_ms),
+            );
             let mut builder = Response::builder()
                 .status(status)
-                .header("content-type", "application/json");
-            if let Some(timing) = &server_timing {
-                builder = builder.header("server-timing", timing.as_str());
+                .header("content-type", content_type)
+                .header("server-timing", timing);
+            for (name, value) in forwarded {
+                builder = builder.header(name, value);
             }
-            builder
-                .body(axum::body::Body::from(body))
-                .unwrap()
+            if let Some(count) = upstream_count {
+                builder = builder.header("x-upstream-request-count", count);
+            }
+            builder.body(body).unwrap()
         }
         Err(e) => {
-            let msg = format!(r#"{{"error":"Upstream unavailable: {}"}}"#, e);
-            json_response(StatusCode::BAD_GATEWAY, &msg)
+            let msg = format!("Upstream unavailable: {}", mask_secrets(&e.to_string()));
+            json_response(StatusCode::BAD_GATEWAY, &json!({ "error": msg }).to_string())
         }
-    }
+    };
+    resp.headers_mut()
+        .insert("x-retry-count", (retries + drain_retries).into());
+    resp
+}
+
+/// Retries allowed for this request: the client's `x-max-retries`, capped by the
+/// server-wide `UPSTREAM_MAX_RETRIES`.
+fn retry_budget(headers: &HeaderMap, server_max: u32) -> u32 {
+    headers
+        .get("x-max-retries")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u32>().ok())
+        .map_or(server_max, |requested| requested.min(server_max))
+}
+
+/// Whether an upstream body is relayed chunk by chunk: NDJSON, or any body sent without a
+/// `content-length`.
+fn is_streamed(resp: &reqwest::Response) -> bool {
+    let ndjson = resp
+        .headers()
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/x-ndjson"));
+    ndjson || resp.content_length().is_none()
+}
+
+/// A 503 carrying `x-draining: true` comes from an upstream that is shutting down.
+fn is_draining(resp: &reqwest::Response) -> bool {
+    resp.status() == reqwest::StatusCode::SERVICE_UNAVAILABLE
+        && resp.headers().get("x-draining").is_some_and(|v| v == "true")
+}
+
+fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::PUT | Method::DELETE | Method::OPTIONS
+    )
+}
+
+/// `/customers/{id}/orders` is owned by the order-service despite its prefix.
+fn is_customer_orders_path(path: &str) -> bool {
+    path.starts_with("/customers/") && path.trim_end_matches('/').ends_with("/orders")
 }
 
 fn json_response(status: StatusCode, body: &str) -> Response {
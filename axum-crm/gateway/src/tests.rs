@@ -0,0 +1,99 @@
+//! Proxying through [`build_app`] in-process, with the upstream URLs pointed at local
+//! stub servers instead of the real services, plus the `/compute?mem=true` RSS parsing.
+
+use super::*;
+use axum::body::{to_bytes, Body};
+use axum::http::Request;
+use axum::routing::any;
+use tokio::net::TcpListener;
+use tower::ServiceExt;
+
+/// Answers every request with its method, path, content type and body as JSON.
+async fn echo(method: Method, uri: axum::http::Uri, headers: HeaderMap, body: Bytes) -> Response {
+    let body = json!({
+        "method": method.as_str(),
+        "path": uri.path(),
+        "content_type": headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok()),
+        "body": String::from_utf8_lossy(&body),
+    });
+    json_response(StatusCode::OK, &body.to_string())
+}
+
+/// The gateway with both upstreams served by [`echo`] on an ephemeral port.
+async fn app() -> Router {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let upstream = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        axum::serve(listener, Router::new().fallback(any(echo))).await.unwrap();
+    });
+    let mut state = AppState::from_env();
+    state.customer_service_url = upstream.clone();
+    state.order_service_url = upstream;
+    build_app(state)
+}
+
+#[tokio::test]
+async fn patch_reaches_the_upstream_unchanged() {
+    let app = app().await;
+    let sent = r#"{"name":"Patched"}"#;
+    for content_type in ["application/json", "application/merge-patch+json"] {
+        let req = Request::builder()
+            .method("PATCH")
+            .uri("/customers/1")
+            .header("content-type", content_type)
+            .body(Body::from(sent))
+            .unwrap();
+        let resp = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let bytes = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let seen: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(seen["method"], "PATCH");
+        assert_eq!(seen["path"], "/customers/1");
+        assert_eq!(seen["content_type"], content_type);
+        assert_eq!(seen["body"], sent);
+    }
+}
+
+/// An NDJSON export reaches the client while the upstream is still sending it.
+#[tokio::test]
+async fn ndjson_is_relayed_before_the_upstream_finishes() {
+    use axum::body::HttpBody;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // Sends the headers and one line, then holds the response open
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let upstream = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut buf = [0u8; 4096];
+        let _ = socket.read(&mut buf).await.unwrap();
+        let head = "HTTP/1.1 200 OK\r\ncontent-type: application/x-ndjson\r\n\
+                    transfer-encoding: chunked\r\n\r\n";
+        socket.write_all(head.as_bytes()).await.unwrap();
+        socket.write_all(b"8\r\n{\"id\":1}\r\n").await.unwrap();
+        std::future::pending::<()>().await;
+    });
+    let mut state = AppState::from_env();
+    state.order_service_url = upstream;
+    let app = build_app(state);
+
+    let first_line = async {
+        let req = Request::builder().uri("/orders/export").body(Body::empty()).unwrap();
+        let resp = app.oneshot(req).await.unwrap();
+        assert_eq!(resp.headers()[CONTENT_TYPE], "application/x-ndjson");
+        let mut body = resp.into_body();
+        let frame = std::future::poll_fn(|cx| std::pin::Pin::new(&mut body).poll_frame(cx));
+        frame.await.unwrap().unwrap().into_data().unwrap()
+    };
+    let line = tokio::time::timeout(Duration::from_secs(5), first_line)
+        .await
+        .expect("first line was held back until the upstream finished");
+    assert_eq!(line, r#"{"id":1}"#);
+}
+
+#[test]
+fn vm_rss_is_read_in_bytes() {
+    let status = "Name:\tgateway\nVmHWM:\t    9000 kB\nVmRSS:\t    8512 kB\nRssAnon:\t 100 kB\n";
+    assert_eq!(vm_rss_bytes(status), Some(8512 * 1024));
+    assert_eq!(vm_rss_bytes("Name:\tgateway\n"), None);
+}
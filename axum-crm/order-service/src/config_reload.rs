@@ -0,0 +1,66 @@
+//! Runtime re-resolution of the customer-service URL. A process cannot see changes to its
+//! own environment, so the reloadable source is `CUSTOMER_SERVICE_URL_FILE` (e.g. a mounted
+//! ConfigMap key), re-read every `CONFIG_RELOAD_MS`. With either unset the URL from startup
+//! is used for the life of the process.
+
+use arc_swap::ArcSwap;
+use crm_common::secrets::mask_secrets;
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+const DEFAULT_CUSTOMER_SERVICE_URL: &str = "http://localhost:8001";
+
+/// The URL at startup: the file's contents when it is set and readable, otherwise
+/// `CUSTOMER_SERVICE_URL`, otherwise the local default.
+pub fn initial_customer_service_url() -> String {
+    read_url_file()
+        .or_else(|| env::var("CUSTOMER_SERVICE_URL").ok())
+        .unwrap_or_else(|| DEFAULT_CUSTOMER_SERVICE_URL.to_string())
+}
+
+/// Starts the reload task when both `CONFIG_RELOAD_MS` (> 0) and
+/// `CUSTOMER_SERVICE_URL_FILE` are set. An unreadable or empty file keeps the current URL.
+pub fn spawn(url: Arc<ArcSwap<String>>) {
+    let interval = env::var("CONFIG_RELOAD_MS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .filter(|&ms| ms > 0)
+        .map(Duration::from_millis);
+    let Some(interval) = interval else {
+        return;
+    };
+    if env::var("CUSTOMER_SERVICE_URL_FILE").is_err() {
+        warn!("CONFIG_RELOAD_MS is set without CUSTOMER_SERVICE_URL_FILE; not reloading");
+        return;
+    }
+    info!("Reloading customer-service URL every {:?}", interval);
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        // The first tick fires immediately and startup has just read the file
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            let Some(next) = read_url_file() else {
+                continue;
+            };
+            if **url.load() != next {
+                info!(
+                    "customer-service URL changed: {} -> {}",
+                    mask_secrets(&url.load()),
+                    mask_secrets(&next)
+                );
+                url.store(Arc::new(next));
+            }
+        }
+    });
+}
+
+fn read_url_file() -> Option<String> {
+    let path = env::var("CUSTOMER_SERVICE_URL_FILE").ok()?;
+    let contents = std::fs::read_to_string(path).ok()?;
+    let url = contents.trim();
+    (!url.is_empty()).then(|| url.to_string())
+}
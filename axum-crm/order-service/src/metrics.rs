@@ -0,0 +1,45 @@
+//! OTLP metrics for the order-service, exported to the same endpoint as its spans (see
+//! `crm_common::axum::telemetry`).
+
+use opentelemetry::global;
+use opentelemetry_otlp::MetricExporter;
+use opentelemetry_sdk::metrics::SdkMeterProvider;
+use opentelemetry_sdk::Resource;
+use std::env;
+
+/// Metrics counterpart of `telemetry::init`. Until it runs the global meter is a no-op,
+/// so [`count_verify`] costs next to nothing with OTLP off.
+pub fn init_metrics(service: &'static str) -> Option<SdkMeterProvider> {
+    env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+        .ok()
+        .filter(|v| !v.is_empty())?;
+    let exporter = MetricExporter::builder()
+        .with_http()
+        .build()
+        .expect("Failed to build OTLP metric exporter");
+    let provider = SdkMeterProvider::builder()
+        .with_periodic_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name(service).build())
+        .build();
+    global::set_meter_provider(provider.clone());
+    Some(provider)
+}
+
+/// Result of the customer-service check in `POST /orders`.
+#[derive(Clone, Copy)]
+pub enum VerifyOutcome {
+    Ok,
+    NotFound,
+    Error,
+}
+
+/// Adds one to `verify_ok`, `verify_not_found` or `verify_error`, so the share of failed
+/// checks during a run can be read off the collector.
+pub fn count_verify(outcome: VerifyOutcome) {
+    let name = match outcome {
+        VerifyOutcome::Ok => "verify_ok",
+        VerifyOutcome::NotFound => "verify_not_found",
+        VerifyOutcome::Error => "verify_error",
+    };
+    global::meter("order-service").u64_counter(name).build().add(1, &[]);
+}
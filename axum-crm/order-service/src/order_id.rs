@@ -0,0 +1,163 @@
+//! Order primary keys: `BIGSERIAL` by default, or `UUID` with `ID_STRATEGY=uuid` once
+//! `k8s/migrations/orders-uuid.sql` has been applied. Decoding follows the column type,
+//! so only parsing (path segments, id lists) needs to know which strategy is configured.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::encode::IsNull;
+use sqlx::error::BoxDynError;
+use sqlx::postgres::{PgArgumentBuffer, PgTypeInfo, PgValueRef};
+use sqlx::{Decode, Encode, Postgres, Type, TypeInfo};
+use std::env;
+use uuid::Uuid;
+
+/// Renders as a bare number or a string, so serial ids serialize exactly as before.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum OrderId {
+    Serial(i64),
+    Uuid(Uuid),
+}
+
+#[derive(Clone, Copy)]
+pub enum IdStrategy {
+    Serial,
+    Uuid,
+}
+
+impl IdStrategy {
+    /// `ID_STRATEGY`: `uuid`, anything else (or unset) keeps the serial default.
+    pub fn from_env() -> Self {
+        match env::var("ID_STRATEGY").as_deref() {
+            Ok("uuid") => IdStrategy::Uuid,
+            _ => IdStrategy::Serial,
+        }
+    }
+
+    /// Parses an `/orders/{id}` segment; `None` is reported as `Invalid order ID`.
+    pub fn parse(self, raw: &str) -> Option<OrderId> {
+        match self {
+            IdStrategy::Serial => raw.parse().ok().map(OrderId::Serial),
+            IdStrategy::Uuid => Uuid::parse_str(raw).ok().map(OrderId::Uuid),
+        }
+    }
+
+    /// Parses a JSON id list: positive integers (`1.0` and `"1"` are rejected rather than
+    /// coerced) or UUID strings, depending on the strategy. `None` if any entry is invalid.
+    pub fn parse_ids(self, ids: &[Value]) -> Option<OrderIds> {
+        match self {
+            IdStrategy::Serial => ids
+                .iter()
+                .map(|v| v.as_i64().filter(|&id| id > 0))
+                .collect::<Option<_>>()
+                .map(OrderIds::Serial),
+            IdStrategy::Uuid => ids
+                .iter()
+                .map(|v| v.as_str().and_then(|s| Uuid::parse_str(s).ok()))
+                .collect::<Option<_>>()
+                .map(OrderIds::Uuid),
+        }
+    }
+
+    /// What [`IdStrategy::parse_ids`] expects, for the 400 message.
+    pub fn ids_expected(self) -> &'static str {
+        match self {
+            IdStrategy::Serial => "ids must be positive integers",
+            IdStrategy::Uuid => "ids must be UUID strings",
+        }
+    }
+}
+
+/// A batch of ids bound as one `bigint[]` or `uuid[]` parameter, for `id = ANY($1)`.
+pub enum OrderIds {
+    Serial(Vec<i64>),
+    Uuid(Vec<Uuid>),
+}
+
+impl Type<Postgres> for OrderId {
+    fn type_info() -> PgTypeInfo {
+        <i64 as Type<Postgres>>::type_info()
+    }
+
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        <i64 as Type<Postgres>>::compatible(ty) || <Uuid as Type<Postgres>>::compatible(ty)
+    }
+}
+
+impl<'r> Decode<'r, Postgres> for OrderId {
+    fn decode(value: PgValueRef<'r>) -> Result<Self, BoxDynError> {
+        if sqlx::ValueRef::type_info(&value).name() == "UUID" {
+            Ok(OrderId::Uuid(<Uuid as Decode<Postgres>>::decode(value)?))
+        } else {
+            Ok(OrderId::Serial(<i64 as Decode<Postgres>>::decode(value)?))
+        }
+    }
+}
+
+impl Encode<'_, Postgres> for OrderId {
+    fn encode_by_ref(&self, buf: &mut PgArgumentBuffer) -> Result<IsNull, BoxDynError> {
+        match self {
+            OrderId::Serial(id) => <i64 as Encode<Postgres>>::encode_by_ref(id, buf),
+            OrderId::Uuid(id) => <Uuid as Encode<Postgres>>::encode_by_ref(id, buf),
+        }
+    }
+
+    // Declares the bound parameter's type per value, so `WHERE id = $1` matches either column
+    fn produces(&self) -> Option<PgTypeInfo> {
+        Some(match self {
+            OrderId::Serial(_) => <i64 as Type<Postgres>>::type_info(),
+            OrderId::Uuid(_) => <Uuid as Type<Postgres>>::type_info(),
+        })
+    }
+}
+
+impl Type<Postgres> for OrderIds {
+    fn type_info() -> PgTypeInfo {
+        <Vec<i64> as Type<Postgres>>::type_info()
+    }
+
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        <Vec<i64> as Type<Postgres>>::compatible(ty) || <Vec<Uuid> as Type<Postgres>>::compatible(ty)
+    }
+}
+
+impl Encode<'_, Postgres> for OrderIds {
+    fn encode_by_ref(&self, buf: &mut PgArgumentBuffer) -> Result<IsNull, BoxDynError> {
+        match self {
+            OrderIds::Serial(ids) => <Vec<i64> as Encode<Postgres>>::encode_by_ref(ids, buf),
+            OrderIds::Uuid(ids) => <Vec<Uuid> as Encode<Postgres>>::encode_by_ref(ids, buf),
+        }
+    }
+
+    fn produces(&self) -> Option<PgTypeInfo> {
+        Some(match self {
+            OrderIds::Serial(_) => <Vec<i64> as Type<Postgres>>::type_info(),
+            OrderIds::Uuid(_) => <Vec<Uuid> as Type<Postgres>>::type_info(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn serial_ids_must_be_positive_integers() {
+        let ids = IdStrategy::Serial.parse_ids(&[json!(1), json!(42)]);
+        assert!(matches!(ids, Some(OrderIds::Serial(v)) if v == [1, 42]));
+        for bad in [json!(0), json!(1.0), json!("1"), json!("00000000-0000-0000-0000-000000000001")] {
+            assert!(IdStrategy::Serial.parse_ids(std::slice::from_ref(&bad)).is_none(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn uuid_ids_must_be_uuid_strings() {
+        let id = "6f1c2f5e-9d3a-4b8e-a1c2-3d4e5f6a7b8c";
+        let ids = IdStrategy::Uuid.parse_ids(&[json!(id)]);
+        assert!(matches!(ids, Some(OrderIds::Uuid(v)) if v[0].to_string() == id));
+        for bad in [json!(1), json!("1"), json!("not-a-uuid")] {
+            assert!(IdStrategy::Uuid.parse_ids(std::slice::from_ref(&bad)).is_none(), "{bad}");
+        }
+    }
+}
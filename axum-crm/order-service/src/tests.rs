@@ -0,0 +1,233 @@
+//! Create / get / list round-trips and create/delete rules through [`build_app`] against a
+//! `#[sqlx::test]` database. Needs `DATABASE_URL` pointing at a server where the user may
+//! create databases, e.g. `postgres://crm@localhost/crm_containers`.
+
+use super::*;
+use axum::body::{to_bytes, Body};
+use axum::http::Request;
+use sqlx::postgres::PgPoolOptions;
+use tower::ServiceExt;
+
+/// Settings from the environment, with customer checks answered from the test database
+/// (`VERIFY_VIA=db`) so no customer-service has to be running. Tests that need another
+/// setting change that one field before [`build_app`].
+async fn state(pool: PgPool) -> AppState {
+    let replicas = Arc::new(ReadReplicas::connect("", PgPoolOptions::new()).await);
+    let mut state = AppState::from_env(pool, replicas);
+    state.verify_via = VerifyVia::Db;
+    state
+}
+
+async fn app(pool: PgPool) -> Router {
+    build_app(state(pool).await)
+}
+
+async fn add_customer(pool: &PgPool) -> i64 {
+    sqlx::query_scalar("INSERT INTO customers (name, email) VALUES ('Ada', 'ada@example.com') RETURNING id")
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+async fn send(app: &Router, method: &str, uri: &str, body: &str) -> (StatusCode, Value) {
+    let req = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let bytes = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+}
+
+#[sqlx::test(fixtures(path = "../../fixtures", scripts("schema")))]
+async fn create_then_get_order(pool: PgPool) {
+    let customer_id = add_customer(&pool).await;
+    let app = app(pool).await;
+    let body = format!(r#"{{"customer_id":{},"product":"Widget","quantity":3}}"#, customer_id);
+    let (status, created) = send(&app, "POST", "/orders", &body).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(created["customer_id"], customer_id);
+    assert_eq!(created["product"], "Widget");
+    assert_eq!(created["quantity"], 3);
+
+    let id = created["id"].as_i64().unwrap();
+    let (status, fetched) = send(&app, "GET", &format!("/orders/{}", id), "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(fetched, created);
+}
+
+#[sqlx::test(fixtures(path = "../../fixtures", scripts("schema")))]
+async fn list_groups_by_customer(pool: PgPool) {
+    let customer_id = add_customer(&pool).await;
+    let other_id = add_customer(&pool).await;
+    let app = app(pool).await;
+    for (customer, product) in [(customer_id, "Widget"), (other_id, "Gadget"), (customer_id, "Gizmo")] {
+        let body = format!(r#"{{"customer_id":{},"product":"{}","quantity":1}}"#, customer, product);
+        assert_eq!(send(&app, "POST", "/orders", &body).await.0, StatusCode::CREATED);
+    }
+
+    let (status, all) = send(&app, "GET", "/orders", "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(all.as_array().unwrap().len(), 3);
+
+    let uri = format!("/orders?customer_ids={},{}", customer_id, other_id);
+    let (status, grouped) = send(&app, "GET", &uri, "").await;
+    assert_eq!(status, StatusCode::OK);
+    let products = |id: i64| -> Vec<Value> {
+        grouped[id.to_string()].as_array().unwrap().iter().map(|o| o["product"].clone()).collect()
+    };
+    assert_eq!(products(customer_id), ["Widget", "Gizmo"]);
+    assert_eq!(products(other_id), ["Gadget"]);
+}
+
+#[sqlx::test(fixtures(path = "../../fixtures", scripts("schema")))]
+async fn order_for_unknown_customer_is_rejected(pool: PgPool) {
+    let app = app(pool).await;
+    let (status, body) =
+        send(&app, "POST", "/orders", r#"{"customer_id":42,"product":"Widget","quantity":1}"#).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body["error"].is_string());
+}
+
+/// With `ID_STRATEGY=uuid` the delete list is bound as `uuid[]`, not `bigint[]`.
+#[sqlx::test(fixtures(path = "../../fixtures", scripts("schema")))]
+async fn delete_by_uuid_ids(pool: PgPool) {
+    sqlx::raw_sql(include_str!("../../../k8s/migrations/orders-uuid.sql"))
+        .execute(&pool)
+        .await
+        .unwrap();
+    let customer_id = add_customer(&pool).await;
+    let mut state = state(pool).await;
+    state.id_strategy = IdStrategy::Uuid;
+    state.admin_token = Some("secret".into());
+    let app = build_app(state);
+
+    let body = format!(r#"{{"customer_id":{},"product":"Widget","quantity":1}}"#, customer_id);
+    let (status, created) = send(&app, "POST", "/orders", &body).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let id = created["id"].as_str().unwrap().to_string();
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/orders/delete")
+        .header("content-type", "application/json")
+        .header("x-admin-token", "secret")
+        .body(Body::from(format!(r#"{{"ids":["{}"]}}"#, id)))
+        .unwrap();
+    let resp = app.clone().oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let bytes = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(&bytes[..], br#"{"deleted":1}"#);
+
+    let (status, _) = send(&app, "GET", &format!("/orders/{}", id), "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[sqlx::test(fixtures(path = "../../fixtures", scripts("schema")))]
+async fn orders_past_the_cap_are_409(pool: PgPool) {
+    let customer_id = add_customer(&pool).await;
+    let mut state = state(pool).await;
+    state.max_orders_per_customer = 3;
+    let app = build_app(state);
+
+    let mut statuses = Vec::new();
+    for i in 0..5 {
+        let body = format!(r#"{{"customer_id":{},"product":"Limit {}","quantity":1}}"#, customer_id, i);
+        let (status, resp) = send(&app, "POST", "/orders", &body).await;
+        if status == StatusCode::CONFLICT {
+            assert_eq!(resp["error"], "order limit reached");
+        }
+        statuses.push(status.as_u16());
+    }
+    assert_eq!(statuses, [201, 201, 201, 409, 409]);
+}
+
+#[sqlx::test(fixtures(path = "../../fixtures", scripts("schema")))]
+async fn repeat_order_inside_window_is_409(pool: PgPool) {
+    let customer_id = add_customer(&pool).await;
+    let mut state = state(pool).await;
+    state.dedup_window_ms = 60_000;
+    let app = build_app(state);
+
+    let body = format!(r#"{{"customer_id":{},"product":"Dedup","quantity":1}}"#, customer_id);
+    assert_eq!(send(&app, "POST", "/orders", &body).await.0, StatusCode::CREATED);
+    let (status, resp) = send(&app, "POST", "/orders", &body).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(resp["error"], "duplicate order");
+}
+
+/// A customer deleted between the existence check and the insert trips the foreign key
+/// (`23503`); the trigger stands in for that concurrent delete.
+#[sqlx::test(fixtures(path = "../../fixtures", scripts("schema")))]
+async fn customer_deleted_before_insert_is_400(pool: PgPool) {
+    sqlx::raw_sql(
+        "ALTER TABLE orders ADD FOREIGN KEY (customer_id) REFERENCES customers (id);
+         CREATE FUNCTION delete_customer() RETURNS trigger LANGUAGE plpgsql AS $$
+         BEGIN DELETE FROM customers WHERE id = NEW.customer_id; RETURN NEW; END $$;
+         CREATE TRIGGER delete_customer BEFORE INSERT ON orders
+         FOR EACH ROW EXECUTE FUNCTION delete_customer();",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    let customer_id = add_customer(&pool).await;
+    let app = app(pool).await;
+
+    let body = format!(r#"{{"customer_id":{},"product":"Widget","quantity":1}}"#, customer_id);
+    let (status, resp) = send(&app, "POST", "/orders", &body).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(resp["error"], "referenced customer does not exist");
+}
+
+/// Create and increment share one quantity bound. A row stored above it (written before the
+/// column check) can still be reduced, and an increment near `i64::MAX` is refused with 400
+/// instead of overflowing.
+#[sqlx::test(fixtures(path = "../../fixtures", scripts("schema")))]
+async fn increment_checks_the_bound_it_moves_towards(pool: PgPool) {
+    let customer_id = add_customer(&pool).await;
+    let insert = |quantity: i64| {
+        sqlx::query_scalar::<_, i64>(
+            "INSERT INTO orders (customer_id, product, quantity) VALUES ($1, 'Widget', $2) \
+             RETURNING id",
+        )
+        .bind(customer_id)
+        .bind(quantity)
+        .fetch_one(&pool)
+    };
+    let big = format!("/orders/{}/increment", insert(3_000_000_000).await.unwrap());
+    let near_max = format!("/orders/{}/increment", insert(i64::MAX).await.unwrap());
+    let app = app(pool.clone()).await;
+
+    let create = |quantity: i64| {
+        format!(r#"{{"customer_id":{},"product":"Widget","quantity":{}}}"#, customer_id, quantity)
+    };
+    let (status, resp) = send(&app, "POST", "/orders", &create(2_147_483_648)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(resp["error"], "quantity out of range");
+
+    let (status, order) = send(&app, "POST", &big, r#"{"by":-1}"#).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(order["quantity"], 2_999_999_999_i64);
+    assert_eq!(send(&app, "POST", &big, r#"{"by":1}"#).await.0, StatusCode::BAD_REQUEST);
+
+    let (_, one) = send(&app, "POST", "/orders", &create(1)).await;
+    let one = format!("/orders/{}/increment", one["id"]);
+    assert_eq!(send(&app, "POST", &one, r#"{"by":-1}"#).await.0, StatusCode::BAD_REQUEST);
+    let (status, order) = send(&app, "POST", &one, r#"{"by":2147483646}"#).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(order["quantity"], i32::MAX);
+    assert_eq!(send(&app, "POST", &one, r#"{"by":1}"#).await.0, StatusCode::BAD_REQUEST);
+
+    // A BIGINT column: the bound is i64::MAX and neither direction may overflow
+    let mut state = state(pool).await;
+    state.quantity_is_int32 = false;
+    let app = build_app(state);
+    assert_eq!(send(&app, "POST", &near_max, r#"{"by":1}"#).await.0, StatusCode::BAD_REQUEST);
+    let by = format!(r#"{{"by":{}}}"#, -i64::MAX);
+    assert_eq!(send(&app, "POST", &one, &by).await.0, StatusCode::BAD_REQUEST);
+    let by = format!(r#"{{"by":{}}}"#, i64::MIN);
+    assert_eq!(send(&app, "POST", &one, &by).await.0, StatusCode::BAD_REQUEST);
+}
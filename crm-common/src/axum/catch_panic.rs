@@ -0,0 +1,49 @@
+//! Turns a panicking handler into the usual JSON 500 instead of a dropped connection.
+
+use crate::secrets::mask_secrets;
+use axum::{
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use std::any::Any;
+use tower_http::catch_panic::CatchPanicLayer;
+use tracing::error;
+
+/// Signature of [`panic_response`], named so the layer type can be spelled out.
+pub type PanicHandler = fn(Box<dyn Any + Send + 'static>) -> Response;
+
+/// `CatchPanicLayer` answering with [`panic_response`]. Layer it inside the `request` span.
+pub fn layer() -> CatchPanicLayer<PanicHandler> {
+    CatchPanicLayer::custom(panic_response as PanicHandler)
+}
+
+/// 500 for a handler that panicked, in the usual `{"error":...}` shape. Runs inside the
+/// `request` span, so the log line carries the method and path.
+pub fn panic_response(err: Box<dyn Any + Send + 'static>) -> Response {
+    let msg = err
+        .downcast_ref::<String>()
+        .map(String::as_str)
+        .or_else(|| err.downcast_ref::<&str>().copied())
+        .unwrap_or("non-string panic payload");
+    error!(panic = %mask_secrets(msg), "handler panicked");
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        [(header::CONTENT_TYPE, HeaderValue::from_static("application/json"))],
+        r#"{"error":"Internal server error"}"#,
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn string_and_str_payloads_give_json_500() {
+        for payload in [Box::new("boom") as Box<dyn Any + Send>, Box::new(String::from("boom"))] {
+            let resp = panic_response(payload);
+            assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+            assert_eq!(resp.headers()[header::CONTENT_TYPE], "application/json");
+        }
+    }
+}
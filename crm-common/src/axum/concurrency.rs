@@ -0,0 +1,58 @@
+//! Per-process cap on in-flight requests, `MAX_CONCURRENT` (unset or 0 = unlimited).
+//! Requests past the cap wait for a slot, or with `CONCURRENCY_REJECT=true` are turned
+//! away immediately with 429 so overload shows up at the client instead of as latency.
+//! The global variant shares one semaphore across routes, which `Router::layer` layers separately.
+
+use axum::{
+    error_handling::HandleErrorLayer,
+    http::{HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Router,
+};
+use std::env;
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower::load_shed::LoadShedLayer;
+use tower::{BoxError, ServiceBuilder};
+use tracing::info;
+
+/// Wraps `app` in the configured limit; returns it unchanged when none is set.
+pub fn limit(app: Router) -> Router {
+    let Some(max) = env::var("MAX_CONCURRENT")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|&n| n > 0)
+    else {
+        return app;
+    };
+    let reject = env::var("CONCURRENCY_REJECT")
+        .map(|v| v == "true")
+        .unwrap_or(false);
+    info!(
+        "Concurrency limit: {} ({})",
+        max,
+        if reject { "reject with 429" } else { "queue" }
+    );
+
+    if reject {
+        app.layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(|_: BoxError| async { too_many_requests() }))
+                .layer(LoadShedLayer::new())
+                .layer(GlobalConcurrencyLimitLayer::new(max)),
+        )
+    } else {
+        app.layer(GlobalConcurrencyLimitLayer::new(max))
+    }
+}
+
+fn too_many_requests() -> Response {
+    let mut resp = (
+        StatusCode::TOO_MANY_REQUESTS,
+        [("content-type", "application/json")],
+        r#"{"error":"Too many concurrent requests"}"#,
+    )
+        .into_response();
+    resp.headers_mut()
+        .insert("retry-after", HeaderValue::from_static("1"));
+    resp
+}
@@ -0,0 +1,127 @@
+//! Pool setup and connection checkout shared by the customer and order services (`sqlx`
+//! feature). Checkout failures are classified so pool saturation answers 503 rather than
+//! the generic database 500.
+
+use super::db_health;
+use crate::db_faults;
+use crate::secrets::mask_secrets;
+use axum::{
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use sqlx::pool::PoolConnection;
+use sqlx::postgres::PgPoolOptions;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{info, warn};
+
+/// Pool settings shared by the primary and the replicas. With `DB_SCHEMA` set, every new
+/// connection runs `search_path` first, so the unqualified table names resolve there.
+pub fn pool_options(max_connections: u32, search_path: Option<String>) -> PgPoolOptions {
+    use sqlx::Executor;
+
+    let options = PgPoolOptions::new().max_connections(max_connections);
+    let Some(sql) = search_path else {
+        return options;
+    };
+    let sql: Arc<str> = sql.into();
+    options.after_connect(move |conn, _meta| {
+        let sql = sql.clone();
+        Box::pin(async move { conn.execute(&*sql).await.map(|_| ()) })
+    })
+}
+
+/// Opens `count` connections up front so the first requests after boot don't pay
+/// connection setup. All connections are held at once to force distinct sockets.
+pub async fn warm_up_pool(pool: &PgPool, count: u32) {
+    if count == 0 {
+        return;
+    }
+    let t = Instant::now();
+    let mut conns = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        match pool.acquire().await {
+            Ok(c) => conns.push(c),
+            Err(e) => {
+                warn!("Pool warm-up stopped early: {}", mask_secrets(&e.to_string()));
+                break;
+            }
+        }
+    }
+    let warmed = conns.len();
+    drop(conns);
+    info!(
+        "Warmed up {} DB connections in {:.1}ms",
+        warmed,
+        t.elapsed().as_secs_f64() * 1000.0
+    );
+}
+
+/// Why `acquire_or_error` failed: the pool is saturated (retryable) or the DB is unreachable.
+#[derive(Clone)]
+pub enum AcquireError {
+    Exhausted,
+    Unavailable,
+}
+
+impl From<sqlx::Error> for AcquireError {
+    fn from(e: sqlx::Error) -> Self {
+        match e {
+            sqlx::Error::PoolTimedOut | sqlx::Error::PoolClosed => AcquireError::Exhausted,
+            _ => AcquireError::Unavailable,
+        }
+    }
+}
+
+impl IntoResponse for AcquireError {
+    fn into_response(self) -> Response {
+        match self {
+            AcquireError::Exhausted => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                [
+                    (header::CONTENT_TYPE, HeaderValue::from_static("application/json")),
+                    (header::RETRY_AFTER, HeaderValue::from_static("1")),
+                ],
+                r#"{"error":"Database pool exhausted"}"#,
+            )
+                .into_response(),
+            AcquireError::Unavailable => db_error(),
+        }
+    }
+}
+
+/// Acquires a pooled connection, classifying failures so saturation surfaces as 503
+/// with `Retry-After` (an autoscaling signal) instead of the generic DB 500. Also the
+/// point where `DB_FAIL_RATE` injects failures, see [`db_faults`].
+pub async fn acquire_or_error(pool: &PgPool) -> Result<PoolConnection<Postgres>, AcquireError> {
+    if db_faults::should_fail() {
+        return Err(AcquireError::Unavailable);
+    }
+    let conn = pool.acquire().await.map_err(AcquireError::from)?;
+    db_health::record(true);
+    Ok(conn)
+}
+
+/// [`acquire_or_error`] for a transaction on the primary.
+pub async fn begin_or_error(
+    pool: &PgPool,
+) -> Result<Transaction<'static, Postgres>, AcquireError> {
+    if db_faults::should_fail() {
+        return Err(AcquireError::Unavailable);
+    }
+    let tx = pool.begin().await.map_err(AcquireError::from)?;
+    db_health::record(true);
+    Ok(tx)
+}
+
+/// 500 for a failed acquire or query; reported as `x-db-healthy: false`, see [`db_health`].
+pub fn db_error() -> Response {
+    db_health::record(false);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        [(header::CONTENT_TYPE, HeaderValue::from_static("application/json"))],
+        r#"{"error":"Database error"}"#,
+    )
+        .into_response()
+}
@@ -0,0 +1,38 @@
+//! `x-db-healthy`, set in one place from what the request's database work did instead of by
+//! each response builder: `true` once a connection was acquired and nothing failed, `false`
+//! after a failed acquire or query, and absent when the request never reached the database
+//! (validation 400s, cache hits, pool-exhausted 503s).
+
+use axum::{extract::Request, http::HeaderValue, middleware::Next, response::Response};
+use std::cell::Cell;
+
+tokio::task_local! {
+    static OUTCOME: Cell<Option<bool>>;
+}
+
+/// Records a database outcome for the current request; once `false`, it stays `false`.
+/// Outside [`stamp_db_health`] (background tasks, unit tests) this is a no-op.
+pub fn record(healthy: bool) {
+    let _ = OUTCOME.try_with(|outcome| {
+        if outcome.get() != Some(false) {
+            outcome.set(Some(healthy));
+        }
+    });
+}
+
+/// Middleware: runs the request with a fresh outcome and stamps `x-db-healthy` from it.
+/// Handlers that spawn their database work must [`record`] the result themselves.
+pub async fn stamp_db_health(req: Request, next: Next) -> Response {
+    let (mut resp, outcome) = OUTCOME
+        .scope(Cell::new(None), async {
+            let resp = next.run(req).await;
+            (resp, OUTCOME.with(Cell::get))
+        })
+        .await;
+    if let Some(healthy) = outcome {
+        let value = if healthy { "true" } else { "false" };
+        resp.headers_mut()
+            .insert("x-db-healthy", HeaderValue::from_static(value));
+    }
+    resp
+}
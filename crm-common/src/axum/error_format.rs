@@ -0,0 +1,37 @@
+//! Middleware side of [`crate::error_format`].
+
+use crate::error_format::{error_message, wants_text};
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+
+/// Middleware: rewrites error responses to their bare `error` message as `text/plain`
+/// for `?error_format=text`, or an `Accept` header asking for `text/plain` but not JSON.
+pub async fn render_text_errors(req: Request, next: Next) -> Response {
+    let accept = req.headers().get(header::ACCEPT).and_then(|v| v.to_str().ok());
+    let text = wants_text(req.uri().query().unwrap_or_default(), accept);
+    let resp = next.run(req).await;
+    if !text || !(resp.status().is_client_error() || resp.status().is_server_error()) {
+        return resp;
+    }
+
+    let (mut parts, body) = resp.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .unwrap_or_default();
+    match error_message(&bytes) {
+        Some(message) => {
+            parts.headers.insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("text/plain; charset=utf-8"),
+            );
+            parts.headers.remove(header::CONTENT_LENGTH);
+            Response::from_parts(parts, Body::from(message))
+        }
+        None => Response::from_parts(parts, Body::from(bytes)),
+    }
+}
@@ -0,0 +1,18 @@
+//! Glue shared by the three Axum services (`axum` feature): middleware, server setup and,
+//! with the `sqlx` feature, database helpers. Each service only wires these into its
+//! router and `main`.
+
+pub mod catch_panic;
+pub mod concurrency;
+#[cfg(feature = "sqlx")]
+pub mod db;
+pub mod db_health;
+pub mod error_format;
+pub mod query;
+#[cfg(feature = "sqlx")]
+pub mod replicas;
+pub mod request_count;
+pub mod server;
+pub mod shutdown;
+pub mod slo;
+pub mod telemetry;
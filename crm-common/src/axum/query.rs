@@ -0,0 +1,84 @@
+//! `Query` extractor whose rejections are the JSON [`ApiError`] body Spin sends for the same
+//! input (`{"error":"envelope must be true/false/1/0/yes/no"}`), instead of axum's plain-text
+//! `Failed to deserialize query string`.
+
+use crate::path::BOOL_EXPECTED;
+use crate::ApiError;
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use axum::http::{header::CONTENT_TYPE, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde::de::DeserializeOwned;
+
+/// Drop-in for `axum::extract::Query<T>`.
+pub struct ApiQuery<T>(pub T);
+
+impl<T, S> FromRequestParts<S> for ApiQuery<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Response> {
+        parse(parts.uri.query().unwrap_or_default())
+            .map(ApiQuery)
+            .map_err(|e| {
+                let status = StatusCode::from_u16(e.status).unwrap_or(StatusCode::BAD_REQUEST);
+                let mut resp = (status, e.body()).into_response();
+                resp.headers_mut()
+                    .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+                resp
+            })
+    }
+}
+
+/// Deserializes `query` into `T`, naming the offending parameter in the 400 message.
+fn parse<T: DeserializeOwned>(query: &str) -> Result<T, ApiError> {
+    let de = serde_urlencoded::Deserializer::new(form_urlencoded::parse(query.as_bytes()));
+    serde_path_to_error::deserialize(de).map_err(|err| {
+        let path = err.path().to_string();
+        let msg = err.inner().to_string();
+        if msg == BOOL_EXPECTED {
+            ApiError::bad_request(format!("{} {}", path, msg))
+        } else {
+            ApiError::bad_request(format!("{}: {}", path, msg))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+    use crate::path::deserialize_bool;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize)]
+    struct Params {
+        #[serde(default, deserialize_with = "deserialize_bool")]
+        envelope: Option<bool>,
+        limit: Option<i64>,
+    }
+
+    #[test]
+    fn accepts_valid_params() {
+        let p: Params = parse("envelope=YES&limit=5").unwrap();
+        assert_eq!((p.envelope, p.limit), (Some(true), Some(5)));
+        let p: Params = parse("").unwrap();
+        assert_eq!((p.envelope, p.limit), (None, None));
+    }
+
+    #[test]
+    fn bad_flag_gets_the_spin_message() {
+        let err = parse::<Params>("envelope=maybe").unwrap_err();
+        assert_eq!(err.status, 400);
+        assert_eq!(err.body(), r#"{"error":"envelope must be true/false/1/0/yes/no"}"#);
+    }
+
+    #[test]
+    fn other_errors_name_the_parameter() {
+        let err = parse::<Params>("limit=abc").unwrap_err();
+        assert_eq!(err.status, 400);
+        assert!(err.message.starts_with("limit: "), "{}", err.message);
+    }
+}
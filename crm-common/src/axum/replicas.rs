@@ -0,0 +1,55 @@
+//! Read replicas from `DATABASE_READ_URL` (comma-separated), picked round-robin for
+//! read-only handlers. With no replicas configured every read falls back to the primary.
+
+use crate::secrets::mask_secrets;
+use sqlx::postgres::PgPoolOptions;
+use sqlx::PgPool;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tracing::{debug, error, info};
+
+pub struct ReadReplicas {
+    pools: Vec<PgPool>,
+    next: AtomicUsize,
+}
+
+impl ReadReplicas {
+    /// Connects one pool per non-empty URL in `urls`, each built from `options`.
+    pub async fn connect(urls: &str, options: PgPoolOptions) -> Self {
+        let mut pools = Vec::new();
+        for url in urls.split(',').map(str::trim).filter(|u| !u.is_empty()) {
+            let pool = options
+                .clone()
+                .connect(url)
+                .await
+                .unwrap_or_else(|e| {
+                    error!("Failed to connect to read replica: {}", mask_secrets(&e.to_string()));
+                    std::process::exit(1);
+                });
+            pools.push(pool);
+        }
+        if !pools.is_empty() {
+            info!("Read replicas: {}", pools.len());
+        }
+        ReadReplicas {
+            pools,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the next replica, or `primary` when none are configured.
+    pub fn pick<'a>(&'a self, primary: &'a PgPool) -> &'a PgPool {
+        if self.pools.is_empty() {
+            debug!("read served by primary");
+            return primary;
+        }
+        let i = self.next.fetch_add(1, Ordering::Relaxed) % self.pools.len();
+        debug!("read served by replica {}", i);
+        &self.pools[i]
+    }
+
+    pub async fn close(&self) {
+        for pool in &self.pools {
+            pool.close().await;
+        }
+    }
+}
@@ -0,0 +1,44 @@
+//! `x-request-count`: the running total of requests this process has served, so load
+//! spread across replicas can be eyeballed from responses without a metrics stack.
+//!
+//! `WARMUP_REQUESTS` (default 0): the first N of those responses also carry
+//! `x-warmup: true`, letting a benchmark drop cold samples by header instead of by guess.
+
+use axum::{
+    extract::{Request, State},
+    http::HeaderValue,
+    middleware::Next,
+    response::Response,
+};
+use std::env;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tracing::info;
+
+/// `WARMUP_REQUESTS`; unset or unparsable means no warmup window.
+pub fn warmup_from_env() -> u64 {
+    env::var("WARMUP_REQUESTS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Middleware: counts the request on arrival and stamps the new total on its response,
+/// plus `x-warmup: true` while the total is still within the first `warmup` requests.
+pub async fn count_requests(
+    State((counter, warmup)): State<(Arc<AtomicU64>, u64)>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let count = counter.fetch_add(1, Ordering::Relaxed) + 1;
+    let mut resp = next.run(req).await;
+    let headers = resp.headers_mut();
+    headers.insert("x-request-count", HeaderValue::from(count));
+    if count <= warmup {
+        headers.insert("x-warmup", HeaderValue::from_static("true"));
+        if count == warmup {
+            info!("Warmup finished after {} requests", warmup);
+        }
+    }
+    resp
+}
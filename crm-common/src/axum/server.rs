@@ -0,0 +1,266 @@
+//! Listening socket, hyper settings and the serve loop shared by the Axum services.
+
+use super::shutdown::{self, Drain};
+use axum::body::{Body, Bytes, HttpBody};
+use axum::extract::{Request, State};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::Response;
+use axum::Router;
+use axum_server::tls_rustls::RustlsConfig;
+use axum_server::Handle;
+use http_body::{Frame, SizeHint};
+use hyper_util::rt::{TokioExecutor, TokioTimer};
+use hyper_util::server::conn::auto::Builder;
+use socket2::{Domain, Protocol, Socket, TcpKeepalive, Type};
+use std::env;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::Sleep;
+use tracing::{error, info};
+
+/// `MAX_HEADER_BYTES` default, see [`configure_http`].
+const DEFAULT_MAX_HEADER_BYTES: usize = 16 * 1024;
+/// Smallest read buffer hyper accepts; `max_buf_size` panics below it.
+const MIN_HEADER_BUF_BYTES: usize = 8 * 1024;
+/// Framework name sent as `x-runtime`.
+const RUNTIME: &str = "axum";
+
+/// Serves `app` over HTTPS when `TLS_CERT_PATH` and `TLS_KEY_PATH` are set, plain HTTP when
+/// neither is; setting only one exits rather than silently serving HTTP. Returns once a shutdown signal has been received and in-flight requests have drained.
+/// `stamp` adds the runtime headers, see [`stamp_service`] and [`stamp_gateway`].
+pub async fn serve(app: Router, addr: SocketAddr, stamp: fn(&mut HeaderMap)) {
+    let tls = match tls_files(env::var("TLS_CERT_PATH").ok(), env::var("TLS_KEY_PATH").ok()) {
+        Ok(tls) => tls,
+        Err(msg) => {
+            error!("{}", msg);
+            std::process::exit(1);
+        }
+    };
+    let drain = Arc::new(Drain::default());
+    let app = match ms_from_env("READ_BODY_TIMEOUT_MS") {
+        Some(timeout) => app.layer(middleware::from_fn_with_state(timeout, body_deadline)),
+        None => app,
+    };
+    // `x-runtime` goes outermost so drain and concurrency rejections carry it as well
+    let app = app
+        .layer(middleware::from_fn_with_state(
+            drain.clone(),
+            shutdown::reject_while_draining,
+        ))
+        .layer(middleware::map_response(move |mut resp: Response| async move {
+            stamp(resp.headers_mut());
+            resp
+        }));
+    let handle = Handle::new();
+    tokio::spawn(shutdown::shutdown_on_signal(drain.clone(), handle.clone()));
+
+    match tls {
+        Some((cert, key)) => {
+            let config = RustlsConfig::from_pem_file(cert, key)
+                .await
+                .expect("Failed to load TLS certificate");
+            info!("Listening on https://{}", addr);
+            let mut server =
+                axum_server::from_tcp_rustls(bind_listener(addr), config).handle(handle);
+            configure_http(server.http_builder());
+            server
+                .serve(app.into_make_service())
+                .await
+                .expect("Server error");
+        }
+        None => {
+            info!("Listening on http://{}", addr);
+            let mut server = axum_server::from_tcp(bind_listener(addr)).handle(handle);
+            configure_http(server.http_builder());
+            server
+                .serve(app.into_make_service())
+                .await
+                .expect("Server error");
+        }
+    }
+    info!(
+        "Server stopped; {} requests were still in flight",
+        drain.in_flight()
+    );
+}
+
+/// The `(cert, key)` PEM paths, or `None` for plain HTTP. A lone path is an error: a typo in
+/// one variable would otherwise downgrade the service to HTTP without notice.
+fn tls_files(cert: Option<String>, key: Option<String>) -> Result<Option<(String, String)>, String> {
+    match (cert, key) {
+        (Some(cert), Some(key)) => Ok(Some((cert, key))),
+        (None, None) => Ok(None),
+        (Some(_), None) => Err("TLS_CERT_PATH is set but TLS_KEY_PATH is not".to_string()),
+        (None, Some(_)) => Err("TLS_KEY_PATH is set but TLS_CERT_PATH is not".to_string()),
+    }
+}
+
+/// Marks every response with the framework that served it (`x-runtime: axum`), so a
+/// benchmark harness hitting a mixed fleet can attribute results without tracking ports.
+pub fn stamp_service(headers: &mut HeaderMap) {
+    headers.insert("x-runtime", HeaderValue::from_static(RUNTIME));
+}
+
+/// Adds `x-gateway-runtime: axum` to every response. `x-runtime` names the framework that
+/// produced the body: the upstream's value is forwarded on proxied paths, and responses the
+/// gateway answers itself (`/compute`, errors) get its own.
+pub fn stamp_gateway(headers: &mut HeaderMap) {
+    headers.insert("x-gateway-runtime", HeaderValue::from_static(RUNTIME));
+    if !headers.contains_key("x-runtime") {
+        headers.insert("x-runtime", HeaderValue::from_static(RUNTIME));
+    }
+}
+
+/// Binds the listening socket with `TCP_NODELAY` (on unless `TCP_NODELAY=false`) and, when
+/// `TCP_KEEPALIVE_SECS` is set, TCP keep-alive. Accepted connections inherit both on Linux,
+/// so small JSON responses aren't held back by Nagle's algorithm.
+fn bind_listener(addr: SocketAddr) -> std::net::TcpListener {
+    let nodelay = env::var("TCP_NODELAY").map(|v| v != "false").unwrap_or(true);
+    let keepalive = env::var("TCP_KEEPALIVE_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&secs| secs > 0)
+        .map(Duration::from_secs);
+
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))
+        .expect("Failed to create socket");
+    socket
+        .set_reuse_address(true)
+        .expect("Failed to set SO_REUSEADDR");
+    socket
+        .set_tcp_nodelay(nodelay)
+        .expect("Failed to set TCP_NODELAY");
+    if let Some(time) = keepalive {
+        socket
+            .set_tcp_keepalive(&TcpKeepalive::new().with_time(time))
+            .expect("Failed to set TCP keep-alive");
+    }
+    socket.set_nonblocking(true).expect("Failed to set non-blocking");
+    socket.bind(&addr.into()).expect("Failed to bind");
+    socket.listen(1024).expect("Failed to listen");
+    info!(
+        "Socket options: TCP_NODELAY={}, keep-alive={}",
+        nodelay,
+        keepalive.map_or("off".to_string(), |t| format!("{}s", t.as_secs()))
+    );
+    socket.into()
+}
+
+/// Closes connections whose request headers don't arrive within `READ_HEADER_TIMEOUT_MS`
+/// (slowloris protection). Unset or 0 leaves hyper's defaults untouched. The body has its
+/// own deadline, `READ_BODY_TIMEOUT_MS`, see [`body_deadline`].
+///
+/// Also caps the request head at `MAX_HEADER_BYTES` (default 16 KiB, raised to hyper's
+/// 8 KiB minimum): hyper answers a larger HTTP/1 head with 431 instead of resetting the
+/// connection, and HTTP/2 gets the same limit on its header list.
+fn configure_http(builder: &mut Builder<TokioExecutor>) {
+    let max_header_bytes = env::var("MAX_HEADER_BYTES")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_MAX_HEADER_BYTES)
+        .max(MIN_HEADER_BUF_BYTES);
+    builder.http1().max_buf_size(max_header_bytes);
+    builder
+        .http2()
+        .max_header_list_size(u32::try_from(max_header_bytes).unwrap_or(u32::MAX));
+    if let Some(timeout) = ms_from_env("READ_HEADER_TIMEOUT_MS") {
+        builder
+            .http1()
+            .timer(TokioTimer::new())
+            .header_read_timeout(timeout);
+    }
+}
+
+/// A positive millisecond setting; unset, unparsable or 0 is `None`.
+fn ms_from_env(name: &str) -> Option<Duration> {
+    env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&ms| ms > 0)
+        .map(Duration::from_millis)
+}
+
+/// Middleware: the whole request body must arrive within `timeout` of the headers, so a
+/// client trickling its body can't hold the connection open. A late body gets 408 and the
+/// connection is closed.
+async fn body_deadline(State(timeout): State<Duration>, req: Request, next: Next) -> Response {
+    if req.body().is_end_stream() {
+        return next.run(req).await;
+    }
+    let expired = Arc::new(AtomicBool::new(false));
+    let req = req.map(|body| {
+        Body::new(DeadlineBody {
+            inner: body,
+            sleep: Box::pin(tokio::time::sleep(timeout)),
+            expired: expired.clone(),
+        })
+    });
+    let resp = next.run(req).await;
+    if !expired.load(Ordering::Relaxed) {
+        return resp;
+    }
+    Response::builder()
+        .status(StatusCode::REQUEST_TIMEOUT)
+        .header("content-type", "application/json")
+        .header("connection", "close")
+        .body(Body::from(r#"{"error":"Request body timeout"}"#))
+        .unwrap()
+}
+
+/// A request body that fails once its deadline passes, flagging `expired` so
+/// [`body_deadline`] can tell the failure apart from a malformed body.
+struct DeadlineBody {
+    inner: Body,
+    sleep: Pin<Box<Sleep>>,
+    expired: Arc<AtomicBool>,
+}
+
+impl HttpBody for DeadlineBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+        if let Poll::Ready(frame) = Pin::new(&mut self.inner).poll_frame(cx) {
+            return Poll::Ready(frame);
+        }
+        if self.sleep.as_mut().poll(cx).is_ready() {
+            self.expired.store(true, Ordering::Relaxed);
+            return Poll::Ready(Some(Err(axum::Error::new("request body timeout"))));
+        }
+        Poll::Pending
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::tls_files;
+
+    #[test]
+    fn tls_needs_both_paths_or_neither() {
+        let path = |p: &str| Some(p.to_string());
+        assert_eq!(tls_files(None, None), Ok(None));
+        assert_eq!(
+            tls_files(path("cert.pem"), path("key.pem")),
+            Ok(Some(("cert.pem".to_string(), "key.pem".to_string())))
+        );
+        assert!(tls_files(path("cert.pem"), None).unwrap_err().contains("TLS_KEY_PATH"));
+        assert!(tls_files(None, path("key.pem")).unwrap_err().contains("TLS_CERT_PATH"));
+    }
+}
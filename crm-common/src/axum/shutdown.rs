@@ -0,0 +1,101 @@
+//! Graceful shutdown: on SIGTERM or Ctrl-C new requests get 503 while in-flight ones
+//! finish within `SHUTDOWN_GRACE_MS`.
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::Response,
+};
+use axum_server::Handle;
+use std::env;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::info;
+
+const DEFAULT_GRACE_MS: u64 = 10_000;
+
+#[derive(Default)]
+pub struct Drain {
+    draining: AtomicBool,
+    in_flight: AtomicUsize,
+}
+
+impl Drain {
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+}
+
+/// Decrements the in-flight count even when the request future is dropped.
+struct InFlight<'a>(&'a Drain);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Middleware: counts in-flight requests and answers 503 once draining has started.
+pub async fn reject_while_draining(
+    State(drain): State<Arc<Drain>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    if drain.draining.load(Ordering::SeqCst) {
+        return Response::builder()
+            .status(StatusCode::SERVICE_UNAVAILABLE)
+            .header("content-type", "application/json")
+            .header("connection", "close")
+            // Tells the gateway this replica is going away and another may answer
+            .header("x-draining", "true")
+            .body(Body::from(r#"{"error":"Server is shutting down"}"#))
+            .unwrap();
+    }
+    drain.in_flight.fetch_add(1, Ordering::SeqCst);
+    let _guard = InFlight(&drain);
+    next.run(req).await
+}
+
+/// Waits for a shutdown signal, flips the draining flag and starts a graceful shutdown
+/// of `handle` bounded by the grace period.
+pub async fn shutdown_on_signal(drain: Arc<Drain>, handle: Handle) {
+    wait_for_signal().await;
+    drain.draining.store(true, Ordering::SeqCst);
+    let grace = Duration::from_millis(
+        env::var("SHUTDOWN_GRACE_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_GRACE_MS),
+    );
+    info!(
+        "Shutdown requested: draining {} in-flight requests (grace {}ms)",
+        drain.in_flight(),
+        grace.as_millis()
+    );
+    handle.graceful_shutdown(Some(grace));
+}
+
+async fn wait_for_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to listen for Ctrl-C");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
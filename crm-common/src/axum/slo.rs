@@ -0,0 +1,51 @@
+//! `SLO_MS`: a latency budget per request (default 100 ms). Slower responses are stamped
+//! `x-slo-violation: true` and logged as a warning, so tail-latency events stand out during
+//! a benchmark run without post-processing `server-timing`.
+
+use axum::{
+    extract::{Request, State},
+    http::HeaderValue,
+    middleware::Next,
+    response::Response,
+};
+use std::env;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+const DEFAULT_SLO_MS: u64 = 100;
+
+/// `SLO_MS` as a duration; unset or unparsable falls back to the default.
+pub fn budget_from_env() -> Duration {
+    Duration::from_millis(
+        env::var("SLO_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_SLO_MS),
+    )
+}
+
+/// Middleware: times the request from handler entry until its response is built and
+/// flags it when that took longer than `budget`.
+pub async fn flag_violations(
+    State(budget): State<Duration>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let started = Instant::now();
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+    let mut resp = next.run(req).await;
+    let elapsed = started.elapsed();
+    if elapsed > budget {
+        warn!(
+            %method,
+            path,
+            elapsed_ms = elapsed.as_secs_f64() * 1000.0,
+            slo_ms = budget.as_millis() as u64,
+            "SLO exceeded"
+        );
+        resp.headers_mut()
+            .insert("x-slo-violation", HeaderValue::from_static("true"));
+    }
+    resp
+}
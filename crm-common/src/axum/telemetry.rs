@@ -0,0 +1,196 @@
+//! Logging and optional OpenTelemetry export of a span tree per request: a root `request`
+//! span with one child per `server-timing` phase. Logs always go to stdout in the format
+//! picked by `LOG_FORMAT`; spans are only exported when `OTEL_EXPORTER_OTLP_ENDPOINT` is set.
+
+use axum::extract::Request;
+use axum::middleware::Next;
+use axum::response::Response;
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::SpanExporter;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
+use std::env;
+use std::time::Instant;
+use tracing::field::Empty;
+use tracing::{info, info_span, warn, Instrument, Span};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, EnvFilter, Layer, Registry};
+
+/// Installs the global subscriber: the stdout log layer, plus the OTLP/HTTP exporter when
+/// `OTEL_EXPORTER_OTLP_ENDPOINT` is set (the exporter reads that and the other standard
+/// `OTEL_EXPORTER_OTLP_*` variables itself). Call this before anything logs, and call
+/// `shutdown` on the returned provider before exiting so buffered spans are flushed.
+pub fn init(service: &'static str) -> Option<SdkTracerProvider> {
+    let endpoint = env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+        .ok()
+        .filter(|v| !v.is_empty());
+    let provider = endpoint.as_ref().map(|_| {
+        let exporter = SpanExporter::builder()
+            .with_http()
+            .build()
+            .expect("Failed to build OTLP exporter");
+        SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(Resource::builder().with_service_name(service).build())
+            .build()
+    });
+    let otel = provider
+        .as_ref()
+        .map(|p| tracing_opentelemetry::layer().with_tracer(p.tracer(service)));
+    let settings = log_settings(
+        env::var("LOG_FORMAT").ok().as_deref(),
+        env::var("RUST_LOG").ok().as_deref(),
+    );
+    tracing_subscriber::registry()
+        .with(log_layer(settings.format, settings.filter))
+        .with(otel)
+        .init();
+    for problem in settings.problems {
+        warn!("{}", problem);
+    }
+    if let Some(endpoint) = endpoint {
+        info!("OTLP tracing enabled: {}", endpoint);
+    }
+    provider
+}
+
+/// Stdout log format (`LOG_FORMAT`): one JSON object per line by default, multi-line
+/// human-readable output with `pretty`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Json,
+    Pretty,
+}
+
+/// Parsed `LOG_FORMAT` / `RUST_LOG`, plus a message for each value that was ignored.
+pub struct LogSettings {
+    pub format: LogFormat,
+    pub filter: EnvFilter,
+    pub problems: Vec<String>,
+}
+
+/// `LOG_FORMAT` is `json` or `pretty` in any case; unset or empty means JSON. `RUST_LOG`
+/// sets the level (default `info`) and only filters logs, so exported spans are
+/// unaffected. Values that don't parse fall back to the default and are reported in
+/// `problems`, to be logged once the subscriber is installed.
+pub fn log_settings(log_format: Option<&str>, rust_log: Option<&str>) -> LogSettings {
+    let mut problems = Vec::new();
+    let format = match log_format.map(str::trim).filter(|v| !v.is_empty()) {
+        None => LogFormat::Json,
+        Some(v) if v.eq_ignore_ascii_case("json") => LogFormat::Json,
+        Some(v) if v.eq_ignore_ascii_case("pretty") => LogFormat::Pretty,
+        Some(v) => {
+            problems.push(format!("LOG_FORMAT={} is not json or pretty; using json", v));
+            LogFormat::Json
+        }
+    };
+    let filter = match rust_log.map(str::trim).filter(|v| !v.is_empty()) {
+        None => EnvFilter::new(DEFAULT_LOG_LEVEL),
+        Some(v) => EnvFilter::try_new(v).unwrap_or_else(|e| {
+            problems.push(format!("RUST_LOG={} is invalid ({}); using {}", v, e, DEFAULT_LOG_LEVEL));
+            EnvFilter::new(DEFAULT_LOG_LEVEL)
+        }),
+    };
+    LogSettings {
+        format,
+        filter,
+        problems,
+    }
+}
+
+const DEFAULT_LOG_LEVEL: &str = "info";
+
+fn log_layer(format: LogFormat, filter: EnvFilter) -> Box<dyn Layer<Registry> + Send + Sync> {
+    match format {
+        LogFormat::Pretty => fmt::layer().pretty().with_filter(filter).boxed(),
+        LogFormat::Json => fmt::layer().json().with_filter(filter).boxed(),
+    }
+}
+
+/// Root span of each request, carrying the caller's `x-request-id` (empty when absent).
+/// Phase spans opened by the handler become its children.
+pub async fn trace_request(req: Request, next: Next) -> Response {
+    let request_id = req
+        .headers()
+        .get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    let span = info_span!(
+        "request",
+        otel.name = %format!("{} {}", req.method(), req.uri().path()),
+        request_id,
+        http.status_code = Empty,
+    );
+    let resp = next.run(req).instrument(span.clone()).await;
+    span.record("http.status_code", resp.status().as_u16());
+    resp
+}
+
+/// A timed phase (`conn`, `query`, `ser`, ...): reports milliseconds for `server-timing`
+/// and spans from [`Phase::start`] until [`Phase::finish`], or until dropped on an early
+/// return.
+pub struct Phase {
+    started: Instant,
+    _span: Span,
+}
+
+impl Phase {
+    pub fn start(name: &'static str) -> Self {
+        Phase {
+            started: Instant::now(),
+            _span: info_span!("phase", otel.name = name),
+        }
+    }
+
+    /// Closes the span and returns the elapsed milliseconds.
+    pub fn finish(self) -> f64 {
+        self.started.elapsed().as_secs_f64() * 1000.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{log_settings, LogFormat};
+
+    #[test]
+    fn json_at_info_is_the_default() {
+        for format in [None, Some(""), Some("json"), Some("JSON")] {
+            let settings = log_settings(format, None);
+            assert_eq!(settings.format, LogFormat::Json);
+            assert_eq!(settings.filter.to_string(), "info");
+            assert!(settings.problems.is_empty());
+        }
+    }
+
+    #[test]
+    fn pretty_in_any_case() {
+        for format in ["pretty", "Pretty", " PRETTY "] {
+            assert_eq!(log_settings(Some(format), None).format, LogFormat::Pretty);
+        }
+    }
+
+    #[test]
+    fn unknown_format_falls_back_to_json() {
+        let settings = log_settings(Some("xml"), None);
+        assert_eq!(settings.format, LogFormat::Json);
+        assert_eq!(settings.problems.len(), 1);
+        assert!(settings.problems[0].starts_with("LOG_FORMAT=xml"));
+    }
+
+    #[test]
+    fn rust_log_sets_the_filter() {
+        let settings = log_settings(None, Some("debug,sqlx=warn"));
+        assert_eq!(settings.filter.to_string(), "sqlx=warn,debug");
+        assert!(settings.problems.is_empty());
+    }
+
+    #[test]
+    fn invalid_rust_log_falls_back_to_info() {
+        let settings = log_settings(Some("pretty"), Some("sqlx=loud"));
+        assert_eq!(settings.format, LogFormat::Pretty);
+        assert_eq!(settings.filter.to_string(), "info");
+        assert_eq!(settings.problems.len(), 1);
+        assert!(settings.problems[0].starts_with("RUST_LOG=sqlx=loud is invalid"));
+    }
+}
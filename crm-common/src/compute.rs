@@ -0,0 +1,40 @@
+//! `GET /compute` input checks shared by both gateways. Rejections are JSON bodies that
+//! echo the attempted `n`, so clients can log what was asked for, e.g.
+//! `{"error":"n exceeds maximum","n":99999999,"max":1000000}`.
+
+use serde_json::Value;
+
+/// `n` when the query has none.
+pub const DEFAULT_N: u64 = 1000;
+
+/// Largest accepted `n`; the loop is linear, so this bounds one request's CPU time.
+pub const MAX_N: u64 = 1_000_000;
+
+/// `?n=` as given: absent means [`DEFAULT_N`]; anything other than an integer in
+/// `0..=MAX_N` is an error body for a 400.
+pub fn parse_n(raw: Option<&str>) -> Result<u64, String> {
+    let Some(raw) = raw else {
+        return Ok(DEFAULT_N);
+    };
+    match raw.parse::<u64>() {
+        Ok(n) if n <= MAX_N => Ok(n),
+        Ok(_) => Err(error_body("n exceeds maximum", Some(raw), &[("max", MAX_N.into())])),
+        Err(_) => Err(error_body("n must be a non-negative integer", Some(raw), &[])),
+    }
+}
+
+/// `{"error":...,"n":...}` followed by `extra`. `n` stays a number when it parses as one
+/// and is echoed as the raw string otherwise.
+pub fn error_body(error: &str, raw_n: Option<&str>, extra: &[(&str, Value)]) -> String {
+    let n = match raw_n {
+        None => DEFAULT_N.into(),
+        Some(raw) => raw.parse::<u64>().map_or_else(|_| raw.into(), Value::from),
+    };
+    // Built by hand so the fields keep this order (serde_json's map would sort them)
+    let mut body = format!(r#"{{"error":{},"n":{}"#, Value::from(error), n);
+    for (key, value) in extra {
+        body.push_str(&format!(r#",{}:{}"#, Value::from(*key), value));
+    }
+    body.push('}');
+    body
+}
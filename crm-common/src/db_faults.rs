@@ -0,0 +1,106 @@
+//! DB failure injection for exercising error paths. `DB_FAIL_RATE` (0.0–1.0) is the
+//! fraction of connection acquires that fail with the usual `Database error` 500 without
+//! touching the database. Draws come from SplitMix64 seeded by `DB_FAIL_SEED`, so a given
+//! seed replays the same failure sequence (up to request interleaving). Unset or 0 disables it.
+
+use std::env;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A seeded failure sequence: each draw fails with probability `rate`.
+pub struct Injector {
+    rate: f64,
+    seed: u64,
+    state: AtomicU64,
+}
+
+static INJECTOR: OnceLock<Injector> = OnceLock::new();
+
+/// SplitMix64 increment.
+const GAMMA: u64 = 0x9E37_79B9_7F4A_7C15;
+
+impl Injector {
+    pub fn new(rate: f64, seed: u64) -> Self {
+        Injector {
+            rate: rate.clamp(0.0, 1.0),
+            seed,
+            state: AtomicU64::new(seed),
+        }
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Advances the sequence and reports whether this draw is a failure.
+    pub fn should_fail(&self) -> bool {
+        let mut z = self
+            .state
+            .fetch_add(GAMMA, Ordering::Relaxed)
+            .wrapping_add(GAMMA);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        // Top 53 bits as a uniform f64 in [0, 1)
+        ((z >> 11) as f64 / (1u64 << 53) as f64) < self.rate
+    }
+}
+
+/// Reads `DB_FAIL_RATE` / `DB_FAIL_SEED` once at startup and returns the injector when
+/// enabled, so the caller can log its settings. Without a seed the clock is used.
+pub fn init() -> Option<&'static Injector> {
+    let rate = env::var("DB_FAIL_RATE")
+        .ok()
+        .and_then(|v| v.parse::<f64>().ok())
+        .filter(|&r| r > 0.0)?;
+    let seed = env::var("DB_FAIL_SEED")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos() as u64)
+                .unwrap_or(0)
+        });
+    Some(INJECTOR.get_or_init(|| Injector::new(rate, seed)))
+}
+
+/// Whether the current DB operation should fail. Always false unless [`init`] enabled it.
+pub fn should_fail() -> bool {
+    INJECTOR.get().is_some_and(Injector::should_fail)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Injector;
+
+    fn draws(injector: &Injector, n: usize) -> Vec<bool> {
+        (0..n).map(|_| injector.should_fail()).collect()
+    }
+
+    #[test]
+    fn same_seed_replays_the_same_failures() {
+        let first = draws(&Injector::new(0.3, 42), 1000);
+        let second = draws(&Injector::new(0.3, 42), 1000);
+        assert_eq!(first, second);
+        assert_ne!(first, draws(&Injector::new(0.3, 43), 1000));
+    }
+
+    #[test]
+    fn failure_share_follows_the_rate() {
+        let failures = draws(&Injector::new(0.3, 7), 10_000).iter().filter(|&&f| f).count();
+        assert!((2700..3300).contains(&failures), "{} failures", failures);
+    }
+
+    #[test]
+    fn rate_bounds() {
+        assert!(draws(&Injector::new(1.0, 1), 100).iter().all(|&f| f));
+        assert!(draws(&Injector::new(5.0, 1), 100).iter().all(|&f| f));
+        assert!(!draws(&Injector::new(0.0, 1), 100).iter().any(|&f| f));
+    }
+}
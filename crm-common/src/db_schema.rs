@@ -0,0 +1,34 @@
+//! `DB_SCHEMA` / `db_schema`: runs the services against tables in a named schema, so
+//! several copies of the CRM (e.g. the Spin and Axum stacks) can share one database.
+//! Queries keep their unqualified table names; each connection's `search_path` is
+//! pointed at the schema instead, which resolves them the same way `myschema.customers`
+//! would.
+
+/// PostgreSQL's identifier length limit (`NAMEDATALEN - 1`).
+const MAX_LEN: usize = 63;
+
+/// The `SET search_path` statement for `raw`, or `None` when it is empty (tables stay in
+/// the default search path). The name must match `[A-Za-z_][A-Za-z0-9_]*`, so nothing
+/// beyond a plain identifier can reach the statement; it is folded to lower case and
+/// quoted, matching how an unquoted `myschema.customers` would be read.
+pub fn search_path(raw: &str) -> Result<Option<String>, String> {
+    let name = raw.trim();
+    if name.is_empty() {
+        return Ok(None);
+    }
+    let mut chars = name.chars();
+    let valid_start = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+    let valid_rest = chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid_start || !valid_rest || name.len() > MAX_LEN {
+        return Err(format!(
+            "\"{}\" is not a valid schema name (letters, digits and _, at most {} characters)",
+            name, MAX_LEN
+        ));
+    }
+    Ok(Some(format!(
+        "SET search_path TO \"{}\"",
+        name.to_ascii_lowercase()
+    )))
+}
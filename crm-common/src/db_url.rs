@@ -0,0 +1,26 @@
+//! Connection-string sanity check. Both runtimes only speak PostgreSQL, and a URL for
+//! another database otherwise fails deep inside the driver with an error that does not
+//! mention the scheme at all.
+
+/// Schemes accepted for `DATABASE_URL` / `db_url`.
+pub const SUPPORTED_SCHEMES: &[&str] = &["postgres", "postgresql"];
+
+/// `Ok` for a `postgres://` or `postgresql://` URL (scheme matched case-insensitively),
+/// otherwise a message naming what was found, e.g.
+/// `unsupported scheme "mysql" (expected postgres:// or postgresql://)`.
+pub fn check_scheme(url: &str) -> Result<(), String> {
+    let Some((scheme, _)) = url.trim().split_once("://") else {
+        return Err("has no scheme (expected postgres:// or postgresql://)".to_string());
+    };
+    if SUPPORTED_SCHEMES
+        .iter()
+        .any(|s| s.eq_ignore_ascii_case(scheme))
+    {
+        Ok(())
+    } else {
+        Err(format!(
+            "unsupported scheme \"{}\" (expected postgres:// or postgresql://)",
+            scheme
+        ))
+    }
+}
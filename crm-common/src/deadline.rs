@@ -0,0 +1,20 @@
+//! `X-Request-Deadline`: an absolute cutoff in unix milliseconds. Each hop forwards the
+//! header unchanged and derives its own remaining budget from it, so time spent in
+//! earlier hops is accounted for without rewriting the value.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const HEADER: &str = "x-request-deadline";
+
+pub const EXCEEDED_BODY: &str = r#"{"error":"Deadline exceeded"}"#;
+
+/// Time left until the deadline in `raw`, or `Duration::ZERO` once it has passed.
+/// `None` for a missing or non-numeric header, meaning no deadline applies.
+pub fn remaining(raw: Option<&str>) -> Option<Duration> {
+    let deadline_ms: u64 = raw?.trim().parse().ok()?;
+    let now_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    Some(Duration::from_millis(deadline_ms.saturating_sub(now_ms)))
+}
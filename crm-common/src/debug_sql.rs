@@ -0,0 +1,10 @@
+//! `X-Debug-SQL` (`DEBUG_SQL` / `debug_sql`): the statement behind a read response and how
+//! many parameters were bound to it. Bound values are never included.
+
+pub const HEADER: &str = "x-debug-sql";
+
+/// `<sql>; params=<n>`, with the statement's whitespace collapsed so it fits in one header line.
+pub fn describe(sql: &str, params: usize) -> String {
+    let sql: Vec<&str> = sql.split_whitespace().collect();
+    format!("{}; params={}", sql.join(" "), params)
+}
@@ -0,0 +1,12 @@
+//! `?envelope=true` wrappers. They splice already-serialized JSON, so enveloping costs no
+//! second serialization pass and the bare default stays byte-for-byte unchanged.
+
+/// `{"data":[...],"meta":{"count":N}}`, where `count` is the size of the returned page.
+pub fn wrap_list(body: &str, count: usize) -> String {
+    format!(r#"{{"data":{},"meta":{{"count":{}}}}}"#, body, count)
+}
+
+/// `{"data":{...}}` for single resources.
+pub fn wrap_one(body: &str) -> String {
+    format!(r#"{{"data":{}}}"#, body)
+}
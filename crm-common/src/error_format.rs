@@ -0,0 +1,19 @@
+//! Optional plain-text rendering of `{"error":"..."}` responses for scripting clients
+//! that don't parse JSON. JSON stays the default. The runtimes apply it through
+//! `axum::error_format` and `spin::error_format`.
+
+use serde_json::Value;
+
+/// True for `?error_format=text`, or an `Accept` header asking for `text/plain` but not JSON.
+pub fn wants_text(query: &str, accept: Option<&str>) -> bool {
+    if query.split('&').any(|pair| pair == "error_format=text") {
+        return true;
+    }
+    accept.is_some_and(|accept| accept.contains("text/plain") && !accept.contains("application/json"))
+}
+
+/// The `error` string of a JSON error body, or `None` when the body has none.
+pub fn error_message(body: &[u8]) -> Option<String> {
+    let value: Value = serde_json::from_slice(body).ok()?;
+    value.get("error")?.as_str().map(str::to_string)
+}
@@ -0,0 +1,18 @@
+//! `X-Hop-Count`: how many gateway forwards a request has been through. Each gateway adds
+//! one before forwarding and services pass the value on unchanged in their own upstream
+//! calls, so a URL that points back at the gateway ends in 508 instead of looping.
+
+pub const HEADER: &str = "x-hop-count";
+
+/// `MAX_HOPS` / `max_hops` default.
+pub const DEFAULT_MAX: u32 = 3;
+
+pub const LOOP_DETECTED_BODY: &str = r#"{"error":"Loop detected"}"#;
+
+/// The count to forward for a request that arrived with `raw`; a missing or non-numeric
+/// header counts as zero hops so far.
+pub fn next(raw: Option<&str>) -> u32 {
+    raw.and_then(|v| v.trim().parse::<u32>().ok())
+        .unwrap_or(0)
+        .saturating_add(1)
+}
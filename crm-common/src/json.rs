@@ -0,0 +1,29 @@
+//! JSON entry points for the hot paths: request bodies in, response bodies out. The
+//! default build is plain `serde_json`; the `fast-json` feature routes both through
+//! `simd-json`, whose compact output is byte-identical for the payloads served here.
+//! Errors stay `serde_json::Error` so call sites are the same under either backend.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+#[cfg(not(feature = "fast-json"))]
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> serde_json::Result<String> {
+    serde_json::to_string(value)
+}
+
+#[cfg(feature = "fast-json")]
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> serde_json::Result<String> {
+    simd_json::serde::to_string(value).map_err(serde::ser::Error::custom)
+}
+
+#[cfg(not(feature = "fast-json"))]
+pub fn from_slice<T: DeserializeOwned>(bytes: &[u8]) -> serde_json::Result<T> {
+    serde_json::from_slice(bytes)
+}
+
+/// simd-json parses in place, so it works on a copy of `bytes`.
+#[cfg(feature = "fast-json")]
+pub fn from_slice<T: DeserializeOwned>(bytes: &[u8]) -> serde_json::Result<T> {
+    let mut buf = bytes.to_vec();
+    simd_json::serde::from_slice(&mut buf).map_err(serde::de::Error::custom)
+}
@@ -0,0 +1,29 @@
+//! Framework-independent helpers shared by the Spin and Axum services, so the two
+//! implementations differ only in their HTTP glue.
+
+pub mod compute;
+pub mod db_faults;
+pub mod db_schema;
+pub mod db_url;
+pub mod deadline;
+pub mod debug_sql;
+pub mod envelope;
+pub mod error_format;
+pub mod error;
+pub mod hops;
+pub mod json;
+pub mod paging;
+pub mod path;
+pub mod prefer;
+pub mod routes;
+pub mod schema;
+pub mod secrets;
+pub mod timing;
+
+#[cfg(feature = "axum")]
+pub mod axum;
+#[cfg(feature = "spin")]
+pub mod spin;
+
+pub use error::ApiError;
+pub use timing::ServerTiming;
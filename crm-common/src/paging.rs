@@ -0,0 +1,245 @@
+use serde::Deserialize;
+use std::num::IntErrorKind;
+
+/// Optional `?limit=&offset=` paging for list endpoints. Omitting both returns every row.
+#[derive(Deserialize)]
+pub struct PageParams {
+    pub limit: Option<i64>,
+    /// Kept raw so out-of-range values can be reported as such.
+    pub offset: Option<String>,
+}
+
+/// How a `limit` above `max_page_size` is handled: clamped by default, 400 when `strict`.
+#[derive(Clone, Copy)]
+pub struct PageLimits {
+    pub max_page_size: i64,
+    pub strict: bool,
+}
+
+impl PageParams {
+    /// `Range: items=` paging, held to the same limits as `?limit=&offset=`.
+    pub fn from_range((limit, offset): (i64, i64)) -> Self {
+        PageParams {
+            limit: Some(limit),
+            offset: Some(offset.to_string()),
+        }
+    }
+
+    /// Resolves to `(limit, offset)`, or `None` when the request is unpaginated.
+    pub fn resolve(&self, limits: PageLimits) -> Result<Option<(i64, i64)>, String> {
+        if self.limit.is_none() && self.offset.is_none() {
+            return Ok(None);
+        }
+        let offset = match self.offset.as_deref() {
+            Some(raw) => parse_offset(raw)?,
+            None => 0,
+        };
+        let limit = match self.limit {
+            Some(l) if l < 1 => return Err("limit must be positive".to_string()),
+            Some(l) if l > limits.max_page_size => {
+                if limits.strict {
+                    return Err(format!("limit exceeds maximum of {}", limits.max_page_size));
+                }
+                limits.max_page_size
+            }
+            Some(l) => l,
+            None => limits.max_page_size,
+        };
+        Ok(Some((limit, offset)))
+    }
+}
+
+/// Parses `?offset=`. Values beyond `i64` get their own 400 instead of a generic parse
+/// error; an offset past the end of the table is valid and just yields an empty page.
+pub fn parse_offset(raw: &str) -> Result<i64, &'static str> {
+    match raw.parse::<i64>() {
+        Ok(v) if v < 0 => Err("offset must be non-negative"),
+        Ok(v) => Ok(v),
+        Err(e) if matches!(e.kind(), IntErrorKind::PosOverflow | IntErrorKind::NegOverflow) => {
+            Err("offset out of range")
+        }
+        Err(_) => Err("offset must be an integer"),
+    }
+}
+
+/// RFC 8288 `Link` header for `?limit=&offset=` paging. `first` and `last` are always
+/// present, `prev` is omitted on the first page and `next` on the last. Targets keep the
+/// request's other query parameters and are relative to the current host.
+pub fn link_header(path: &str, query: &str, limit: i64, offset: i64, total: i64) -> String {
+    let kept: Vec<&str> = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .filter(|pair| {
+            let key = pair.split('=').next().unwrap_or("");
+            key != "limit" && key != "offset"
+        })
+        .collect();
+    let target = |offset: i64| {
+        let mut params = kept.clone();
+        let page = format!("limit={}&offset={}", limit, offset);
+        params.push(&page);
+        format!("{}?{}", path, params.join("&"))
+    };
+
+    let last = if total > 0 { (total - 1) / limit * limit } else { 0 };
+    let mut links = vec![format!(r#"<{}>; rel="first""#, target(0))];
+    if offset > 0 {
+        let prev = offset.saturating_sub(limit).clamp(0, last);
+        links.push(format!(r#"<{}>; rel="prev""#, target(prev)));
+    }
+    if offset.saturating_add(limit) < total {
+        links.push(format!(r#"<{}>; rel="next""#, target(offset + limit)));
+    }
+    links.push(format!(r#"<{}>; rel="last""#, target(last)));
+    links.join(", ")
+}
+
+/// Drops the probe row fetched past `cap` (queries ask for `cap + 1`). Returns whether
+/// the cap was hit, i.e. the table holds more rows than were returned.
+pub fn truncate_to_cap<T>(rows: &mut Vec<T>, cap: Option<i64>) -> bool {
+    match cap {
+        Some(cap) if rows.len() as i64 > cap => {
+            rows.truncate(cap as usize);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Parses a `Range: items=<first>-<last>` header (inclusive bounds, as sent by
+/// Collection+JSON-style clients) into `(limit, offset)`.
+pub fn parse_items_range(raw: &str) -> Result<(i64, i64), &'static str> {
+    const INVALID: &str = "Range must be items=<first>-<last>";
+    let (first, last) = raw
+        .trim()
+        .strip_prefix("items=")
+        .and_then(|spec| spec.split_once('-'))
+        .ok_or(INVALID)?;
+    let first: i64 = first.trim().parse().map_err(|_| INVALID)?;
+    let last: i64 = last.trim().parse().map_err(|_| INVALID)?;
+    if first < 0 || last < first {
+        return Err(INVALID);
+    }
+    let limit = (last - first).checked_add(1).ok_or(INVALID)?;
+    Ok((limit, first))
+}
+
+/// `Content-Range` of a 206 answer: `items <first>-<last>/<total>`, where `limit` is the
+/// page size actually applied. `None` when `offset` is at or past `total`, which is
+/// answered with 416 and `items */<total>` instead.
+pub fn content_range(limit: i64, offset: i64, total: i64) -> Option<String> {
+    if offset >= total {
+        return None;
+    }
+    let last = offset + limit.min(total - offset) - 1;
+    Some(format!("items {}-{}/{}", offset, last, total))
+}
+
+/// `Range: items=<first>-<last>` from the request, if any; it takes precedence over
+/// `?limit=&offset=`.
+#[cfg(feature = "axum")]
+pub fn items_range(
+    headers: &axum::http::HeaderMap,
+) -> Result<Option<(i64, i64)>, &'static str> {
+    match headers.get(axum::http::header::RANGE) {
+        None => Ok(None),
+        Some(v) => v
+            .to_str()
+            .map_err(|_| "Range must be items=<first>-<last>")
+            .and_then(parse_items_range)
+            .map(Some),
+    }
+}
+
+/// Marks a list answered for a `Range` request as 206 with its `Content-Range`.
+#[cfg(feature = "axum")]
+pub fn with_content_range(
+    mut resp: axum::response::Response,
+    content_range: Option<String>,
+) -> axum::response::Response {
+    use axum::http::{header::CONTENT_RANGE, HeaderValue, StatusCode};
+    if let Some(value) = content_range.and_then(|v| HeaderValue::from_str(&v).ok()) {
+        *resp.status_mut() = StatusCode::PARTIAL_CONTENT;
+        resp.headers_mut().insert(CONTENT_RANGE, value);
+    }
+    resp
+}
+
+/// 416 for a `Range` that starts past the last item, reporting the total as `items */N`.
+#[cfg(feature = "axum")]
+pub fn range_not_satisfiable(total: i64) -> axum::response::Response {
+    use axum::http::{header, HeaderValue, StatusCode};
+    use axum::response::IntoResponse;
+    (
+        StatusCode::RANGE_NOT_SATISFIABLE,
+        [
+            (header::CONTENT_TYPE, HeaderValue::from_static("application/json")),
+            (
+                header::CONTENT_RANGE,
+                HeaderValue::from_str(&format!("items */{}", total)).unwrap(),
+            ),
+        ],
+        r#"{"error":"Range not satisfiable"}"#,
+    )
+        .into_response()
+}
+
+/// `EMPTY_LIST_204=true`: turns an empty bare-array list into 204 with no body. Headers such
+/// as `server-timing` and `link` stay; `?envelope=true` lists always answer 200.
+#[cfg(feature = "axum")]
+pub fn no_content_if_empty(
+    mut resp: axum::response::Response,
+    empty: bool,
+) -> axum::response::Response {
+    if empty {
+        *resp.status_mut() = axum::http::StatusCode::NO_CONTENT;
+        *resp.body_mut() = axum::body::Body::empty();
+        resp.headers_mut().remove(axum::http::header::CONTENT_TYPE);
+    }
+    resp
+}
+
+/// Flags a list cut off by `MAX_ROWS` with `x-truncated: true`.
+#[cfg(feature = "axum")]
+pub fn mark_truncated(
+    mut resp: axum::response::Response,
+    truncated: bool,
+) -> axum::response::Response {
+    if truncated {
+        resp.headers_mut()
+            .insert("x-truncated", axum::http::HeaderValue::from_static("true"));
+    }
+    resp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: PageLimits = PageLimits { max_page_size: 100, strict: false };
+
+    fn page(limit: Option<i64>, offset: Option<&str>) -> PageParams {
+        PageParams { limit, offset: offset.map(str::to_string) }
+    }
+
+    #[test]
+    fn resolve_defaults_clamps_and_rejects() {
+        assert_eq!(page(None, None).resolve(LIMITS), Ok(None));
+        assert_eq!(page(None, Some("5")).resolve(LIMITS), Ok(Some((100, 5))));
+        assert_eq!(page(Some(500), None).resolve(LIMITS), Ok(Some((100, 0))));
+        let strict = PageLimits { strict: true, ..LIMITS };
+        assert!(page(Some(500), None).resolve(strict).is_err());
+        assert!(page(Some(0), None).resolve(LIMITS).is_err());
+        assert!(page(Some(1), Some("-1")).resolve(LIMITS).is_err());
+    }
+
+    #[test]
+    fn truncate_to_cap_drops_only_the_probe_row() {
+        let mut rows = vec![1, 2, 3];
+        assert!(truncate_to_cap(&mut rows, Some(2)));
+        assert_eq!(rows, [1, 2]);
+        assert!(!truncate_to_cap(&mut rows, Some(2)));
+        assert!(!truncate_to_cap(&mut rows, None));
+        assert_eq!(rows, [1, 2]);
+    }
+}
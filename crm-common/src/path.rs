@@ -0,0 +1,124 @@
+/// Returns the id segment of `/{collection}[/{id}]`. One trailing slash is tolerated,
+/// but an empty or whitespace-only id (`/customers//`, `/orders/%20`) is rejected.
+pub fn resource_id(uri: &str) -> Result<Option<&str>, &'static str> {
+    let path = uri.split('?').next().unwrap_or(uri);
+    let path = path.strip_suffix('/').unwrap_or(path);
+    match path.split('/').nth(2) {
+        None => Ok(None),
+        Some(id) if is_blank_segment(id) => Err("invalid resource id"),
+        Some(id) => Ok(Some(id)),
+    }
+}
+
+/// True for segments that are empty or only (possibly percent-encoded) whitespace.
+pub fn is_blank_segment(segment: &str) -> bool {
+    let mut rest = segment.trim_start();
+    while let Some(r) = rest.strip_prefix("%20").or_else(|| rest.strip_prefix("%09")) {
+        rest = r.trim_start();
+    }
+    rest.trim_end().is_empty()
+}
+
+/// Value of `key` in a raw query string; a bare `key` yields `Some("")`.
+pub fn query_param<'a>(query: &'a str, key: &str) -> Option<&'a str> {
+    query.split('&').find_map(|pair| {
+        let mut kv = pair.splitn(2, '=');
+        (kv.next()? == key).then(|| kv.next().unwrap_or(""))
+    })
+}
+
+/// Accepted spellings of a boolean flag, matched case-insensitively.
+const TRUE_VALUES: &[&str] = &["true", "1", "yes"];
+const FALSE_VALUES: &[&str] = &["false", "0", "no"];
+/// What a rejected flag value is told, after the parameter name (`envelope must be ...`).
+pub const BOOL_EXPECTED: &str = "must be true/false/1/0/yes/no";
+
+/// Parses a flag value: `true`/`false`, `1`/`0` or `yes`/`no` in any case.
+pub fn parse_bool(raw: &str) -> Option<bool> {
+    if TRUE_VALUES.iter().any(|v| raw.eq_ignore_ascii_case(v)) {
+        Some(true)
+    } else if FALSE_VALUES.iter().any(|v| raw.eq_ignore_ascii_case(v)) {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// `?key=` as a boolean flag via [`parse_bool`]. Absent means `false`; any other value,
+/// including a bare `key`, is an error message suitable for a 400.
+pub fn bool_param(query: &str, key: &str) -> Result<bool, String> {
+    match query_param(query, key) {
+        None => Ok(false),
+        Some(raw) => parse_bool(raw)
+            .ok_or_else(|| format!("{} {}", key, BOOL_EXPECTED)),
+    }
+}
+
+/// serde `deserialize_with` for `Option<bool>` query fields, so typed query structs accept
+/// the same spellings as [`bool_param`]. Pair it with `#[serde(default)]`.
+pub fn deserialize_bool<'de, D>(deserializer: D) -> Result<Option<bool>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::Deserialize;
+    let raw = Option::<String>::deserialize(deserializer)?;
+    raw.map(|v| {
+        parse_bool(&v).ok_or_else(|| serde::de::Error::custom(BOOL_EXPECTED))
+    })
+    .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    struct Flags {
+        #[serde(default, deserialize_with = "deserialize_bool")]
+        envelope: Option<bool>,
+    }
+
+    fn flags(json: &str) -> Result<Option<bool>, String> {
+        serde_json::from_str::<Flags>(json)
+            .map(|f| f.envelope)
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn parse_bool_accepts_each_spelling_in_any_case() {
+        for raw in ["true", "TRUE", "1", "yes", "Yes"] {
+            assert_eq!(parse_bool(raw), Some(true), "{raw}");
+        }
+        for raw in ["false", "False", "0", "no", "NO"] {
+            assert_eq!(parse_bool(raw), Some(false), "{raw}");
+        }
+    }
+
+    #[test]
+    fn parse_bool_rejects_other_values() {
+        for raw in ["", "maybe", "on", "off", "2", "y", " true", "true "] {
+            assert_eq!(parse_bool(raw), None, "{raw:?}");
+        }
+    }
+
+    #[test]
+    fn bool_param_defaults_to_false_and_names_the_key() {
+        assert_eq!(bool_param("", "envelope"), Ok(false));
+        assert_eq!(bool_param("envelope=yes&x=1", "envelope"), Ok(true));
+        assert_eq!(
+            bool_param("envelope=maybe", "envelope"),
+            Err("envelope must be true/false/1/0/yes/no".to_string())
+        );
+        assert!(bool_param("envelope", "envelope").is_err());
+    }
+
+    #[test]
+    fn deserialize_bool_matches_parse_bool() {
+        assert_eq!(flags("{}"), Ok(None));
+        assert_eq!(flags(r#"{"envelope":"1"}"#), Ok(Some(true)));
+        assert_eq!(flags(r#"{"envelope":"No"}"#), Ok(Some(false)));
+        let err = flags(r#"{"envelope":"maybe"}"#).unwrap_err();
+        assert!(err.starts_with(BOOL_EXPECTED), "{err}");
+    }
+}
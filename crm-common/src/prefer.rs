@@ -0,0 +1,18 @@
+//! `Prefer: return=representation` (RFC 7240) on `DELETE`: the deleted row comes back with
+//! 200 instead of an empty 204, and the response confirms it with `Preference-Applied`.
+
+pub const HEADER: &str = "prefer";
+pub const APPLIED_HEADER: &str = "preference-applied";
+pub const RETURN_REPRESENTATION: &str = "return=representation";
+
+/// Whether any comma-separated preference in `raw` is `return=representation`. Parameters
+/// after `;` are ignored and matching is case-insensitive.
+pub fn wants_representation(raw: Option<&str>) -> bool {
+    raw.is_some_and(|v| {
+        v.split(',').any(|pref| {
+            let token = pref.split(';').next().unwrap_or("");
+            let token: String = token.chars().filter(|c| !c.is_whitespace()).collect();
+            token.eq_ignore_ascii_case(RETURN_REPRESENTATION)
+        })
+    })
+}
@@ -0,0 +1,67 @@
+//! `GET /routez`: the routes a service serves, so clients and tests can enumerate what a
+//! deployment supports without reading the docs. Each service keeps its table next to its
+//! router (Axum) or `route` function (Spin); the two must be updated together.
+//!
+//! Bodies are assembled by hand so `path` comes before `methods` (`serde_json::Value`
+//! objects would sort their keys).
+
+use serde_json::Value;
+
+/// One path pattern and the methods it answers. `{id}` segments are written the way the
+/// Axum router declares them; `GET` routes also answer `HEAD`.
+pub struct Route {
+    pub path: &'static str,
+    pub methods: &'static [&'static str],
+}
+
+/// `[{"path":"/customers","methods":["GET","POST"]},...]` in table order.
+pub fn routez_body<'a>(routes: impl IntoIterator<Item = &'a Route>) -> String {
+    let items: Vec<String> = routes
+        .into_iter()
+        .map(|r| {
+            format!(
+                r#"{{"path":{},"methods":{}}}"#,
+                Value::from(r.path),
+                Value::from(r.methods.to_vec())
+            )
+        })
+        .collect();
+    format!("[{}]", items.join(","))
+}
+
+/// The gateway's `/routez`: its own routes, the prefixes it proxies as
+/// `{"prefix":...,"upstream":...}`, and each upstream's list embedded as received
+/// (`null` when it could not be fetched).
+pub fn gateway_body(
+    own: &str,
+    prefixes: &[(&str, &str)],
+    customer: Option<&str>,
+    order: Option<&str>,
+) -> String {
+    let prefixes: Vec<String> = prefixes
+        .iter()
+        .map(|(prefix, upstream)| {
+            format!(
+                r#"{{"prefix":{},"upstream":{}}}"#,
+                Value::from(*prefix),
+                Value::from(*upstream)
+            )
+        })
+        .collect();
+    format!(
+        r#"{{"gateway":{},"prefixes":[{}],"customer-service":{},"order-service":{}}}"#,
+        own,
+        prefixes.join(","),
+        customer.unwrap_or("null"),
+        order.unwrap_or("null")
+    )
+}
+
+/// An upstream `/routez` body if it is a JSON array, so a proxy error page is never
+/// spliced into the gateway's response.
+pub fn upstream_list(body: &[u8]) -> Option<String> {
+    match serde_json::from_slice::<Value>(body) {
+        Ok(Value::Array(_)) => String::from_utf8(body.to_vec()).ok(),
+        _ => None,
+    }
+}
@@ -0,0 +1,22 @@
+//! `GET /schemaz`: compares the schema version recorded in the database with the one this
+//! build expects, so "deployed but the schema change wasn't applied" shows up directly.
+
+/// Version written to `schema_version` by `k8s/postgres.yaml`. Bump both together
+/// whenever the tables change.
+pub const EXPECTED_VERSION: i64 = 2;
+
+pub const VERSION_QUERY: &str = "SELECT MAX(version) FROM schema_version";
+
+/// SQLSTATE `undefined_table`: the version table itself is missing.
+pub const UNDEFINED_TABLE: &str = "42P01";
+
+/// `{"current":N,"expected":N,"mismatch":bool}`. `current` is `null` when no version is
+/// recorded, which always counts as a mismatch.
+pub fn schemaz_body(current: Option<i64>) -> String {
+    serde_json::json!({
+        "current": current,
+        "expected": EXPECTED_VERSION,
+        "mismatch": current != Some(EXPECTED_VERSION),
+    })
+    .to_string()
+}
@@ -0,0 +1,51 @@
+//! Request body decoding: inflates `Content-Encoding: gzip` bodies, bounded by
+//! `max_body_bytes` so a small compressed payload can't expand without limit.
+
+use flate2::read::GzDecoder;
+use spin_sdk::http::Request;
+use spin_sdk::variables;
+use std::borrow::Cow;
+use std::io::Read;
+
+const DEFAULT_MAX_BODY_BYTES: u64 = 2 * 1024 * 1024;
+
+/// Returns the request body ready for JSON parsing, or the status and error body to reply with.
+pub fn decoded_body(req: &Request) -> Result<Cow<'_, [u8]>, (u16, &'static str)> {
+    let raw = req.body();
+    let encoding = req
+        .header("content-encoding")
+        .and_then(|v| v.as_str())
+        .map(str::trim)
+        .unwrap_or_default();
+    if raw.is_empty() && encoding.is_empty() {
+        return Ok(Cow::Borrowed(raw));
+    }
+
+    let limit = max_body_bytes();
+    if encoding.is_empty() || encoding.eq_ignore_ascii_case("identity") {
+        if raw.len() as u64 > limit {
+            return Err((413, r#"{"error":"Request body too large"}"#));
+        }
+        return Ok(Cow::Borrowed(raw));
+    }
+    if !encoding.eq_ignore_ascii_case("gzip") {
+        return Err((415, r#"{"error":"Unsupported Content-Encoding"}"#));
+    }
+
+    let mut inflated = Vec::new();
+    GzDecoder::new(raw)
+        .take(limit + 1)
+        .read_to_end(&mut inflated)
+        .map_err(|_| (400, r#"{"error":"Invalid gzip body"}"#))?;
+    if inflated.len() as u64 > limit {
+        return Err((413, r#"{"error":"Request body too large"}"#));
+    }
+    Ok(Cow::Owned(inflated))
+}
+
+fn max_body_bytes() -> u64 {
+    variables::get("max_body_bytes")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MAX_BODY_BYTES)
+}
@@ -0,0 +1,92 @@
+//! Per-request database handle. A statement that fails at the connection level is retried
+//! once on a freshly opened connection; errors carrying a SQLSTATE come from the statement
+//! itself and are returned unchanged, so constraint violations are never replayed. Writes
+//! are only retried when the connection failed outright, since a connection that dropped
+//! mid-statement may already have committed it.
+//!
+//! With `db_schema` set, every connection (including a reopened one) first runs the
+//! `SET search_path` from [`crate::db_schema`].
+
+use super::db_health;
+use spin_sdk::pg4::{Connection, ParameterValue, PgError, QueryError, RowSet};
+use std::cell::{Cell, RefCell};
+
+pub struct Db {
+    url: String,
+    search_path: Option<String>,
+    conn: RefCell<Connection>,
+    /// Set between `BEGIN` and `COMMIT`/`ROLLBACK`: a reopened connection would not be part
+    /// of the transaction, so nothing is retried while one is open.
+    in_tx: Cell<bool>,
+}
+
+impl Db {
+    /// Opens the request's connection, recording the outcome for `x-db-healthy`.
+    pub fn open(url: &str, search_path: Option<String>) -> Result<Self, PgError> {
+        let conn = connect(url, search_path.as_deref());
+        db_health::record(conn.is_ok());
+        let conn = conn?;
+        Ok(Db {
+            url: url.to_string(),
+            search_path,
+            conn: RefCell::new(conn),
+            in_tx: Cell::new(false),
+        })
+    }
+
+    pub fn query(&self, sql: &str, params: &[ParameterValue]) -> Result<RowSet, PgError> {
+        let read_only = sql
+            .trim_start()
+            .get(..6)
+            .is_some_and(|kw| kw.eq_ignore_ascii_case("SELECT"));
+        self.with_retry(read_only, |conn| conn.query(sql, params))
+    }
+
+    pub fn execute(&self, sql: &str, params: &[ParameterValue]) -> Result<u64, PgError> {
+        let result = self.with_retry(false, |conn| conn.execute(sql, params));
+        match sql.trim() {
+            "BEGIN" => self.in_tx.set(result.is_ok()),
+            "COMMIT" | "ROLLBACK" => self.in_tx.set(false),
+            _ => {}
+        }
+        result
+    }
+
+    fn with_retry<T>(
+        &self,
+        read_only: bool,
+        op: impl Fn(&Connection) -> Result<T, PgError>,
+    ) -> Result<T, PgError> {
+        let first = op(&self.conn.borrow());
+        match first {
+            Err(e) if !self.in_tx.get() && is_connection_error(&e, read_only) => {
+                // Keep the original error if the database is still unreachable
+                let Ok(fresh) = connect(&self.url, self.search_path.as_deref()) else {
+                    return Err(e);
+                };
+                *self.conn.borrow_mut() = fresh;
+                op(&self.conn.borrow())
+            }
+            other => other,
+        }
+    }
+}
+
+fn connect(url: &str, search_path: Option<&str>) -> Result<Connection, PgError> {
+    let conn = Connection::open(url)?;
+    if let Some(sql) = search_path {
+        conn.execute(sql, &[])?;
+    }
+    Ok(conn)
+}
+
+/// Failures without a SQLSTATE. `ConnectionFailed` means the statement never reached the
+/// server; an unstructured `Text` error (e.g. the connection closing mid-query) only
+/// counts for reads.
+fn is_connection_error(err: &PgError, read_only: bool) -> bool {
+    match err {
+        PgError::ConnectionFailed(_) => true,
+        PgError::QueryFailed(QueryError::Text(_)) => read_only,
+        _ => false,
+    }
+}
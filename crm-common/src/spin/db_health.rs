@@ -0,0 +1,29 @@
+//! `x-db-healthy`, stamped once in each component's `handle_request` from what the request's
+//! database work did, the counterpart of [`crate::axum::db_health`]: `true` once
+//! [`Db::open`](super::db::Db::open) succeeded and nothing failed, `false` after a failed
+//! open, a `Database error` 500 or a `PgError` propagated out of the handler, and absent when
+//! the database was never opened.
+
+use spin_sdk::http::Response;
+use std::cell::Cell;
+
+thread_local! {
+    static OUTCOME: Cell<Option<bool>> = const { Cell::new(None) };
+}
+
+/// Records a database outcome for the current request; once `false`, it stays `false`.
+pub fn record(healthy: bool) {
+    OUTCOME.with(|outcome| {
+        if outcome.get() != Some(false) {
+            outcome.set(Some(healthy));
+        }
+    });
+}
+
+/// Sets `x-db-healthy` from the recorded outcome and clears it, since an instance may be
+/// reused for the next request.
+pub fn stamp(resp: &mut Response) {
+    if let Some(healthy) = OUTCOME.with(Cell::take) {
+        resp.set_header("x-db-healthy", if healthy { "true" } else { "false" });
+    }
+}
@@ -0,0 +1,24 @@
+//! Spin side of [`crate::error_format`].
+
+use crate::error_format::error_message;
+use spin_sdk::http::{Request, Response};
+
+/// True for `?error_format=text`, or an `Accept` header asking for `text/plain` but not JSON.
+pub fn wants_text(req: &Request) -> bool {
+    let accept = req.header("accept").and_then(|v| v.as_str());
+    crate::error_format::wants_text(req.query(), accept)
+}
+
+/// Replaces an error response's JSON body with its bare `error` message as `text/plain`.
+/// Responses without an `error` string are returned unchanged.
+pub fn render_text(mut resp: Response) -> Response {
+    if *resp.status() < 400 {
+        return resp;
+    }
+    let Some(message) = error_message(resp.body()) else {
+        return resp;
+    };
+    resp.set_header("content-type", "text/plain; charset=utf-8");
+    *resp.body_mut() = message.into_bytes();
+    resp
+}
@@ -0,0 +1,7 @@
+//! Helpers shared by the three Spin components (`spin` feature), the counterpart of
+//! [`crate::axum`] for the Wasm runtime.
+
+pub mod body;
+pub mod db;
+pub mod db_health;
+pub mod error_format;
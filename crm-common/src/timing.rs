@@ -0,0 +1,179 @@
+//! Building, parsing and merging of `server-timing` header values.
+
+use std::fmt;
+
+/// Builds a `server-timing` header value such as
+/// `conn;dur=0.1, query;dur=1.2, ser;dur=0.0, body;size=42`.
+#[derive(Default)]
+pub struct ServerTiming {
+    segments: Vec<String>,
+}
+
+impl ServerTiming {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends `name;dur=ms` with the one-decimal precision all services use.
+    pub fn dur(mut self, name: &str, ms: f64) -> Self {
+        self.segments.push(format!("{};dur={:.1}", name, ms));
+        self
+    }
+
+    /// Appends `name;size=bytes`.
+    pub fn size(mut self, name: &str, bytes: usize) -> Self {
+        self.segments.push(format!("{};size={}", name, bytes));
+        self
+    }
+
+    /// The `conn`, `query`, `ser` breakdown shared by every DB-backed handler.
+    pub fn db(conn_ms: f64, query_ms: f64, ser_ms: f64) -> Self {
+        Self::new()
+            .dur("conn", conn_ms)
+            .dur("query", query_ms)
+            .dur("ser", ser_ms)
+    }
+}
+
+impl fmt::Display for ServerTiming {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.segments.join(", "))
+    }
+}
+
+/// One `server-timing` entry: the metric name, its `dur` if present, and every other
+/// parameter (`desc=`, `size=`, ...) kept verbatim.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metric {
+    pub name: String,
+    pub dur: Option<f64>,
+    pub params: Vec<String>,
+}
+
+/// Parses a `server-timing` value into metrics in header order. A `dur` that isn't a
+/// number is kept verbatim with the other parameters so formatting loses nothing.
+pub fn parse_server_timing(header: &str) -> Vec<Metric> {
+    header
+        .split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.split(';').map(str::trim);
+            let name = parts.next().filter(|n| !n.is_empty())?;
+            let mut dur = None;
+            let mut params = Vec::new();
+            for param in parts {
+                match param
+                    .strip_prefix("dur=")
+                    .and_then(|v| v.trim_matches('"').parse().ok())
+                {
+                    Some(v) => dur = Some(v),
+                    None => params.push(param.to_string()),
+                }
+            }
+            Some(Metric {
+                name: name.to_string(),
+                dur,
+                params,
+            })
+        })
+        .collect()
+}
+
+/// Formats metrics as a `server-timing` value, rounding durations to microseconds.
+pub fn format_server_timing(metrics: &[Metric]) -> String {
+    metrics
+        .iter()
+        .map(|m| {
+            let mut entry = m.name.clone();
+            if let Some(dur) = m.dur {
+                entry.push_str(&format!(";dur={}", (dur * 1000.0).round() / 1000.0));
+            }
+            for param in &m.params {
+                entry.push(';');
+                entry.push_str(param);
+            }
+            entry
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Merges `new` into `existing`, keeping first-seen order. Durations of metrics present
+/// in both are summed so a repeated phase accumulates instead of appearing twice; other
+/// parameters such as `body;size=` pass through unchanged.
+pub fn merge_timings(existing: &str, new: &str) -> String {
+    let mut merged = parse_server_timing(existing);
+    for metric in parse_server_timing(new) {
+        match merged.iter_mut().find(|m| m.name == metric.name) {
+            Some(m) => {
+                if let Some(dur) = metric.dur {
+                    *m.dur.get_or_insert(0.0) += dur;
+                }
+                for param in metric.params {
+                    if !m.params.contains(&param) {
+                        m.params.push(param);
+                    }
+                }
+            }
+            None => merged.push(metric),
+        }
+    }
+    format_server_timing(&merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builder_output_round_trips() {
+        let header = ServerTiming::db(0.1, 1.2, 0.0).size("body", 42).to_string();
+        assert_eq!(header, "conn;dur=0.1, query;dur=1.2, ser;dur=0.0, body;size=42");
+        let metrics = parse_server_timing(&header);
+        assert_eq!(
+            format_server_timing(&metrics),
+            "conn;dur=0.1, query;dur=1.2, ser;dur=0, body;size=42"
+        );
+        assert_eq!(parse_server_timing(&format_server_timing(&metrics)), metrics);
+    }
+
+    #[test]
+    fn round_trip_keeps_desc_size_and_malformed_dur() {
+        let header = "db;dur=1.5;desc=\"primary\", body;size=10, odd;dur=abc";
+        let metrics = parse_server_timing(header);
+        assert_eq!(
+            metrics[0],
+            Metric {
+                name: "db".into(),
+                dur: Some(1.5),
+                params: vec!["desc=\"primary\"".into()],
+            }
+        );
+        assert_eq!(metrics[2].dur, None);
+        assert_eq!(metrics[2].params, vec!["dur=abc".to_string()]);
+        assert_eq!(format_server_timing(&metrics), header);
+    }
+
+    #[test]
+    fn merge_sums_duplicate_names_in_first_seen_order() {
+        let merged = merge_timings("query;dur=1.2, ser;dur=0.3", "gateway;dur=2, query;dur=0.8");
+        assert_eq!(merged, "query;dur=2, ser;dur=0.3, gateway;dur=2");
+    }
+
+    #[test]
+    fn merge_passes_non_dur_params_through() {
+        let merged = merge_timings(
+            "conn;dur=0.1, body;size=42, req;size=7",
+            "gateway;dur=1.5, conn;dur=0.2;desc=pool",
+        );
+        assert_eq!(
+            merged,
+            "conn;dur=0.3;desc=pool, body;size=42, req;size=7, gateway;dur=1.5"
+        );
+    }
+
+    #[test]
+    fn merge_into_empty_header() {
+        assert_eq!(merge_timings("", "gateway;dur=1.5"), "gateway;dur=1.5");
+        assert_eq!(merge_timings("", ""), "");
+    }
+}
@@ -0,0 +1,9 @@
+-- schema_version 2: 注文の変更履歴（GET /orders/{id}/history）用の order_audit を追加する。
+-- 既存の注文には履歴がなく、履歴は適用後の変更から記録される。
+-- 対象 DB ごとに実行する: psql -d crm_wasm -f k8s/migrations/order-audit.sql
+-- orders-uuid.sql 適用済みの DB では order_id を UUID にして作成すること。
+BEGIN;
+CREATE TABLE order_audit (id BIGSERIAL PRIMARY KEY, order_id BIGINT NOT NULL, changed_at TIMESTAMPTZ NOT NULL DEFAULT now(), change TEXT NOT NULL);
+CREATE INDEX order_audit_order_id ON order_audit (order_id);
+INSERT INTO schema_version VALUES (2);
+COMMIT;
@@ -0,0 +1,12 @@
+-- 任意: orders.id を UUID に切り替える（ID_STRATEGY=uuid / id_strategy=uuid 用）。
+-- 既存の連番 ID は UUID に対応付けられないため、全注文に新しい UUID を振り直す。
+-- 対象 DB ごとに実行する: psql -d crm_wasm -f k8s/migrations/orders-uuid.sql
+BEGIN;
+ALTER TABLE orders ALTER COLUMN id DROP DEFAULT;
+ALTER TABLE orders ALTER COLUMN id SET DATA TYPE UUID USING gen_random_uuid();
+ALTER TABLE orders ALTER COLUMN id SET DEFAULT gen_random_uuid();
+DROP SEQUENCE IF EXISTS orders_id_seq;
+-- 変更履歴は旧 ID に紐づくため破棄し、order_audit.order_id も UUID に揃える
+TRUNCATE order_audit;
+ALTER TABLE order_audit ALTER COLUMN order_id SET DATA TYPE UUID USING NULL;
+COMMIT;
@@ -41,7 +41,7 @@ echo ""
 echo "=== 4/6 Axum build & import ==="
 for svc in gateway customer-service order-service; do
   echo "--- axum-crm/${svc} ---"
-  docker build -t "axum-crm-${svc}:latest" "axum-crm/${svc}/"
+  docker build -t "axum-crm-${svc}:latest" -f "axum-crm/${svc}/Dockerfile" .
 done
 k3d image import axum-crm-gateway:latest axum-crm-customer-service:latest axum-crm-order-service:latest -c wasm-cluster
 
@@ -1,10 +1,32 @@
 use anyhow::Result;
+use crm_common::db_schema;
+use crm_common::db_url;
+use crm_common::debug_sql;
+use crm_common::envelope;
+use crm_common::hops;
+use crm_common::json;
+use crm_common::paging::{content_range, link_header, parse_items_range, parse_offset};
+use crm_common::path::{bool_param, query_param, resource_id};
+use crm_common::prefer;
+use crm_common::routes::{self, Route};
+use crm_common::schema;
+use crm_common::secrets::mask_secrets;
+use crm_common::spin::body;
+use crm_common::spin::db::Db;
+use crm_common::spin::db_health;
+use crm_common::spin::error_format;
+use crm_common::{ApiError, ServerTiming};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use spin_sdk::http::{IntoResponse, Method, Request, Response};
+use serde_json::{Map, Value};
+use spin_sdk::http::{send, IntoResponse, Method, Request, Response};
 use spin_sdk::http_component;
-use spin_sdk::pg4::{Connection, Decode, ParameterValue};
+use spin_sdk::pg4::{Decode, ParameterValue, PgError, QueryError};
 use spin_sdk::variables;
-use std::time::Instant;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 #[derive(Serialize, Deserialize)]
 struct Customer {
@@ -13,49 +35,401 @@ struct Customer {
     email: String,
 }
 
+/// A customer rendered for `with_order_count=true`.
+#[derive(Serialize)]
+struct CustomerWithOrderCount<'a> {
+    #[serde(flatten)]
+    customer: &'a Customer,
+    order_count: i64,
+}
+
 #[derive(Deserialize)]
 struct CreateCustomerRequest {
     name: Option<String>,
     email: Option<String>,
 }
 
+#[derive(Deserialize)]
+struct ExistsRequest {
+    ids: Vec<i64>,
+}
+
+#[derive(Deserialize)]
+struct ValidateEmailsRequest {
+    emails: Vec<String>,
+}
+
+/// One entry of the `POST /customers/validate` response, in request order.
+#[derive(Serialize)]
+struct EmailCheck<'a> {
+    email: &'a str,
+    valid: bool,
+    exists: bool,
+}
+
+#[derive(Deserialize)]
+struct MergeRequest {
+    into: Option<i64>,
+}
+
+/// Keys accepted in create bodies when `strict_json` is `true`.
+const CREATE_CUSTOMER_FIELDS: &[&str] = &["name", "email"];
+
+/// Per-instance `GET /customers` cache with the same TTL semantics as the Axum service.
+/// Spin instances are short-lived, so hits only occur while the host reuses an instance.
+static LIST_CACHE: Mutex<Option<(String, Instant)>> = Mutex::new(None);
+
+const DEFAULT_MAX_PAGE_SIZE: i64 = 500;
+const DEFAULT_MAX_ROWS: i64 = 10_000;
+const MAX_BULK_CUSTOMERS: usize = 1000;
+/// Variables without which no request can be served; checked before routing.
+const REQUIRED_VARIABLES: &[&str] = &["db_url"];
+
+/// Requests served by this instance, sent as `x-request-count`. Spin usually creates an
+/// instance per request, so values above 1 only appear when the host reuses one. The
+/// first request an instance handles is also marked `x-warmup: true`.
+static REQUEST_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// Framework name sent as `x-runtime`, so mixed-fleet benchmark results can be
+/// attributed without tracking ports.
+const RUNTIME: &str = "spin";
+const DEFAULT_SLO_MS: u64 = 100;
+
 #[http_component]
 fn handle_request(req: Request) -> Result<impl IntoResponse> {
+    let started = Instant::now();
+    let (method, path) = (req.method().to_string(), req.path().to_string());
+    let text_errors = error_format::wants_text(&req);
+    let count = REQUEST_COUNT.fetch_add(1, Ordering::Relaxed) + 1;
+    let resp = match route(req) {
+        Ok(resp) => resp,
+        Err(e) => {
+            if e.is::<PgError>() {
+                db_health::record(false);
+            }
+            internal_error(&e)?
+        }
+    };
+    let mut resp = if text_errors {
+        error_format::render_text(resp)
+    } else {
+        resp
+    };
+    resp.set_header("x-request-count", count.to_string());
+    // Each instance starts cold, so only its first request is a warmup sample
+    if count == 1 {
+        resp.set_header("x-warmup", "true");
+    }
+    db_health::stamp(&mut resp);
+    flag_slo_violation(&mut resp, started, &method, &path);
+    resp.set_header("x-runtime", RUNTIME);
+    Ok(resp)
+}
+
+/// `slo_ms` (default 100): responses that took longer, counted from the top of
+/// `handle_request`, get `x-slo-violation: true` and a warning on stderr.
+fn flag_slo_violation(resp: &mut Response, started: Instant, method: &str, path: &str) {
+    let slo_ms: u64 = variables::get("slo_ms")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_SLO_MS);
+    let elapsed_ms = started.elapsed().as_secs_f64() * 1000.0;
+    if elapsed_ms > slo_ms as f64 {
+        eprintln!(
+            "SLO exceeded: {} {} took {:.1}ms (slo_ms={})",
+            method, path, elapsed_ms, slo_ms
+        );
+        resp.set_header("x-slo-violation", "true");
+    }
+}
+
+/// Served by `GET /routez`; keep in step with the dispatch in [`route`].
+const ROUTES: &[Route] = &[
+    Route { path: "/healthz", methods: &["GET"] },
+    Route { path: "/schemaz", methods: &["GET"] },
+    Route { path: "/routez", methods: &["GET"] },
+    Route { path: "/customers/ping", methods: &["GET"] },
+    Route { path: "/customers", methods: &["GET", "POST"] },
+    Route { path: "/customers/bulk", methods: &["POST"] },
+    Route { path: "/customers/exists", methods: &["POST"] },
+    Route { path: "/customers/validate", methods: &["POST"] },
+    Route { path: "/customers/{id}", methods: &["GET", "DELETE"] },
+    Route { path: "/customers/{id}/merge", methods: &["POST"] },
+];
+
+fn route(req: Request) -> Result<Response> {
     let path = req.path().to_string();
     let method = req.method();
 
     if path == "/healthz" {
         return json_response(200, r#"{"status":"ok"}"#);
     }
+    if path == "/routez" {
+        return json_response(200, &routes::routez_body(ROUTES));
+    }
+
+    if let Some(name) = missing_variable(REQUIRED_VARIABLES) {
+        return json_response(500, &format!(r#"{{"error":"configuration missing: {}"}}"#, name));
+    }
+    if let Err(msg) = db_url::check_scheme(&variables::get("db_url")?) {
+        eprintln!("db_url {}", msg);
+        return error_response(ApiError::new(500, format!("configuration invalid: db_url {}", msg)));
+    }
+    let search_path = match db_schema::search_path(&variables::get("db_schema")?) {
+        Ok(sql) => sql,
+        Err(msg) => {
+            eprintln!("db_schema {}", msg);
+            let message = format!("configuration invalid: db_schema {}", msg);
+            return error_response(ApiError::new(500, message));
+        }
+    };
+
+    let resource_id = match resource_id(&path) {
+        Ok(id) => id,
+        Err(msg) => return error_response(ApiError::bad_request(msg)),
+    };
+    let body = match body::decoded_body(&req) {
+        Ok(b) => b,
+        Err((status, msg)) => return json_response(status, msg),
+    };
+    let fmt = match OutputFormat::from_query(req.query()) {
+        Ok(f) => f,
+        Err(msg) => return error_response(ApiError::bad_request(msg)),
+    };
+
+    let page = if method == &Method::Get && resource_id.is_none() {
+        let range = req.header("range").and_then(|v| v.as_str());
+        match parse_page(req.query(), range) {
+            Ok(p) => p,
+            Err(msg) => return error_response(ApiError::bad_request(msg)),
+        }
+    } else {
+        None
+    };
+
+    let fields = if method == &Method::Get {
+        match parse_fields(query_param(req.query(), "fields")) {
+            Ok(f) => f,
+            Err(msg) => return error_response(ApiError::bad_request(msg)),
+        }
+    } else {
+        None
+    };
+    if fmt.with_order_count && fields.is_some() {
+        return error_response(ApiError::bad_request(
+            "with_order_count cannot be combined with fields",
+        ));
+    }
+
+    let csv = if method == &Method::Get && resource_id.is_none() {
+        let accept = req.header("accept").and_then(|v| v.as_str());
+        match wants_csv(query_param(req.query(), "format"), accept) {
+            Ok(c) => c,
+            Err(msg) => return error_response(ApiError::bad_request(msg)),
+        }
+    } else {
+        false
+    };
+    if csv && (fmt.with_order_count || fields.is_some()) {
+        return error_response(ApiError::bad_request(
+            "format=csv cannot be combined with fields or with_order_count",
+        ));
+    }
+
+    // Only the default, unpaginated, full-field list representation is cached
+    let cache_list = method == &Method::Get
+        && resource_id.is_none()
+        && !csv
+        && !fmt.ids_as_strings
+        && !fmt.envelope
+        && !fmt.with_order_count
+        && page.is_none()
+        && fields.is_none();
+    let list_cache_ttl = if cache_list { list_cache_ttl() } else { Duration::ZERO };
+    if !list_cache_ttl.is_zero() {
+        if let Some(body) = cached_list(list_cache_ttl) {
+            let resp = cached_response(&body)?;
+            return Ok(no_content_if_empty(resp, empty_list_204() && body == "[]"));
+        }
+    }
 
     let t_conn = Instant::now();
-    let conn = Connection::open(&variables::get("db_url")?)?;
+    let conn = match Db::open(&variables::get("db_url")?, search_path) {
+        Ok(c) => c,
+        Err(_) => return db_error(),
+    };
     let conn_ms = t_conn.elapsed().as_secs_f64() * 1000.0;
 
-    let (_, resource_id) = parse_path(&path);
+    if path == "/schemaz" {
+        return schemaz(&conn, conn_ms);
+    }
+
+    if let Some(id) = parse_merge_path(&path) {
+        return match method {
+            &Method::Post => merge_customer(&conn, conn_ms, &req, id, &body, fmt),
+            _ => json_response(405, r#"{"error":"Method not allowed"}"#),
+        };
+    }
 
     match (method, resource_id) {
         (&Method::Get, Some("ping")) => ping_db(&conn, conn_ms),
-        (&Method::Get, None) => list_customers(&conn, conn_ms),
-        (&Method::Post, None) => create_customer(&conn, conn_ms, req.body()),
-        (&Method::Get, Some(id)) => get_customer(&conn, conn_ms, id),
-        (&Method::Delete, Some(id)) => delete_customer(&conn, conn_ms, id),
+        (&Method::Get, None) => {
+            // Counted first so an unsatisfiable `Range` is answered before the list query
+            let paging = match page {
+                Some(page) => {
+                    let count_sql = "SELECT COUNT(*) FROM customers";
+                    match page_headers(&conn, count_sql, &[], &req, page)? {
+                        Ok(h) => Some(h),
+                        Err(resp) => return Ok(resp),
+                    }
+                }
+                None => None,
+            };
+            let resp = list_customers(
+                &conn,
+                conn_ms,
+                page,
+                fields.as_deref(),
+                fmt,
+                csv,
+                list_cache_ttl,
+            )?;
+            Ok(match paging {
+                Some(h) => with_page_headers(resp, h),
+                None => resp,
+            })
+        }
+        (&Method::Post, Some("bulk")) => {
+            bulk_create_customers(&conn, conn_ms, &body, fmt.ids_as_strings)
+        }
+        (&Method::Post, Some("exists")) => customers_exist(&conn, conn_ms, &body),
+        (&Method::Post, Some("validate")) => validate_emails(&conn, conn_ms, &body),
+        (&Method::Post, None) => create_customer(&conn, conn_ms, &body, fmt.ids_as_strings),
+        (&Method::Get, Some(id)) => {
+            get_customer(&conn, conn_ms, id, fields.as_deref(), fmt)
+        }
+        (&Method::Delete, Some(id)) => delete_customer(&conn, conn_ms, &req, id, fmt),
         _ => json_response(405, r#"{"error":"Method not allowed"}"#),
     }
 }
 
-fn parse_path(uri: &str) -> (&str, Option<&str>) {
-    let path = uri.split('?').next().unwrap_or(uri);
-    let path = path.trim_end_matches('/');
-    let parts: Vec<&str> = path.split('/').collect();
-    if parts.len() >= 3 && !parts[2].is_empty() {
-        ("/customers", Some(parts[2]))
-    } else {
-        ("/customers", None)
+/// Matches `/customers/{id}/merge` and returns the raw customer id segment.
+fn parse_merge_path(path: &str) -> Option<&str> {
+    path.trim_end_matches('/')
+        .strip_prefix("/customers/")?
+        .strip_suffix("/merge")
+}
+
+/// How a `limit` above `max_page_size` is handled: clamped by default, 400 when `strict`.
+struct PageLimits {
+    max_page_size: i64,
+    strict: bool,
+}
+
+fn page_limits() -> PageLimits {
+    PageLimits {
+        max_page_size: variables::get("max_page_size")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|&n| n > 0)
+            .unwrap_or(DEFAULT_MAX_PAGE_SIZE),
+        strict: variables::get("strict_limits")
+            .map(|v| v == "true")
+            .unwrap_or(false),
+    }
+}
+
+/// Row cap for unpaged lists; queries fetch one row past it to detect truncation.
+fn max_rows() -> i64 {
+    variables::get("max_rows")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(DEFAULT_MAX_ROWS)
+}
+
+/// `empty_list_204 = "true"`: turns an empty bare-array list into 204 with no body,
+/// keeping `server-timing` and the other headers. `?envelope=true` lists stay 200.
+fn no_content_if_empty(resp: Response, empty: bool) -> Response {
+    if !empty {
+        return resp;
+    }
+    let mut builder = Response::builder();
+    builder.status(204);
+    for (name, value) in resp.headers() {
+        if let Some(value) = value.as_str().filter(|_| !name.eq_ignore_ascii_case("content-type")) {
+            builder.header(name, value);
+        }
+    }
+    builder.build()
+}
+
+/// `debug_sql=true`: `X-Debug-SQL` with the read's statement and bound-parameter count.
+/// Exposes the schema to any client, so it is for local debugging only.
+fn set_debug_sql(resp: &mut Response, sql: &str, params: usize) {
+    let enabled = variables::get("debug_sql")
+        .map(|v| v == "true")
+        .unwrap_or(false);
+    if enabled {
+        resp.set_header(debug_sql::HEADER, debug_sql::describe(sql, params));
+    }
+}
+
+fn empty_list_204() -> bool {
+    variables::get("empty_list_204")
+        .map(|v| v == "true")
+        .unwrap_or(false)
+}
+
+/// Drops the probe row fetched past `cap` (queries ask for `cap + 1`). Returns whether
+/// the cap was hit, i.e. the table holds more rows than were returned.
+fn truncate_to_cap<T>(rows: &mut Vec<T>, cap: Option<i64>) -> bool {
+    match cap {
+        Some(cap) if rows.len() as i64 > cap => {
+            rows.truncate(cap as usize);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Parses `Range: items=<first>-<last>` or, without one, optional `?limit=&offset=` into
+/// `(limit, offset)`, or `None` when the request is unpaginated. Omitting both returns
+/// every row.
+fn parse_page(query: &str, range: Option<&str>) -> Result<Option<(i64, i64)>, String> {
+    let limits = page_limits();
+    let (limit, offset) = match range {
+        Some(raw) => parse_items_range(raw)?,
+        None => {
+            let limit = query_param(query, "limit");
+            let offset = query_param(query, "offset");
+            if limit.is_none() && offset.is_none() {
+                return Ok(None);
+            }
+            let offset = match offset {
+                Some(v) => parse_offset(v)?,
+                None => 0,
+            };
+            let limit = match limit {
+                Some(v) => v.parse::<i64>().map_err(|_| "limit must be an integer")?,
+                None => limits.max_page_size,
+            };
+            (limit, offset)
+        }
+    };
+    if limit < 1 {
+        return Err("limit must be positive".to_string());
+    }
+    if limit > limits.max_page_size {
+        if limits.strict {
+            return Err(format!("limit exceeds maximum of {}", limits.max_page_size));
+        }
+        return Ok(Some((limits.max_page_size, offset)));
     }
+    Ok(Some((limit, offset)))
 }
 
-fn ping_db(conn: &Connection, conn_ms: f64) -> Result<Response> {
+fn ping_db(conn: &Db, conn_ms: f64) -> Result<Response> {
     let t_query = Instant::now();
     conn.query("SELECT 1", &[])?;
     let query_ms = t_query.elapsed().as_secs_f64() * 1000.0;
@@ -67,12 +441,106 @@ fn ping_db(conn: &Connection, conn_ms: f64) -> Result<Response> {
     timed_response(200, &body, conn_ms, query_ms, 0.0)
 }
 
-fn list_customers(conn: &Connection, conn_ms: f64) -> Result<Response> {
+/// Schema version check; a missing `schema_version` table reports `current: null`.
+fn schemaz(conn: &Db, conn_ms: f64) -> Result<Response> {
+    let t_query = Instant::now();
+    let current = match conn.query(schema::VERSION_QUERY, &[]) {
+        Ok(rowset) => rowset.rows.first().and_then(|row| i64::decode(&row[0]).ok()),
+        Err(PgError::QueryFailed(QueryError::DbError(db))) if db.code == schema::UNDEFINED_TABLE => {
+            None
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let query_ms = t_query.elapsed().as_secs_f64() * 1000.0;
+
+    timed_response(200, &schema::schemaz_body(current), conn_ms, query_ms, 0.0)
+}
+
+/// Paging headers for a list: the RFC 8288 `Link` header and, for a `Range` request,
+/// `Content-Range`. `Err` carries the 416 for a range starting past the last row.
+struct PageHeaders {
+    link: String,
+    content_range: Option<String>,
+}
+
+fn page_headers(
+    conn: &Db,
+    count_sql: &str,
+    params: &[ParameterValue],
+    req: &Request,
+    (limit, offset): (i64, i64),
+) -> Result<std::result::Result<PageHeaders, Response>> {
+    let rowset = conn.query(count_sql, params)?;
+    let total = rowset
+        .rows
+        .first()
+        .and_then(|row| i64::decode(&row[0]).ok())
+        .unwrap_or(0);
+    let content_range = match req.header("range") {
+        Some(_) => match content_range(limit, offset, total) {
+            Some(v) => Some(v),
+            None => return range_not_satisfiable(total).map(Err),
+        },
+        None => None,
+    };
+    Ok(Ok(PageHeaders {
+        link: link_header(req.path(), req.query(), limit, offset, total),
+        content_range,
+    }))
+}
+
+/// Sets `Link` and, for a `Range` request, turns the list into 206 with `Content-Range`.
+fn with_page_headers(mut resp: Response, headers: PageHeaders) -> Response {
+    resp.set_header("link", headers.link);
+    match headers.content_range {
+        Some(v) => resp.into_builder().status(206).header("content-range", v).build(),
+        None => resp,
+    }
+}
+
+fn range_not_satisfiable(total: i64) -> Result<Response> {
+    let mut resp = json_response(416, r#"{"error":"Range not satisfiable"}"#)?;
+    resp.set_header("content-range", format!("items */{}", total));
+    Ok(resp)
+}
+
+fn list_customers(
+    conn: &Db,
+    conn_ms: f64,
+    page: Option<(i64, i64)>,
+    fields: Option<&[&str]>,
+    fmt: OutputFormat,
+    csv: bool,
+    cache_ttl: Duration,
+) -> Result<Response> {
+    // Unpaged reads stop at `max_rows`; the extra row only tells whether the cap was hit
+    let row_cap = max_rows();
+    let cap = page.is_none().then_some(row_cap);
+    let (filter, params) = match page {
+        Some((limit, offset)) => (
+            " ORDER BY id LIMIT $1 OFFSET $2",
+            vec![ParameterValue::Int64(limit), ParameterValue::Int64(offset)],
+        ),
+        None => (
+            " ORDER BY id LIMIT $1",
+            vec![ParameterValue::Int64(row_cap + 1)],
+        ),
+    };
+
+    if let Some(fields) = fields {
+        let shape = SparseShape::Many {
+            cap,
+            empty_as_204: empty_list_204(),
+        };
+        return sparse_response(conn, conn_ms, fields, filter, &params, shape, fmt);
+    }
+
     let t_query = Instant::now();
-    let rowset = conn.query("SELECT id, name, email FROM customers", &[])?;
+    let sql = format!("SELECT id, name, email FROM customers{}", filter);
+    let rowset = conn.query(&sql, &params)?;
     let query_ms = t_query.elapsed().as_secs_f64() * 1000.0;
 
-    let customers: Vec<Customer> = rowset
+    let mut customers: Vec<Customer> = rowset
         .rows
         .iter()
         .map(|row| Customer {
@@ -81,43 +549,127 @@ fn list_customers(conn: &Connection, conn_ms: f64) -> Result<Response> {
             email: String::decode(&row[2]).unwrap_or_default(),
         })
         .collect();
+    let truncated = truncate_to_cap(&mut customers, cap);
+
+    if csv {
+        let t_ser = Instant::now();
+        let body = customers_csv(&customers)?;
+        let ser_ms = t_ser.elapsed().as_secs_f64() * 1000.0;
+        let mut resp = timed_response(200, &body, conn_ms, query_ms, ser_ms)?;
+        resp.set_header("content-type", "text/csv; charset=utf-8");
+        resp.set_header("content-disposition", r#"attachment; filename="customers.csv""#);
+        if truncated {
+            resp.set_header("x-truncated", "true");
+        }
+        set_debug_sql(&mut resp, &sql, params.len());
+        return Ok(resp);
+    }
+
+    let orders = if fmt.with_order_count {
+        let ids: Vec<i64> = customers.iter().map(|c| c.id).collect();
+        match timed_order_counts(conn, &ids)? {
+            Some(v) => Some(v),
+            None => return order_service_unavailable(),
+        }
+    } else {
+        None
+    };
 
     let t_ser = Instant::now();
-    let body = serde_json::to_string(&customers)?;
+    let body = match &orders {
+        Some((counts, _)) => {
+            let rows: Vec<_> = customers.iter().map(|c| counted(c, counts)).collect();
+            read_json(&rows, Some(rows.len()), fmt)?
+        }
+        None => read_json(&customers, Some(customers.len()), fmt)?,
+    };
     let ser_ms = t_ser.elapsed().as_secs_f64() * 1000.0;
 
-    timed_response(200, &body, conn_ms, query_ms, ser_ms)
+    if !cache_ttl.is_zero() && !truncated {
+        *LIST_CACHE.lock().unwrap() = Some((body.clone(), Instant::now()));
+    }
+
+    let mut resp = timed_response(200, &body, conn_ms, query_ms, ser_ms)?;
+    if let Some((_, orders_ms)) = orders {
+        resp = append_timing(resp, &format!("orders;dur={:.1}", orders_ms));
+    }
+    if truncated {
+        resp.set_header("x-truncated", "true");
+    }
+    set_debug_sql(&mut resp, &sql, params.len());
+    let empty = empty_list_204() && customers.is_empty() && !fmt.envelope;
+    Ok(no_content_if_empty(resp, empty))
+}
+
+/// `?format=csv`, or no `format` and an `Accept` header listing `text/csv`. Explicit
+/// `format=json` wins over the header.
+fn wants_csv(format: Option<&str>, accept: Option<&str>) -> Result<bool, &'static str> {
+    match format {
+        Some("csv") => Ok(true),
+        Some("json") => Ok(false),
+        Some(_) => Err("format must be json or csv"),
+        None => Ok(accept.is_some_and(|accept| {
+            accept.split(',').any(|media| {
+                let media = media.split(';').next().unwrap_or_default();
+                media.trim().eq_ignore_ascii_case("text/csv")
+            })
+        })),
+    }
+}
+
+/// Header row plus one record per customer. The `csv` writer quotes names and emails
+/// containing commas, quotes or line breaks.
+fn customers_csv(customers: &[Customer]) -> Result<String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(["id", "name", "email"])?;
+    for c in customers {
+        writer.write_record([c.id.to_string().as_str(), &c.name, &c.email])?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
 }
 
-fn create_customer(conn: &Connection, conn_ms: f64, body: &[u8]) -> Result<Response> {
+fn create_customer(
+    conn: &Db,
+    conn_ms: f64,
+    body: &[u8],
+    ids_as_strings: bool,
+) -> Result<Response> {
+    let req_size = body.len();
     let body_str = std::str::from_utf8(body)?;
-    let input: CreateCustomerRequest = match serde_json::from_str(body_str) {
+    let input: CreateCustomerRequest =
+        match parse_body(body_str.as_bytes(), CREATE_CUSTOMER_FIELDS, strict_json()) {
+            Ok(v) => v,
+            Err(msg) => return error_response(ApiError::bad_request(msg)),
+        };
+
+    let (name, email) = match validate_customer(&input, collapse_name_whitespace()) {
         Ok(v) => v,
-        Err(_) => return json_response(400, r#"{"error":"Invalid JSON"}"#),
+        Err(msg) => return error_response(ApiError::bad_request(msg)),
     };
 
-    let name = match &input.name {
-        Some(n) if !n.is_empty() => n.clone(),
-        _ => return json_response(400, r#"{"error":"name and email are required"}"#),
-    };
-    let email = match &input.email {
-        Some(e) if !e.is_empty() => e.clone(),
-        _ => return json_response(400, r#"{"error":"name and email are required"}"#),
+    // Best-effort duplicate check for databases without the unique index. Racy: two
+    // concurrent inserts can both pass, so the DB constraint is preferred when possible.
+    let check_ms = if check_email_unique() {
+        let t_check = Instant::now();
+        let existing = conn.query(
+            "SELECT 1 FROM customers WHERE email = $1",
+            &[ParameterValue::Str(email.clone())],
+        )?;
+        if !existing.rows.is_empty() {
+            return json_response(409, r#"{"error":"email already exists"}"#);
+        }
+        Some(t_check.elapsed().as_secs_f64() * 1000.0)
+    } else {
+        None
     };
 
-    if name.len() > 255 {
-        return json_response(400, r#"{"error":"name must be 255 characters or less"}"#);
-    }
-    if email.len() > 255 || !email.contains('@') {
-        return json_response(400, r#"{"error":"invalid email format"}"#);
-    }
-
     let t_query = Instant::now();
     let rowset = conn.query(
         "INSERT INTO customers (name, email) VALUES ($1, $2) RETURNING id, name, email",
         &[ParameterValue::Str(name), ParameterValue::Str(email)],
     )?;
     let query_ms = t_query.elapsed().as_secs_f64() * 1000.0;
+    invalidate_list_cache();
 
     let customer = rowset.rows.first().map(|row| Customer {
         id: i64::decode(&row[0]).unwrap_or(0),
@@ -128,26 +680,387 @@ fn create_customer(conn: &Connection, conn_ms: f64, body: &[u8]) -> Result<Respo
     match customer {
         Some(c) => {
             let t_ser = Instant::now();
-            let body = serde_json::to_string(&c)?;
+            let body = to_json(&c, ids_as_strings)?;
             let ser_ms = t_ser.elapsed().as_secs_f64() * 1000.0;
-            timed_response(201, &body, conn_ms, query_ms, ser_ms)
+            let Some(check_ms) = check_ms else {
+                let resp = timed_response(201, &body, conn_ms, query_ms, ser_ms)?;
+                return Ok(with_request_size(resp, req_size));
+            };
+            Ok(Response::builder()
+                .status(201)
+                .header("content-type", "application/json")
+                .header(
+                    "server-timing",
+                    ServerTiming::new()
+                        .dur("conn", conn_ms)
+                        .dur("check", check_ms)
+                        .dur("query", query_ms)
+                        .dur("ser", ser_ms)
+                        .size("body", body.len())
+                        .size("req", req_size)
+                        .to_string(),
+                )
+                .body(body)
+                .build())
         }
         None => json_response(500, r#"{"error":"Failed to retrieve created customer"}"#),
     }
 }
 
-fn get_customer(conn: &Connection, conn_ms: f64, id_str: &str) -> Result<Response> {
-    let id: i64 = match id_str.parse() {
+/// Parses a JSON body. With `strict`, first rejects object keys outside `allowed`
+/// (checked per element for arrays) so typos like `nmae` surface as 400s.
+fn parse_body<T: DeserializeOwned>(body: &[u8], allowed: &[&str], strict: bool) -> Result<T, String> {
+    if !strict {
+        return json::from_slice(body).map_err(|_| "Invalid JSON".to_string());
+    }
+    let value: Value = json::from_slice(body).map_err(|_| "Invalid JSON".to_string())?;
+    let objects: Vec<&Value> = match &value {
+        Value::Array(items) => items.iter().collect(),
+        other => vec![other],
+    };
+    let mut unknown: Vec<&str> = objects
+        .iter()
+        .filter_map(|v| v.as_object())
+        .flat_map(|map| map.keys())
+        .map(String::as_str)
+        .filter(|key| !allowed.contains(key))
+        .collect();
+    unknown.sort_unstable();
+    unknown.dedup();
+    if !unknown.is_empty() {
+        return Err(format!("unknown fields: {}", unknown.join(", ")));
+    }
+    serde_json::from_value(value).map_err(|_| "Invalid JSON".to_string())
+}
+
+fn strict_json() -> bool {
+    variables::get("strict_json")
+        .map(|v| v == "true")
+        .unwrap_or(false)
+}
+
+/// Validates a create payload and returns the normalized `(name, email)`.
+fn validate_customer(
+    input: &CreateCustomerRequest,
+    collapse_whitespace: bool,
+) -> Result<(String, String), &'static str> {
+    let name = match &input.name {
+        Some(n) if !n.is_empty() => normalize_name(n, collapse_whitespace),
+        _ => return Err("name and email are required"),
+    };
+    let email = match &input.email {
+        Some(e) if !e.is_empty() => e.clone(),
+        _ => return Err("name and email are required"),
+    };
+
+    if name.is_empty() {
+        return Err("name cannot be blank");
+    }
+    if name.len() > 255 {
+        return Err("name must be 255 characters or less");
+    }
+    if !validate_email(&email) {
+        return Err("invalid email format");
+    }
+    Ok((name, email))
+}
+
+/// The email rule shared by customer creation and `POST /customers/validate`.
+fn validate_email(email: &str) -> bool {
+    !email.is_empty() && email.len() <= 255 && email.contains('@')
+}
+
+/// Creates up to [`MAX_BULK_CUSTOMERS`] customers with one multi-row INSERT in a
+/// transaction. Any invalid item or email collision rejects the whole batch, reporting
+/// the offending `index`.
+fn bulk_create_customers(
+    conn: &Db,
+    conn_ms: f64,
+    body: &[u8],
+    ids_as_strings: bool,
+) -> Result<Response> {
+    let req_size = body.len();
+    let inputs: Vec<CreateCustomerRequest> =
+        match parse_body(body, CREATE_CUSTOMER_FIELDS, strict_json()) {
+            Ok(v) => v,
+            Err(msg) => return error_response(ApiError::bad_request(msg)),
+        };
+    if inputs.is_empty() {
+        return json_response(400, r#"{"error":"batch must not be empty"}"#);
+    }
+    if inputs.len() > MAX_BULK_CUSTOMERS {
+        return json_response(
+            400,
+            &format!(
+                r#"{{"error":"batch exceeds maximum of {}"}}"#,
+                MAX_BULK_CUSTOMERS
+            ),
+        );
+    }
+
+    let collapse = collapse_name_whitespace();
+    let mut rows = Vec::with_capacity(inputs.len());
+    for (index, input) in inputs.iter().enumerate() {
+        match validate_customer(input, collapse) {
+            Ok(row) => rows.push(row),
+            Err(msg) => {
+                return json_response(
+                    400,
+                    &format!(r#"{{"error":"{}","index":{}}}"#, msg, index),
+                )
+            }
+        }
+    }
+    if let Some(index) = duplicate_email_index(&rows) {
+        return email_conflict(index);
+    }
+
+    let t_query = Instant::now();
+    conn.execute("BEGIN", &[])?;
+    let created = match insert_customer_batch(conn, &rows) {
+        Ok(Ok(created)) => {
+            conn.execute("COMMIT", &[])?;
+            created
+        }
+        Ok(Err(index)) => {
+            conn.execute("ROLLBACK", &[])?;
+            return email_conflict(index);
+        }
+        Err(e) => {
+            let _ = conn.execute("ROLLBACK", &[]);
+            return Err(e);
+        }
+    };
+    let query_ms = t_query.elapsed().as_secs_f64() * 1000.0;
+    invalidate_list_cache();
+
+    let t_ser = Instant::now();
+    let body = to_json(&created, ids_as_strings)?;
+    let ser_ms = t_ser.elapsed().as_secs_f64() * 1000.0;
+
+    let resp = timed_response(201, &body, conn_ms, query_ms, ser_ms)?;
+    Ok(with_request_size(resp, req_size))
+}
+
+/// Runs the batch INSERT inside the caller's transaction. `Ok(Err(index))` reports the
+/// item whose email collides with an existing customer.
+fn insert_customer_batch(
+    conn: &Db,
+    rows: &[(String, String)],
+) -> Result<Result<Vec<Customer>, usize>> {
+    let position = |email: &str| rows.iter().position(|(_, e)| e == email).unwrap_or(0);
+
+    if check_email_unique() {
+        let emails = rows.iter().map(|(_, e)| Some(e.clone())).collect();
+        let taken = conn.query(
+            "SELECT email FROM customers WHERE email = ANY($1) LIMIT 1",
+            &[ParameterValue::ArrayStr(emails)],
+        )?;
+        if let Some(row) = taken.rows.first() {
+            return Ok(Err(position(&String::decode(&row[0]).unwrap_or_default())));
+        }
+    }
+
+    let placeholders: Vec<String> = (0..rows.len())
+        .map(|i| format!("(${}, ${})", 2 * i + 1, 2 * i + 2))
+        .collect();
+    let sql = format!(
+        "INSERT INTO customers (name, email) VALUES {} RETURNING id, name, email",
+        placeholders.join(", ")
+    );
+    let params: Vec<ParameterValue> = rows
+        .iter()
+        .flat_map(|(name, email)| {
+            [
+                ParameterValue::Str(name.clone()),
+                ParameterValue::Str(email.clone()),
+            ]
+        })
+        .collect();
+    let rowset = match conn.query(&sql, &params) {
+        Ok(r) => r,
+        Err(e) => {
+            return match unique_violation_email(&e) {
+                Some(email) => Ok(Err(position(email))),
+                None => Err(e.into()),
+            }
+        }
+    };
+
+    Ok(Ok(rowset
+        .rows
+        .iter()
+        .map(|row| Customer {
+            id: i64::decode(&row[0]).unwrap_or(0),
+            name: String::decode(&row[1]).unwrap_or_default(),
+            email: String::decode(&row[2]).unwrap_or_default(),
+        })
+        .collect()))
+}
+
+/// Index of the first item whose email already appeared earlier in the batch.
+fn duplicate_email_index(rows: &[(String, String)]) -> Option<usize> {
+    let mut seen = std::collections::HashSet::with_capacity(rows.len());
+    rows.iter().position(|(_, email)| !seen.insert(email.as_str()))
+}
+
+/// Extracts the email from a unique-violation detail (`Key (email)=(x) already exists.`).
+fn unique_violation_email(err: &PgError) -> Option<&str> {
+    let PgError::QueryFailed(QueryError::DbError(db)) = err else {
+        return None;
+    };
+    if db.code != "23505" {
+        return None;
+    }
+    let detail = db.detail.as_deref()?;
+    Some(detail.split_once(")=(")?.1.rsplit_once(") already exists")?.0)
+}
+
+fn email_conflict(index: usize) -> Result<Response> {
+    json_response(
+        409,
+        &format!(r#"{{"error":"email already exists","index":{}}}"#, index),
+    )
+}
+
+/// Trims the name and, when enabled, collapses internal whitespace runs to a single space.
+fn normalize_name(name: &str, collapse: bool) -> String {
+    if collapse {
+        name.split_whitespace().collect::<Vec<_>>().join(" ")
+    } else {
+        name.trim().to_string()
+    }
+}
+
+fn collapse_name_whitespace() -> bool {
+    variables::get("collapse_name_whitespace")
+        .map(|v| v == "true")
+        .unwrap_or(false)
+}
+
+fn check_email_unique() -> bool {
+    variables::get("check_email_unique")
+        .map(|v| v == "true")
+        .unwrap_or(false)
+}
+
+/// Reports which of up to [`MAX_BULK_CUSTOMERS`] ids have no customer, so callers such
+/// as the order-service audit can verify many references in one round trip.
+fn customers_exist(conn: &Db, conn_ms: f64, body: &[u8]) -> Result<Response> {
+    let input: ExistsRequest = match json::from_slice(body) {
         Ok(v) => v,
-        Err(_) => return json_response(400, r#"{"error":"Invalid customer ID"}"#),
+        Err(_) => return json_response(400, r#"{"error":"Invalid JSON"}"#),
+    };
+    if input.ids.len() > MAX_BULK_CUSTOMERS {
+        return json_response(
+            400,
+            &format!(r#"{{"error":"batch exceeds maximum of {}"}}"#, MAX_BULK_CUSTOMERS),
+        );
+    }
+
+    let t_query = Instant::now();
+    let ids: Vec<Option<i64>> = input.ids.iter().copied().map(Some).collect();
+    let rowset = conn.query(
+        "SELECT id FROM customers WHERE id = ANY($1)",
+        &[ParameterValue::ArrayInt64(ids)],
+    )?;
+    let query_ms = t_query.elapsed().as_secs_f64() * 1000.0;
+    let found: Vec<i64> = rowset
+        .rows
+        .iter()
+        .filter_map(|row| i64::decode(&row[0]).ok())
+        .collect();
+
+    let t_ser = Instant::now();
+    let mut missing: Vec<i64> = input.ids.into_iter().filter(|id| !found.contains(id)).collect();
+    missing.sort_unstable();
+    missing.dedup();
+    let body = serde_json::json!({ "missing": missing }).to_string();
+    let ser_ms = t_ser.elapsed().as_secs_f64() * 1000.0;
+
+    timed_response(200, &body, conn_ms, query_ms, ser_ms)
+}
+
+/// Import preflight: checks each email against the creation rule and, for the valid
+/// ones, whether a customer already uses it (one `email = ANY($1)` query). Inserts nothing.
+fn validate_emails(conn: &Db, conn_ms: f64, body: &[u8]) -> Result<Response> {
+    let input: ValidateEmailsRequest = match json::from_slice(body) {
+        Ok(v) => v,
+        Err(_) => return json_response(400, r#"{"error":"Invalid JSON"}"#),
     };
+    if input.emails.len() > MAX_BULK_CUSTOMERS {
+        return json_response(
+            400,
+            &format!(r#"{{"error":"batch exceeds maximum of {}"}}"#, MAX_BULK_CUSTOMERS),
+        );
+    }
+    let valid: Vec<Option<String>> = input
+        .emails
+        .iter()
+        .filter(|e| validate_email(e))
+        .map(|e| Some(e.clone()))
+        .collect();
 
     let t_query = Instant::now();
     let rowset = conn.query(
-        "SELECT id, name, email FROM customers WHERE id = $1",
-        &[ParameterValue::Int64(id)],
+        "SELECT email FROM customers WHERE email = ANY($1)",
+        &[ParameterValue::ArrayStr(valid)],
     )?;
     let query_ms = t_query.elapsed().as_secs_f64() * 1000.0;
+    let taken: HashSet<String> = rowset
+        .rows
+        .iter()
+        .filter_map(|row| String::decode(&row[0]).ok())
+        .collect();
+
+    let t_ser = Instant::now();
+    let checks: Vec<EmailCheck> = input
+        .emails
+        .iter()
+        .map(|email| {
+            let valid = validate_email(email);
+            EmailCheck {
+                email,
+                valid,
+                exists: valid && taken.contains(email),
+            }
+        })
+        .collect();
+    let body = json::to_string(&checks)?;
+    let ser_ms = t_ser.elapsed().as_secs_f64() * 1000.0;
+
+    timed_response(200, &body, conn_ms, query_ms, ser_ms)
+}
+
+const GET_CUSTOMER_SQL: &str = "SELECT id, name, email FROM customers WHERE id = $1";
+
+fn get_customer(
+    conn: &Db,
+    conn_ms: f64,
+    id_str: &str,
+    fields: Option<&[&str]>,
+    fmt: OutputFormat,
+) -> Result<Response> {
+    let id: i64 = match id_str.parse() {
+        Ok(v) => v,
+        Err(_) => return json_response(400, r#"{"error":"Invalid customer ID"}"#),
+    };
+    if let Some(fields) = fields {
+        let params = [ParameterValue::Int64(id)];
+        return sparse_response(
+            conn,
+            conn_ms,
+            fields,
+            " WHERE id = $1",
+            &params,
+            SparseShape::One,
+            fmt,
+        );
+    }
+
+    let t_query = Instant::now();
+    let rowset = conn.query(GET_CUSTOMER_SQL, &[ParameterValue::Int64(id)])?;
+    let query_ms = t_query.elapsed().as_secs_f64() * 1000.0;
 
     let customer = rowset.rows.first().map(|row| Customer {
         id: i64::decode(&row[0]).unwrap_or(0),
@@ -157,46 +1070,530 @@ fn get_customer(conn: &Connection, conn_ms: f64, id_str: &str) -> Result<Respons
 
     match customer {
         Some(c) => {
+            let orders = if fmt.with_order_count {
+                match timed_order_counts(conn, &[c.id])? {
+                    Some(v) => Some(v),
+                    None => return order_service_unavailable(),
+                }
+            } else {
+                None
+            };
             let t_ser = Instant::now();
-            let body = serde_json::to_string(&c)?;
+            let body = match &orders {
+                Some((counts, _)) => read_json(&counted(&c, counts), None, fmt)?,
+                None => read_json(&c, None, fmt)?,
+            };
             let ser_ms = t_ser.elapsed().as_secs_f64() * 1000.0;
-            timed_response(200, &body, conn_ms, query_ms, ser_ms)
+            let mut resp = timed_response(200, &body, conn_ms, query_ms, ser_ms)?;
+            set_debug_sql(&mut resp, GET_CUSTOMER_SQL, 1);
+            Ok(match orders {
+                Some((_, orders_ms)) => append_timing(resp, &format!("orders;dur={:.1}", orders_ms)),
+                None => resp,
+            })
         }
         None => json_response(404, r#"{"error":"Customer not found"}"#),
     }
 }
 
-fn delete_customer(conn: &Connection, conn_ms: f64, id_str: &str) -> Result<Response> {
+#[derive(Deserialize)]
+struct OrderCount {
+    count: i64,
+}
+
+/// Order counts keyed by customer id; customers without orders are absent. With
+/// `order_service_url` set each count is fetched from order-service's `/orders/count`
+/// (sequentially, as handlers here are synchronous); otherwise a grouped query on the
+/// `orders` table of this database. `None` when order-service could not answer.
+fn order_counts(conn: &Db, ids: &[i64]) -> Result<Option<HashMap<i64, i64>>> {
+    let base = variables::get("order_service_url").unwrap_or_default();
+    if base.is_empty() {
+        let rowset = conn.query(
+            "SELECT customer_id, COUNT(*) FROM orders WHERE customer_id = ANY($1) GROUP BY customer_id",
+            &[ParameterValue::ArrayInt64(ids.iter().copied().map(Some).collect())],
+        )?;
+        let counts = rowset
+            .rows
+            .iter()
+            .filter_map(|row| Some((i64::decode(&row[0]).ok()?, i64::decode(&row[1]).ok()?)))
+            .collect();
+        return Ok(Some(counts));
+    }
+
+    let mut counts = HashMap::new();
+    for &id in ids {
+        let url = format!("{}/orders/count?customer_id={}", base, id);
+        let count = match spin_sdk::http::run(send::<_, Response>(Request::get(&url).build())) {
+            Ok(resp) if *resp.status() == 200 => {
+                serde_json::from_slice::<OrderCount>(resp.body()).ok()
+            }
+            _ => None,
+        };
+        match count {
+            Some(c) => counts.insert(id, c.count),
+            None => return Ok(None),
+        };
+    }
+    Ok(Some(counts))
+}
+
+/// [`order_counts`] plus the time it took, reported as `orders;dur=`.
+fn timed_order_counts(conn: &Db, ids: &[i64]) -> Result<Option<(HashMap<i64, i64>, f64)>> {
+    let t_orders = Instant::now();
+    let counts = order_counts(conn, ids)?;
+    Ok(counts.map(|c| (c, t_orders.elapsed().as_secs_f64() * 1000.0)))
+}
+
+fn counted<'a>(customer: &'a Customer, counts: &HashMap<i64, i64>) -> CustomerWithOrderCount<'a> {
+    CustomerWithOrderCount {
+        customer,
+        order_count: counts.get(&customer.id).copied().unwrap_or(0),
+    }
+}
+
+fn order_service_unavailable() -> Result<Response> {
+    json_response(502, r#"{"error":"Order service unavailable"}"#)
+}
+
+const DELETE_CUSTOMER_SQL: &str = "DELETE FROM customers WHERE id = $1 RETURNING id, name, email";
+
+/// 204 by default; with `Prefer: return=representation` the deleted row comes back with
+/// 200. `RETURNING` doubles as the existence check, so the row returned is the one removed.
+fn delete_customer(
+    conn: &Db,
+    conn_ms: f64,
+    req: &Request,
+    id_str: &str,
+    fmt: OutputFormat,
+) -> Result<Response> {
     let id: i64 = match id_str.parse() {
         Ok(v) => v,
         Err(_) => return json_response(400, r#"{"error":"Invalid customer ID"}"#),
     };
 
     let t_query = Instant::now();
-    let rowset = conn.query(
-        "SELECT id FROM customers WHERE id = $1",
-        &[ParameterValue::Int64(id)],
-    )?;
+    let rowset = conn.query(DELETE_CUSTOMER_SQL, &[ParameterValue::Int64(id)])?;
+    let query_ms = t_query.elapsed().as_secs_f64() * 1000.0;
 
-    if rowset.rows.is_empty() {
+    let Some(row) = rowset.rows.first() else {
         return json_response(404, r#"{"error":"Customer not found"}"#);
-    }
+    };
+    invalidate_list_cache();
 
-    conn.execute(
-        "DELETE FROM customers WHERE id = $1",
-        &[ParameterValue::Int64(id)],
-    )?;
-    let query_ms = t_query.elapsed().as_secs_f64() * 1000.0;
+    let prefer = req.header(prefer::HEADER).and_then(|v| v.as_str());
+    if prefer::wants_representation(prefer) {
+        let deleted = Customer {
+            id: i64::decode(&row[0]).unwrap_or(0),
+            name: String::decode(&row[1]).unwrap_or_default(),
+            email: String::decode(&row[2]).unwrap_or_default(),
+        };
+        let t_ser = Instant::now();
+        let body = to_json(&deleted, fmt.ids_as_strings)?;
+        let ser_ms = t_ser.elapsed().as_secs_f64() * 1000.0;
+        let mut resp = timed_response(200, &body, conn_ms, query_ms, ser_ms)?;
+        resp.set_header(prefer::APPLIED_HEADER, prefer::RETURN_REPRESENTATION);
+        return Ok(resp);
+    }
 
     Ok(Response::builder()
         .status(204)
         .header(
             "server-timing",
-            format!("conn;dur={:.1}, query;dur={:.1}", conn_ms, query_ms),
+            ServerTiming::new()
+                .dur("conn", conn_ms)
+                .dur("query", query_ms)
+                .to_string(),
         )
         .build())
 }
 
+/// Folds customer `id` into `{"into":N}` and returns the survivor with its new
+/// `order_count`, inside one transaction on this connection. With `order_service_url`
+/// set the orders are moved and counted by order-service instead, so a failure of those
+/// calls or of the delete after them is reported as a 502 naming the `step`; retrying is
+/// safe either way.
+fn merge_customer(
+    conn: &Db,
+    conn_ms: f64,
+    req: &Request,
+    id_str: &str,
+    body: &[u8],
+    fmt: OutputFormat,
+) -> Result<Response> {
+    if let Some((status, body)) = admin_denied(req) {
+        return json_response(status, body);
+    }
+    let id: i64 = match id_str.parse() {
+        Ok(v) => v,
+        Err(_) => return json_response(400, r#"{"error":"Invalid customer ID"}"#),
+    };
+    let into = match json::from_slice::<MergeRequest>(body) {
+        Ok(MergeRequest { into: Some(into) }) if into == id => {
+            return json_response(400, r#"{"error":"cannot merge a customer into itself"}"#)
+        }
+        Ok(MergeRequest { into: Some(into) }) => into,
+        Ok(_) => return json_response(400, r#"{"error":"into is required"}"#),
+        Err(_) => return json_response(400, r#"{"error":"Invalid JSON"}"#),
+    };
+
+    let t_query = Instant::now();
+    conn.execute("BEGIN", &[])?;
+    let abort = |resp: Result<Response>| {
+        let _ = conn.execute("ROLLBACK", &[]);
+        resp
+    };
+    let found: Vec<i64> = match conn.query(
+        "SELECT id FROM customers WHERE id = ANY($1) FOR UPDATE",
+        &[ParameterValue::ArrayInt64(vec![Some(id), Some(into)])],
+    ) {
+        Ok(rowset) => rowset
+            .rows
+            .iter()
+            .filter_map(|row| i64::decode(&row[0]).ok())
+            .collect(),
+        Err(_) => return abort(db_error()),
+    };
+    if !found.contains(&id) {
+        return abort(json_response(404, r#"{"error":"Customer not found"}"#));
+    }
+    if !found.contains(&into) {
+        return abort(json_response(404, r#"{"error":"Merge target not found"}"#));
+    }
+
+    let base = variables::get("order_service_url").unwrap_or_default();
+    let (reassigned, order_count, orders_ms) = if base.is_empty() {
+        let moved = match conn.execute(
+            "UPDATE orders SET customer_id = $2 WHERE customer_id = $1",
+            &[ParameterValue::Int64(id), ParameterValue::Int64(into)],
+        ) {
+            Ok(n) => n as i64,
+            Err(_) => return abort(db_error()),
+        };
+        let count = match conn.query(
+            "SELECT COUNT(*) FROM orders WHERE customer_id = $1",
+            &[ParameterValue::Int64(into)],
+        ) {
+            Ok(rowset) => rowset
+                .rows
+                .first()
+                .and_then(|row| i64::decode(&row[0]).ok())
+                .unwrap_or(0),
+            Err(_) => return abort(db_error()),
+        };
+        (moved, count, None)
+    } else {
+        let t_orders = Instant::now();
+        let Some(reassigned) = reassign_orders(&base, req, id, into) else {
+            return abort(merge_failed("reassign_orders", None));
+        };
+        match order_counts(conn, &[into])? {
+            Some(counts) => (
+                reassigned,
+                counts.get(&into).copied().unwrap_or(0),
+                Some(t_orders.elapsed().as_secs_f64() * 1000.0),
+            ),
+            None => return abort(merge_failed("count_orders", Some(reassigned))),
+        }
+    };
+    // Once order-service has moved the orders, later failures leave the source empty
+    let delete_failed = || match orders_ms {
+        Some(_) => merge_failed("delete_source", Some(reassigned)),
+        None => db_error(),
+    };
+
+    let survivor = conn
+        .execute("DELETE FROM customers WHERE id = $1", &[ParameterValue::Int64(id)])
+        .and_then(|_| {
+            conn.query(
+                "SELECT id, name, email FROM customers WHERE id = $1",
+                &[ParameterValue::Int64(into)],
+            )
+        })
+        .map(|rowset| {
+            rowset.rows.first().map(|row| Customer {
+                id: i64::decode(&row[0]).unwrap_or(0),
+                name: String::decode(&row[1]).unwrap_or_default(),
+                email: String::decode(&row[2]).unwrap_or_default(),
+            })
+        });
+    let survivor = match survivor {
+        Ok(Some(c)) => c,
+        _ => return abort(delete_failed()),
+    };
+    if conn.execute("COMMIT", &[]).is_err() {
+        return abort(delete_failed());
+    }
+    let query_ms = t_query.elapsed().as_secs_f64() * 1000.0;
+    invalidate_list_cache();
+
+    let t_ser = Instant::now();
+    let merged = CustomerWithOrderCount {
+        customer: &survivor,
+        order_count,
+    };
+    let body = read_json(&merged, None, fmt)?;
+    let ser_ms = t_ser.elapsed().as_secs_f64() * 1000.0;
+
+    let resp = timed_response(200, &body, conn_ms, query_ms, ser_ms)?;
+    Ok(match orders_ms {
+        Some(ms) => append_timing(resp, &format!("orders;dur={:.1}", ms)),
+        None => resp,
+    })
+}
+
+#[derive(Deserialize)]
+struct ReassignResult {
+    reassigned: i64,
+}
+
+/// Moves the orders through order-service's `POST /orders/reassign` with the caller's
+/// admin token, which both services share. Returns how many orders were moved.
+fn reassign_orders(base: &str, req: &Request, from: i64, to: i64) -> Option<i64> {
+    let token = req.header("x-admin-token").and_then(|v| v.as_str()).unwrap_or_default();
+    let mut outbound = Request::post(
+        format!("{}/orders/reassign", base),
+        serde_json::json!({ "from_customer_id": from, "to_customer_id": to }).to_string(),
+    );
+    outbound
+        .header("content-type", "application/json")
+        .header("x-admin-token", token);
+    // Passed on unchanged so a URL pointing back at a gateway is still caught there
+    if let Some(hop) = req.header(hops::HEADER).and_then(|v| v.as_str()) {
+        outbound.header(hops::HEADER, hop);
+    }
+    let outbound = outbound.build();
+    match spin_sdk::http::run(send::<_, Response>(outbound)) {
+        Ok(resp) if *resp.status() == 200 => serde_json::from_slice::<ReassignResult>(resp.body())
+            .ok()
+            .map(|r| r.reassigned),
+        _ => None,
+    }
+}
+
+/// 502 naming the merge step that failed; `reassigned` is included once order-service
+/// has already moved the orders.
+fn merge_failed(step: &str, reassigned: Option<i64>) -> Result<Response> {
+    let mut body = serde_json::json!({
+        "error": format!("Merge failed at {}", step),
+        "step": step,
+    });
+    if let Some(n) = reassigned {
+        body["reassigned"] = n.into();
+    }
+    json_response(502, &body.to_string())
+}
+
+/// Checks the `x-admin-token` header against the `admin_token` variable. Admin
+/// endpoints are disabled entirely when the variable is empty.
+fn admin_denied(req: &Request) -> Option<(u16, &'static str)> {
+    let expected = variables::get("admin_token").unwrap_or_default();
+    if expected.is_empty() {
+        return Some((403, r#"{"error":"Admin endpoints disabled"}"#));
+    }
+    match req.header("x-admin-token").and_then(|v| v.as_str()) {
+        Some(token) if token == expected => None,
+        _ => Some((401, r#"{"error":"Invalid admin token"}"#)),
+    }
+}
+
+fn list_cache_ttl() -> Duration {
+    Duration::from_millis(
+        variables::get("list_cache_ms")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0),
+    )
+}
+
+fn cached_list(ttl: Duration) -> Option<String> {
+    let entry = LIST_CACHE.lock().unwrap();
+    entry
+        .as_ref()
+        .filter(|(_, stored_at)| stored_at.elapsed() < ttl)
+        .map(|(body, _)| body.clone())
+}
+
+fn invalidate_list_cache() {
+    *LIST_CACHE.lock().unwrap() = None;
+}
+
+/// Columns selectable via `?fields=`, in response order.
+const CUSTOMER_FIELDS: &[&str] = &["id", "name", "email"];
+
+/// Validates `?fields=` against [`CUSTOMER_FIELDS`]. `None` means the full object.
+fn parse_fields(fields: Option<&str>) -> Result<Option<Vec<&'static str>>, String> {
+    let Some(raw) = fields else {
+        return Ok(None);
+    };
+    let mut selected = Vec::new();
+    for name in raw.split(',').map(str::trim) {
+        match CUSTOMER_FIELDS.iter().find(|f| **f == name) {
+            Some(field) if !selected.contains(field) => selected.push(*field),
+            Some(_) => {}
+            None => return Err(format!("unknown field: {}", name)),
+        }
+    }
+    Ok(Some(selected))
+}
+
+/// What [`sparse_response`] answers with.
+#[derive(Clone, Copy)]
+enum SparseShape {
+    /// One object, or 404 when no row matched.
+    One,
+    /// An array. With `Some(cap)` the query fetched one row past the cap, see [`truncate_to_cap`].
+    Many { cap: Option<i64>, empty_as_204: bool },
+}
+
+/// `?fields=` variant of the customer reads: selects only the requested columns and
+/// serializes each row as an object with just those keys.
+fn sparse_response(
+    conn: &Db,
+    conn_ms: f64,
+    fields: &[&str],
+    filter: &str,
+    params: &[ParameterValue],
+    shape: SparseShape,
+    fmt: OutputFormat,
+) -> Result<Response> {
+    // Column names come from the whitelist only, never from the request
+    let sql = format!("SELECT {} FROM customers{}", fields.join(", "), filter);
+
+    let t_query = Instant::now();
+    let rowset = conn.query(&sql, params)?;
+    let query_ms = t_query.elapsed().as_secs_f64() * 1000.0;
+
+    let t_ser = Instant::now();
+    let mut customers: Vec<Map<String, Value>> = rowset
+        .rows
+        .iter()
+        .map(|row| {
+            fields
+                .iter()
+                .zip(row)
+                .map(|(field, cell)| {
+                    let value = match *field {
+                        "id" => Value::from(i64::decode(cell).unwrap_or(0)),
+                        _ => Value::from(String::decode(cell).unwrap_or_default()),
+                    };
+                    (field.to_string(), value)
+                })
+                .collect()
+        })
+        .collect();
+    let mut empty = false;
+    let (body, truncated) = match shape {
+        SparseShape::One => match customers.pop() {
+            Some(c) => (read_json(&c, None, fmt)?, false),
+            None => return json_response(404, r#"{"error":"Customer not found"}"#),
+        },
+        SparseShape::Many { cap, empty_as_204 } => {
+            let truncated = truncate_to_cap(&mut customers, cap);
+            empty = empty_as_204 && customers.is_empty() && !fmt.envelope;
+            (read_json(&customers, Some(customers.len()), fmt)?, truncated)
+        }
+    };
+    let ser_ms = t_ser.elapsed().as_secs_f64() * 1000.0;
+
+    let mut resp = timed_response(200, &body, conn_ms, query_ms, ser_ms)?;
+    if truncated {
+        resp.set_header("x-truncated", "true");
+    }
+    set_debug_sql(&mut resp, &sql, params.len());
+    Ok(no_content_if_empty(resp, empty))
+}
+
+/// Response shaping requested via `?ids_as_strings=true`, `?envelope=true` and
+/// `?with_order_count=true`.
+#[derive(Clone, Copy)]
+struct OutputFormat {
+    ids_as_strings: bool,
+    envelope: bool,
+    with_order_count: bool,
+}
+
+impl OutputFormat {
+    fn from_query(query: &str) -> Result<Self, String> {
+        Ok(OutputFormat {
+            ids_as_strings: bool_param(query, "ids_as_strings")?,
+            envelope: bool_param(query, "envelope")?,
+            with_order_count: bool_param(query, "with_order_count")?,
+        })
+    }
+}
+
+/// [`to_json`] for read endpoints: additionally applies `envelope`, wrapping lists
+/// (`count` is `Some`) in `{"data":[...],"meta":{"count":N}}` and single resources in
+/// `{"data":{...}}`.
+fn read_json<T: Serialize>(value: &T, count: Option<usize>, fmt: OutputFormat) -> Result<String> {
+    let body = to_json(value, fmt.ids_as_strings)?;
+    if !fmt.envelope {
+        return Ok(body);
+    }
+    Ok(match count {
+        Some(n) => envelope::wrap_list(&body, n),
+        None => envelope::wrap_one(&body),
+    })
+}
+
+/// Fields rendered as JSON strings when `?ids_as_strings=true` is requested.
+const ID_FIELDS: &[&str] = &["id"];
+
+/// Serializes `value`, rendering [`ID_FIELDS`] as JSON strings when `ids_as_strings` is set
+/// so JavaScript clients keep full i64 precision. The default path goes straight through
+/// [`json::to_string`] (serde_json, or simd-json with the `fast-json` feature).
+fn to_json<T: Serialize>(value: &T, ids_as_strings: bool) -> Result<String> {
+    if !ids_as_strings {
+        return Ok(json::to_string(value)?);
+    }
+    let mut value = serde_json::to_value(value)?;
+    stringify_ids(&mut value);
+    Ok(json::to_string(&value)?)
+}
+
+fn stringify_ids(value: &mut Value) {
+    match value {
+        Value::Array(items) => items.iter_mut().for_each(stringify_ids),
+        Value::Object(map) => {
+            for field in ID_FIELDS {
+                if let Some(id @ Value::Number(_)) = map.get_mut(*field) {
+                    *id = Value::String(id.to_string());
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// 500 for a failed `Db::open` or statement; reported as `x-db-healthy: false`, see
+/// [`db_health`].
+fn db_error() -> Result<Response> {
+    db_health::record(false);
+    Ok(Response::builder()
+        .status(500)
+        .header("content-type", "application/json")
+        .body(r#"{"error":"Database error"}"#)
+        .build())
+}
+
+/// Returns the first required variable that is not configured, so the 500 can name it
+/// instead of surfacing an opaque `variables::get` error mid-request.
+fn missing_variable(names: &[&'static str]) -> Option<&'static str> {
+    names.iter().copied().find(|name| variables::get(name).is_err())
+}
+
+/// 500 for an error propagated out of `route`. Returned as-is, Spin would put the raw
+/// error text in the body, and a connection error can quote `db_url` with its password.
+fn internal_error(e: &anyhow::Error) -> Result<Response> {
+    let msg = mask_secrets(&format!("{:#}", e));
+    eprintln!("Handler returned an error: {}", msg);
+    error_response(ApiError::new(500, msg))
+}
+
+/// Spin adapter for the shared [`ApiError`].
+fn error_response(e: ApiError) -> Result<Response> {
+    json_response(e.status, &e.body())
+}
+
 fn json_response(status: u16, body: &str) -> Result<Response> {
     Ok(Response::builder()
         .status(status)
@@ -205,6 +1602,29 @@ fn json_response(status: u16, body: &str) -> Result<Response> {
         .build())
 }
 
+fn cached_response(body: &str) -> Result<Response> {
+    Ok(Response::builder()
+        .status(200)
+        .header("content-type", "application/json")
+        .header("server-timing", "cache;dur=0")
+        .body(body.to_owned())
+        .build())
+}
+
+/// Appends the request body length as `req;size=` to an existing `server-timing` header.
+fn with_request_size(resp: Response, size: usize) -> Response {
+    append_timing(resp, &format!("req;size={}", size))
+}
+
+fn append_timing(mut resp: Response, entry: &str) -> Response {
+    let timing = match resp.header("server-timing").and_then(|v| v.as_str()) {
+        Some(t) => format!("{}, {}", t, entry),
+        None => entry.to_string(),
+    };
+    resp.set_header("server-timing", timing);
+    resp
+}
+
 fn timed_response(
     status: u16,
     body: &str,
@@ -217,10 +1637,9 @@ fn timed_response(
         .header("content-type", "application/json")
         .header(
             "server-timing",
-            format!(
-                "conn;dur={:.1}, query;dur={:.1}, ser;dur={:.1}",
-                conn_ms, query_ms, ser_ms
-            ),
+            ServerTiming::db(conn_ms, query_ms, ser_ms)
+                .size("body", body.len())
+                .to_string(),
         )
         .body(body.to_owned())
         .build())
@@ -1,11 +1,120 @@
 use anyhow::Result;
-use spin_sdk::http::{IntoResponse, Request, Response, send};
+use crm_common::compute;
+use crm_common::deadline;
+use crm_common::hops;
+use crm_common::path::bool_param;
+use crm_common::routes::{self, Route};
+use crm_common::secrets::mask_secrets;
+use crm_common::spin::error_format;
+use crm_common::timing::merge_timings;
+use serde_json::{json, Map, Value};
+use spin_sdk::http::{IntoResponse, Method, Request, Response, send};
 use spin_sdk::http_component;
 use spin_sdk::variables;
-use std::time::Instant;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Headers whose values are never echoed back by `POST /echo`.
+const REDACTED_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "x-admin-token",
+    "x-signature",
+];
+/// Upstream response headers passed through to the client unchanged.
+const FORWARDED_HEADERS: &[&str] = &[
+    "x-db-healthy",
+    "link",
+    "x-truncated",
+    "x-runtime",
+    "x-verify-outcome",
+    "content-range",
+    "x-debug-sql",
+    "content-disposition",
+    "preference-applied",
+];
+/// Pause before resending a request that a draining upstream turned away.
+const DRAIN_RETRY_DELAY: Duration = Duration::from_millis(200);
+/// Variables without which no request can be served; checked before routing.
+const REQUIRED_VARIABLES: &[&str] = &["customer_service_url", "order_service_url"];
+
+/// Requests served by this instance, sent as `x-request-count`. Spin usually creates an
+/// instance per request, so values above 1 only appear when the host reuses one. The
+/// first request an instance handles is also marked `x-warmup: true`.
+static REQUEST_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// Framework name sent as `x-gateway-runtime`, so mixed-fleet benchmark results can be
+/// attributed without tracking ports.
+const RUNTIME: &str = "spin";
+const DEFAULT_SLO_MS: u64 = 100;
 
 #[http_component]
 async fn handle_request(req: Request) -> Result<impl IntoResponse> {
+    let started = Instant::now();
+    let (method, path) = (req.method().to_string(), req.path().to_string());
+    let text_errors = error_format::wants_text(&req);
+    let count = REQUEST_COUNT.fetch_add(1, Ordering::Relaxed) + 1;
+    let resp = match route(req).await {
+        Ok(resp) => resp,
+        Err(e) => internal_error(&e)?,
+    };
+    let mut resp = if text_errors {
+        error_format::render_text(resp)
+    } else {
+        resp
+    };
+    resp.set_header("x-request-count", count.to_string());
+    // Each instance starts cold, so only its first request is a warmup sample
+    if count == 1 {
+        resp.set_header("x-warmup", "true");
+    }
+    flag_slo_violation(&mut resp, started, &method, &path);
+    // `x-runtime` stays the upstream's on proxied paths; local responses get ours
+    resp.set_header("x-gateway-runtime", RUNTIME);
+    if resp.header("x-runtime").is_none() {
+        resp.set_header("x-runtime", RUNTIME);
+    }
+    Ok(resp)
+}
+
+/// `slo_ms` (default 100): responses that took longer, counted from the top of
+/// `handle_request`, get `x-slo-violation: true` and a warning on stderr.
+fn flag_slo_violation(resp: &mut Response, started: Instant, method: &str, path: &str) {
+    let slo_ms: u64 = variables::get("slo_ms")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_SLO_MS);
+    let elapsed_ms = started.elapsed().as_secs_f64() * 1000.0;
+    if elapsed_ms > slo_ms as f64 {
+        eprintln!(
+            "SLO exceeded: {} {} took {:.1}ms (slo_ms={})",
+            method, path, elapsed_ms, slo_ms
+        );
+        resp.set_header("x-slo-violation", "true");
+    }
+}
+
+/// Served by `GET /routez` alongside the upstreams' own lists. `/echo` is listed only
+/// while debug endpoints are enabled.
+const ROUTES: &[Route] = &[
+    Route { path: "/healthz", methods: &["GET"] },
+    Route { path: "/compute", methods: &["GET"] },
+    Route { path: "/echo", methods: &["POST"] },
+    Route { path: "/schemaz", methods: &["GET"] },
+    Route { path: "/routez", methods: &["GET"] },
+];
+
+/// Path prefixes proxied upstream, most specific first, as reported by `GET /routez`.
+const UPSTREAM_PREFIXES: &[(&str, &str)] = &[
+    ("/customers/{id}/orders", "order-service"),
+    ("/customers", "customer-service"),
+    ("/orders", "order-service"),
+    ("/products", "order-service"),
+    ("/admin/orders", "order-service"),
+];
+
+async fn route(req: Request) -> Result<Response> {
     let path = req.path().to_string();
     let full_uri = req.uri().to_string();
 
@@ -13,47 +122,173 @@ async fn handle_request(req: Request) -> Result<impl IntoResponse> {
         return json_response(200, r#"{"status":"ok"}"#);
     }
 
+    if let Some(name) = missing_variable(REQUIRED_VARIABLES) {
+        return json_response(500, &format!(r#"{{"error":"configuration missing: {}"}}"#, name));
+    }
+
     if path == "/compute" {
-        let n = parse_query_param(&full_uri, "n").unwrap_or(1000);
+        let raw_n = query_value(&full_uri, "n");
+        let hex = match query_value(&full_uri, "format") {
+            None | Some("dec") => false,
+            Some("hex") => true,
+            Some(format) => {
+                let extra = [("format", format.into())];
+                let body = compute::error_body("format must be dec or hex", raw_n, &extra);
+                return json_response(400, &body);
+            }
+        };
+        let (raw, mem) = match (bool_param(req.query(), "raw"), bool_param(req.query(), "mem")) {
+            (Ok(raw), Ok(mem)) => (raw, mem),
+            (Err(msg), _) | (_, Err(msg)) => {
+                return json_response(400, &compute::error_body(&msg, raw_n, &[]))
+            }
+        };
+        let n = match compute::parse_n(raw_n) {
+            Ok(n) => n,
+            Err(body) => return json_response(400, &body),
+        };
         let t = Instant::now();
         let result = fibonacci(n);
         let compute_ms = t.elapsed().as_secs_f64() * 1000.0;
-        let body = format!(r#"{{"n":{},"result":"{}","compute_ms":{:.3}}}"#, n, result, compute_ms);
+        let result = if hex {
+            format!("{:x}", result)
+        } else {
+            result.to_string()
+        };
+        // raw=true skips the JSON envelope so benchmarks measure the loop, not serialization
+        let (content_type, body) = if raw {
+            ("text/plain", result)
+        } else {
+            let mut body =
+                format!(r#"{{"n":{},"result":"{}","compute_ms":{:.3}"#, n, result, compute_ms);
+            if mem {
+                body.push_str(&format!(r#","memory_bytes":{}"#, memory_bytes()));
+            }
+            body.push('}');
+            ("application/json", body)
+        };
+        let timing = format!("compute;dur={:.3}, body;size={}", compute_ms, body.len());
+        // HEAD gets GET's headers (timing included) without the body, for cheap probes
+        let body = if *req.method() == Method::Head {
+            String::new()
+        } else {
+            body
+        };
         return Ok(Response::builder()
             .status(200)
-            .header("content-type", "application/json")
-            .header("server-timing", format!("compute;dur={:.3}", compute_ms))
+            .header("content-type", content_type)
+            .header("server-timing", timing)
             .body(body)
             .build());
     }
 
+    if path == "/echo" && *req.method() == Method::Post && debug_endpoints() {
+        return echo(&req);
+    }
+
+    if path == "/schemaz" {
+        return schemaz().await;
+    }
+    if path == "/routez" {
+        return routez().await;
+    }
+
     let customer_url = variables::get("customer_service_url")?;
     let order_url = variables::get("order_service_url")?;
 
-    let upstream_base = if path.starts_with("/customers") {
+    let upstream_base = if is_customer_orders_path(&path) {
+        order_url
+    } else if path.starts_with("/customers") {
         customer_url
-    } else if path.starts_with("/orders") {
+    } else if path.starts_with("/orders")
+        || path.starts_with("/products")
+        || path.starts_with("/admin/orders")
+    {
         order_url
     } else {
         return json_response(404, r#"{"error":"Not found"}"#);
     };
 
-    let upstream_url = format!("{}{}", upstream_base, path);
+    let path_and_query = req.path_and_query().unwrap_or(&path);
+    let upstream_url = format!("{}{}", upstream_base, path_and_query);
     let method = req.method().clone();
+
+    let hop = hops::next(req.header(hops::HEADER).and_then(|v| v.as_str()));
+    if hop > max_hops() {
+        eprintln!(
+            "loop detected: {} {} reached hop {}; an upstream URL points back at a gateway",
+            method, path, hop
+        );
+        return json_response(508, hops::LOOP_DETECTED_BODY);
+    }
     let body = req.body().to_vec();
 
-    let outbound = Request::builder()
-        .method(method)
-        .uri(&upstream_url)
-        .header("content-type", "application/json")
-        .body(body)
-        .build();
+    // Only idempotent requests are retried, and only on transport errors
+    let max_retries = if is_idempotent(&method) {
+        retry_budget(&req)
+    } else {
+        0
+    };
+    let mut retries = 0;
+    let drain_budget = drain_retry_budget();
+    let mut drain_retries = 0;
+    // The body keeps its media type, compressed bodies are passed through for the upstream
+    // to decode, `accept` and `prefer` let it negotiate e.g. CSV or a DELETE body, and the
+    // deadline is forwarded as-is so each hop computes its own remaining budget
+    const PASSTHROUGH: [&str; 6] = [
+        "content-type",
+        "content-encoding",
+        "range",
+        "accept",
+        "prefer",
+        deadline::HEADER,
+    ];
+    let mut passthrough: Vec<(&str, String)> = PASSTHROUGH
+        .into_iter()
+        .filter_map(|name| {
+            req.header(name)
+                .and_then(|v| v.as_str())
+                .map(|v| (name, v.to_string()))
+        })
+        .collect();
+    passthrough.push((hops::HEADER, hop.to_string()));
+    let deadline_raw = req.header(deadline::HEADER).and_then(|v| v.as_str());
 
-    let resp: Response = match send(outbound).await {
+    let t_upstream = Instant::now();
+    let result = loop {
+        // An in-flight `send` can't be cancelled here, so the deadline is checked before
+        // every attempt instead of aborting one midway
+        if deadline::remaining(deadline_raw).is_some_and(|left| left.is_zero()) {
+            let mut resp = json_response(504, deadline::EXCEEDED_BODY)?;
+            resp.set_header("x-retry-count", (retries + drain_retries).to_string());
+            return Ok(resp);
+        }
+        let mut outbound = Request::builder();
+        outbound
+            .method(method.clone())
+            .uri(&upstream_url)
+            .header("content-type", "application/json");
+        for (name, value) in &passthrough {
+            outbound.header(*name, value);
+        }
+        let outbound = outbound.body(body.clone()).build();
+        match send::<_, Response>(outbound).await {
+            Err(_) if retries < max_retries => retries += 1,
+            // Turned away before reaching a handler, so any method may be resent
+            Ok(r) if is_draining(&r) && drain_retries < drain_budget => {
+                drain_retries += 1;
+                std::thread::sleep(DRAIN_RETRY_DELAY);
+            }
+            other => break other,
+        }
+    };
+    let resp = match result {
         Ok(r) => r,
         Err(e) => {
-            let msg = format!(r#"{{"error":"Upstream unavailable: {}"}}"#, e);
-            return json_response(502, &msg);
+            let msg = format!("Upstream unavailable: {}", mask_secrets(&e.to_string()));
+            let mut resp = json_response(502, &json!({ "error": msg }).to_string())?;
+            resp.set_header("x-retry-count", retries.to_string());
+            return Ok(resp);
         }
     };
 
@@ -62,22 +297,169 @@ async fn handle_request(req: Request) -> Result<impl IntoResponse> {
         .headers()
         .find(|(name, _)| name.eq_ignore_ascii_case("server-timing"))
         .and_then(|(_, value)| value.as_str().map(|s| s.to_string()));
+    // Non-JSON bodies (e.g. NDJSON from `/orders/export`) keep their media type
+    let content_type = resp
+        .headers()
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+        .and_then(|(_, value)| value.as_str().map(|s| s.to_string()))
+        .unwrap_or_else(|| "application/json".to_string());
+    // The upstream instance's own counter, renamed so it doesn't clash with ours
+    let upstream_count = resp
+        .headers()
+        .find(|(name, _)| name.eq_ignore_ascii_case("x-request-count"))
+        .and_then(|(_, value)| value.as_str().map(|s| s.to_string()));
+    let forwarded: Vec<(&str, String)> = FORWARDED_HEADERS
+        .iter()
+        .filter_map(|&header| {
+            resp.headers()
+                .find(|(name, _)| name.eq_ignore_ascii_case(header))
+                .and_then(|(_, value)| value.as_str().map(|s| (header, s.to_string())))
+        })
+        .collect();
     let body = resp.into_body();
-    match timing {
-        Some(t) => Ok(Response::builder()
-            .status(status)
-            .header("content-type", "application/json")
-            .header("server-timing", &t)
-            .body(body)
-            .build()),
-        None => Ok(Response::builder()
-            .status(status)
-            .header("content-type", "application/json")
-            .body(body)
-            .build()),
+    let gateway_ms = t_upstream.elapsed().as_secs_f64() * 1000.0;
+    let timing = merge_timings(
+        timing.as_deref().unwrap_or_default(),
+        &format!("gateway;dur={:.1}", gateway_ms),
+    );
+    let mut builder = Response::builder();
+    builder
+        .status(status)
+        .header("content-type", content_type)
+        .header("server-timing", timing)
+        .header("x-retry-count", (retries + drain_retries).to_string());
+    for (name, value) in forwarded {
+        builder.header(name, value);
+    }
+    if let Some(count) = upstream_count {
+        builder.header("x-upstream-request-count", count);
+    }
+    Ok(builder.body(body).build())
+}
+
+/// Size of this instance's linear memory (64 KiB pages), for comparison with the
+/// container's RSS. Linear memory only grows, so this is the instance's high-water mark.
+fn memory_bytes() -> u64 {
+    core::arch::wasm32::memory_size(0) as u64 * 64 * 1024
+}
+
+/// Aggregates the upstreams' `/schemaz`. An unreachable upstream reports `null` and
+/// counts as a mismatch.
+async fn schemaz() -> Result<Response> {
+    let customer = fetch_schemaz(&variables::get("customer_service_url")?).await;
+    let order = fetch_schemaz(&variables::get("order_service_url")?).await;
+    let mismatch = [&customer, &order]
+        .iter()
+        .any(|v| v.get("mismatch") != Some(&Value::Bool(false)));
+    let body = json!({
+        "customer-service": customer,
+        "order-service": order,
+        "mismatch": mismatch,
+    });
+    json_response(200, &body.to_string())
+}
+
+/// Own routes, proxied prefixes and each upstream's `/routez`; an unreachable upstream
+/// reports `null`.
+async fn routez() -> Result<Response> {
+    let customer = fetch_routez(&variables::get("customer_service_url")?).await;
+    let order = fetch_routez(&variables::get("order_service_url")?).await;
+    let debug = debug_endpoints();
+    let own = ROUTES.iter().filter(|r| r.path != "/echo" || debug);
+    let body = routes::gateway_body(
+        &routes::routez_body(own),
+        UPSTREAM_PREFIXES,
+        customer.as_deref(),
+        order.as_deref(),
+    );
+    json_response(200, &body)
+}
+
+async fn fetch_routez(base: &str) -> Option<String> {
+    let outbound = Request::get(format!("{}/routez", base)).build();
+    let resp: Response = send(outbound).await.ok()?;
+    routes::upstream_list(resp.body())
+}
+
+async fn fetch_schemaz(base: &str) -> Value {
+    let outbound = Request::get(format!("{}/schemaz", base)).build();
+    match send::<_, Response>(outbound).await {
+        Ok(resp) => serde_json::from_slice(resp.body()).unwrap_or(Value::Null),
+        Err(_) => Value::Null,
     }
 }
 
+/// Debug aid: returns what the gateway received without proxying. Only reachable
+/// when the `debug_endpoints` variable is `true`.
+fn echo(req: &Request) -> Result<Response> {
+    let mut headers = Map::new();
+    for (name, value) in req.headers() {
+        let value = if REDACTED_HEADERS.iter().any(|h| name.eq_ignore_ascii_case(h)) {
+            "[redacted]".to_string()
+        } else {
+            String::from_utf8_lossy(value.as_bytes()).into_owned()
+        };
+        headers.insert(name.to_ascii_lowercase(), Value::String(value));
+    }
+    let body = json!({
+        "method": req.method().to_string(),
+        "headers": headers,
+        "body": String::from_utf8_lossy(req.body()),
+    });
+    json_response(200, &body.to_string())
+}
+
+fn debug_endpoints() -> bool {
+    variables::get("debug_endpoints")
+        .map(|v| v == "true")
+        .unwrap_or(false)
+}
+
+/// Retries allowed for this request: the client's `x-max-retries`, capped by the
+/// `upstream_max_retries` variable.
+fn retry_budget(req: &Request) -> u32 {
+    let server_max: u32 = variables::get("upstream_max_retries")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    req.header("x-max-retries")
+        .and_then(|v| v.as_str())
+        .and_then(|v| v.parse::<u32>().ok())
+        .map_or(server_max, |requested| requested.min(server_max))
+}
+
+/// `max_hops`: forwards allowed before answering 508, see [`hops`].
+fn max_hops() -> u32 {
+    variables::get("max_hops")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(hops::DEFAULT_MAX)
+}
+
+fn drain_retry_budget() -> u32 {
+    variables::get("upstream_drain_retries")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(2)
+}
+
+/// Whether the upstream rejected the request because it is shutting down.
+fn is_draining(resp: &Response) -> bool {
+    *resp.status() == 503
+        && resp
+            .headers()
+            .any(|(name, value)| {
+                name.eq_ignore_ascii_case("x-draining") && value.as_str() == Some("true")
+            })
+}
+
+fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        method,
+        Method::Get | Method::Head | Method::Put | Method::Delete | Method::Options
+    )
+}
+
 fn fibonacci(n: u64) -> u64 {
     if n <= 1 {
         return n;
@@ -91,17 +473,36 @@ fn fibonacci(n: u64) -> u64 {
     b
 }
 
-fn parse_query_param(uri: &str, key: &str) -> Option<u64> {
+fn query_value<'a>(uri: &'a str, key: &str) -> Option<&'a str> {
     let query = uri.split('?').nth(1)?;
     for pair in query.split('&') {
         let mut kv = pair.splitn(2, '=');
         if kv.next()? == key {
-            return kv.next()?.parse().ok();
+            return kv.next();
         }
     }
     None
 }
 
+/// `/customers/{id}/orders` is owned by the order-service despite its prefix.
+fn is_customer_orders_path(path: &str) -> bool {
+    path.starts_with("/customers/") && path.trim_end_matches('/').ends_with("/orders")
+}
+
+/// Returns the first required variable that is not configured, so the 500 can name it
+/// instead of surfacing an opaque `variables::get` error mid-request.
+fn missing_variable(names: &[&'static str]) -> Option<&'static str> {
+    names.iter().copied().find(|name| variables::get(name).is_err())
+}
+
+/// 500 for an error propagated out of `route`. Returned as-is, Spin would put the raw
+/// error text in the body, and a connection error can quote an upstream URL with its password.
+fn internal_error(e: &anyhow::Error) -> Result<Response> {
+    let msg = mask_secrets(&format!("{:#}", e));
+    eprintln!("Handler returned an error: {}", msg);
+    json_response(500, &json!({ "error": msg }).to_string())
+}
+
 fn json_response(status: u16, body: &str) -> Result<Response> {
     Ok(Response::builder()
         .status(status)
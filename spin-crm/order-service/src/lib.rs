@@ -1,19 +1,57 @@
 use anyhow::Result;
+use crm_common::deadline;
+use crm_common::db_schema;
+use crm_common::db_url;
+use crm_common::debug_sql;
+use crm_common::envelope;
+use crm_common::hops;
+use crm_common::json;
+use crm_common::paging::{content_range, link_header, parse_items_range, parse_offset};
+use crm_common::path::{bool_param, query_param, resource_id};
+use crm_common::routes::{self, Route};
+use crm_common::schema;
+use crm_common::secrets::mask_secrets;
+use crm_common::spin::body;
+use crm_common::spin::db::Db;
+use crm_common::spin::db_health;
+use crm_common::spin::error_format;
+use crm_common::{ApiError, ServerTiming};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use spin_sdk::http::{IntoResponse, Method, Request, Response, send};
 use spin_sdk::http_component;
-use spin_sdk::pg4::{Connection, Decode, ParameterValue};
+use spin_sdk::pg4::{Decode, ParameterValue, PgError, QueryError};
 use spin_sdk::variables;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Instant;
 
+mod order_id;
+
+use order_id::{OrderId, OrderIds};
+
 #[derive(Serialize, Deserialize)]
 struct Order {
-    id: i64,
+    id: OrderId,
     customer_id: i64,
     product: String,
     quantity: i64,
 }
 
+/// One `order_audit` row as returned by `GET /orders/{id}/history`.
+#[derive(Serialize)]
+struct AuditEntry {
+    changed_at: String,
+    change: String,
+}
+
+/// Row of the optional `products` catalog used when `validate_product` is `true`.
+#[derive(Serialize)]
+struct Product {
+    name: String,
+}
+
 #[derive(Deserialize)]
 struct CreateOrderRequest {
     customer_id: Option<i64>,
@@ -21,81 +59,787 @@ struct CreateOrderRequest {
     quantity: Option<i64>,
 }
 
+/// Keys accepted in create bodies when `strict_json` is `true`.
+const CREATE_ORDER_FIELDS: &[&str] = &["customer_id", "product", "quantity"];
+
+/// Body fields that must be JSON integers (or `null`), checked before deserializing.
+const INTEGER_FIELDS: &[&str] = &["customer_id", "quantity"];
+
+/// Body of the customer-service's `POST /customers/exists`.
+#[derive(Deserialize)]
+struct ExistsResponse {
+    missing: Vec<i64>,
+}
+
+#[derive(Deserialize)]
+struct IncrementRequest {
+    by: Option<i64>,
+}
+
+/// Body of `POST /orders/delete`; entries are validated by [`parse_delete_ids`].
+#[derive(Deserialize)]
+struct DeleteOrdersRequest {
+    ids: Option<Vec<Value>>,
+}
+
+/// Body of `POST /orders/reassign`, sent by customer-service when merging customers.
+#[derive(Deserialize)]
+struct ReassignRequest {
+    from_customer_id: Option<i64>,
+    to_customer_id: Option<i64>,
+}
+
+/// Response of `POST /admin/orders/generate`.
+#[derive(Serialize)]
+struct GenerateReport {
+    generated: i64,
+    customer_id: i64,
+    total_ms: f64,
+    inserts_per_sec: f64,
+    batches: Vec<BatchTiming>,
+}
+
+/// One committed batch: rows inserted and the time from `BEGIN` to `COMMIT`.
+#[derive(Serialize)]
+struct BatchTiming {
+    rows: i64,
+    ms: f64,
+}
+
+/// Optional `GET /orders` filters, combined with AND.
+struct OrderFilter {
+    min_quantity: Option<i64>,
+    max_quantity: Option<i64>,
+}
+
+impl OrderFilter {
+    fn validate(&self) -> Result<(), &'static str> {
+        if self.min_quantity.is_some_and(|q| q < 0) || self.max_quantity.is_some_and(|q| q < 0) {
+            return Err("quantity bounds must be non-negative");
+        }
+        if let (Some(min), Some(max)) = (self.min_quantity, self.max_quantity) {
+            if min > max {
+                return Err("min_quantity must not exceed max_quantity");
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the WHERE clause (empty when unfiltered) and its positional parameters.
+    fn where_clause(&self) -> (String, Vec<ParameterValue>) {
+        let mut conditions = Vec::new();
+        let mut params = Vec::new();
+        if let Some(min) = self.min_quantity {
+            params.push(ParameterValue::Int64(min));
+            conditions.push(format!("quantity >= ${}", params.len()));
+        }
+        if let Some(max) = self.max_quantity {
+            params.push(ParameterValue::Int64(max));
+            conditions.push(format!("quantity <= ${}", params.len()));
+        }
+        if conditions.is_empty() {
+            (String::new(), params)
+        } else {
+            (format!(" WHERE {}", conditions.join(" AND ")), params)
+        }
+    }
+}
+
+const DEFAULT_MAX_PAGE_SIZE: i64 = 500;
+const DEFAULT_MAX_ROWS: i64 = 10_000;
+const DEFAULT_MAX_PRODUCT_LEN: usize = 255;
+/// A `created` audit entry for the same customer and product within the last $3 ms. The
+/// orders table has no timestamp, so creation time is taken from `order_audit`.
+const RECENT_DUPLICATE_SQL: &str = "SELECT 1 FROM orders o \
+     JOIN order_audit a ON a.order_id = o.id AND a.change = 'created' \
+     WHERE o.customer_id = $1 AND o.product = $2 \
+     AND a.changed_at > now() - $3::bigint * interval '1 millisecond' LIMIT 1";
+const CREATE_ORDER_SQL: &str = "WITH created AS ( \
+         INSERT INTO orders (customer_id, product, quantity) VALUES ($1, $2, $3) \
+         RETURNING id, customer_id, product, quantity \
+     ), audit AS ( \
+         INSERT INTO order_audit (order_id, change) SELECT id, 'created' FROM created \
+     ) \
+     SELECT id, customer_id, product, quantity FROM created";
+const GET_ORDER_SQL: &str = "SELECT id, customer_id, product, quantity FROM orders WHERE id = $1";
+const ORDERS_BY_CUSTOMER_SQL: &str = "SELECT id, customer_id, product, quantity FROM orders \
+     WHERE customer_id = ANY($1) ORDER BY id";
+/// Audit trail of one order; `changed_at` is rendered as UTC RFC 3339 by the database.
+const ORDER_HISTORY_SQL: &str = "SELECT \
+     to_char(changed_at AT TIME ZONE 'UTC', 'YYYY-MM-DD\"T\"HH24:MI:SS.MS\"Z\"') AS changed_at, \
+     change FROM order_audit WHERE order_id = $1 ORDER BY id";
+/// Customer ids sent per `POST /customers/exists` call during a reverify audit.
+const EXISTS_BATCH_SIZE: usize = 1000;
+/// Upper bound on ids accepted by `POST /orders/delete`.
+const MAX_DELETE_IDS: usize = 1000;
+/// Upper bound on `?count=` for `POST /admin/orders/generate`.
+const MAX_GENERATE_COUNT: i64 = 100_000;
+/// Orders inserted per transaction by `POST /admin/orders/generate`.
+const GENERATE_BATCH_SIZE: i64 = 1000;
+/// One generated batch: random products from the catalog (`Generated` when it is empty),
+/// quantities 1-10, each order audited as `created`.
+const GENERATE_ORDERS_SQL: &str = "WITH catalog AS ( \
+         SELECT COALESCE(array_agg(name), ARRAY['Generated']) AS names FROM products \
+     ), created AS ( \
+         INSERT INTO orders (customer_id, product, quantity) \
+         SELECT $1, names[1 + floor(random() * cardinality(names))::int], 1 + floor(random() * 10)::bigint \
+         FROM catalog, generate_series(1, $2) \
+         RETURNING id \
+     ), audit AS ( \
+         INSERT INTO order_audit (order_id, change) SELECT id, 'created' FROM created \
+     ) \
+     SELECT COUNT(*) FROM created";
+/// Upper bound on ids accepted by `GET /orders?customer_ids=`.
+const MAX_CUSTOMER_IDS: usize = 100;
+/// Variables without which no request can be served; checked before routing.
+const REQUIRED_VARIABLES: &[&str] = &["db_url", "customer_service_url"];
+
+/// Requests served by this instance, sent as `x-request-count`. Spin usually creates an
+/// instance per request, so values above 1 only appear when the host reuses one. The
+/// first request an instance handles is also marked `x-warmup: true`.
+static REQUEST_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// Framework name sent as `x-runtime`, so mixed-fleet benchmark results can be
+/// attributed without tracking ports.
+const RUNTIME: &str = "spin";
+const DEFAULT_SLO_MS: u64 = 100;
+
 #[http_component]
 async fn handle_request(req: Request) -> Result<impl IntoResponse> {
+    let started = Instant::now();
+    let (method, path) = (req.method().to_string(), req.path().to_string());
+    let text_errors = error_format::wants_text(&req);
+    let count = REQUEST_COUNT.fetch_add(1, Ordering::Relaxed) + 1;
+    let resp = match route(req).await {
+        Ok(resp) => resp,
+        Err(e) => {
+            if e.is::<PgError>() {
+                db_health::record(false);
+            }
+            internal_error(&e)?
+        }
+    };
+    let mut resp = if text_errors {
+        error_format::render_text(resp)
+    } else {
+        resp
+    };
+    resp.set_header("x-request-count", count.to_string());
+    // Each instance starts cold, so only its first request is a warmup sample
+    if count == 1 {
+        resp.set_header("x-warmup", "true");
+    }
+    db_health::stamp(&mut resp);
+    flag_slo_violation(&mut resp, started, &method, &path);
+    resp.set_header("x-runtime", RUNTIME);
+    Ok(resp)
+}
+
+/// `slo_ms` (default 100): responses that took longer, counted from the top of
+/// `handle_request`, get `x-slo-violation: true` and a warning on stderr.
+fn flag_slo_violation(resp: &mut Response, started: Instant, method: &str, path: &str) {
+    let slo_ms: u64 = variables::get("slo_ms")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_SLO_MS);
+    let elapsed_ms = started.elapsed().as_secs_f64() * 1000.0;
+    if elapsed_ms > slo_ms as f64 {
+        eprintln!(
+            "SLO exceeded: {} {} took {:.1}ms (slo_ms={})",
+            method, path, elapsed_ms, slo_ms
+        );
+        resp.set_header("x-slo-violation", "true");
+    }
+}
+
+/// Served by `GET /routez`; keep in step with the dispatch in [`route`].
+const ROUTES: &[Route] = &[
+    Route { path: "/healthz", methods: &["GET"] },
+    Route { path: "/schemaz", methods: &["GET"] },
+    Route { path: "/readyz", methods: &["GET"] },
+    Route { path: "/routez", methods: &["GET"] },
+    Route { path: "/orders", methods: &["GET", "POST"] },
+    Route { path: "/orders/count", methods: &["GET"] },
+    Route { path: "/orders/export", methods: &["GET"] },
+    Route { path: "/orders/reassign", methods: &["POST"] },
+    Route { path: "/orders/delete", methods: &["POST"] },
+    Route { path: "/orders/{id}", methods: &["GET"] },
+    Route { path: "/orders/{id}/increment", methods: &["POST"] },
+    Route { path: "/orders/{id}/history", methods: &["GET"] },
+    Route { path: "/products", methods: &["GET"] },
+    Route { path: "/customers/{id}/orders", methods: &["DELETE"] },
+    Route { path: "/admin/orders/reverify", methods: &["POST"] },
+    Route { path: "/admin/orders/generate", methods: &["POST"] },
+];
+
+async fn route(req: Request) -> Result<Response> {
     let path = req.path().to_string();
     let method = req.method();
 
     if path == "/healthz" {
         return json_response(200, r#"{"status":"ok"}"#);
     }
+    if path == "/routez" {
+        return json_response(200, &routes::routez_body(ROUTES));
+    }
+
+    if let Some(name) = missing_variable(REQUIRED_VARIABLES) {
+        return json_response(500, &format!(r#"{{"error":"configuration missing: {}"}}"#, name));
+    }
+    if let Err(msg) = db_url::check_scheme(&variables::get("db_url")?) {
+        eprintln!("db_url {}", msg);
+        return error_response(ApiError::new(500, format!("configuration invalid: db_url {}", msg)));
+    }
+    let search_path = match db_schema::search_path(&variables::get("db_schema")?) {
+        Ok(sql) => sql,
+        Err(msg) => {
+            eprintln!("db_schema {}", msg);
+            let message = format!("configuration invalid: db_schema {}", msg);
+            return error_response(ApiError::new(500, message));
+        }
+    };
+    if path == "/readyz" {
+        return readyz().await;
+    }
+
+    let body = match body::decoded_body(&req) {
+        Ok(b) => b,
+        Err((status, msg)) => return json_response(status, msg),
+    };
 
     let t_conn = Instant::now();
-    let conn = Connection::open(&variables::get("db_url")?)?;
+    let conn = match Db::open(&variables::get("db_url")?, search_path) {
+        Ok(c) => c,
+        Err(_) => return db_error(),
+    };
     let conn_ms = t_conn.elapsed().as_secs_f64() * 1000.0;
 
-    let (_, resource_id) = parse_path(&path);
+    if path == "/schemaz" {
+        return schemaz(&conn, conn_ms);
+    }
+
+    if let Some(customer_id) = parse_customer_orders_path(&path) {
+        return match method {
+            Method::Delete => delete_customer_orders(&conn, conn_ms, &req, customer_id),
+            _ => json_response(405, r#"{"error":"Method not allowed"}"#),
+        };
+    }
+
+    let fmt = match OutputFormat::from_query(req.query()) {
+        Ok(f) => f,
+        Err(msg) => return error_response(ApiError::bad_request(msg)),
+    };
+
+    if path.trim_end_matches('/') == "/admin/orders/reverify" {
+        return match method {
+            Method::Post => reverify_orders(&conn, conn_ms, &req, fmt.ids_as_strings).await,
+            _ => json_response(405, r#"{"error":"Method not allowed"}"#),
+        };
+    }
+
+    if path.trim_end_matches('/') == "/admin/orders/generate" {
+        return match method {
+            Method::Post => generate_orders(&conn, conn_ms, &req).await,
+            _ => json_response(405, r#"{"error":"Method not allowed"}"#),
+        };
+    }
+
+    if let Some(order_id) = parse_increment_path(&path) {
+        return match method {
+            Method::Post => increment_order(&conn, conn_ms, order_id, &body, fmt.ids_as_strings),
+            _ => json_response(405, r#"{"error":"Method not allowed"}"#),
+        };
+    }
+
+    if let Some(order_id) = parse_history_path(&path) {
+        return match method {
+            Method::Get => order_history(&conn, conn_ms, order_id),
+            _ => json_response(405, r#"{"error":"Method not allowed"}"#),
+        };
+    }
+
+    if path == "/products" {
+        return match method {
+            Method::Get => list_products(&conn, conn_ms, fmt),
+            _ => json_response(405, r#"{"error":"Method not allowed"}"#),
+        };
+    }
+
+    let resource_id = match resource_id(&path) {
+        Ok(id) => id,
+        Err(msg) => return error_response(ApiError::bad_request(msg)),
+    };
 
     match (method, resource_id) {
-        (&Method::Get, None) => list_orders(&conn, conn_ms),
-        (&Method::Post, None) => create_order(&conn, conn_ms, req.body()).await,
-        (&Method::Get, Some(id)) => get_order(&conn, conn_ms, id),
+        (&Method::Get, None) => {
+            if let Some(raw) = query_param(req.query(), "customer_ids") {
+                return match parse_customer_ids(raw, req.query()) {
+                    Ok(ids) => list_orders_by_customer(&conn, conn_ms, &ids, fmt),
+                    Err(msg) => error_response(ApiError::bad_request(msg)),
+                };
+            }
+            let filter = match parse_order_filter(req.query()) {
+                Ok(f) => f,
+                Err(msg) => return error_response(ApiError::bad_request(msg)),
+            };
+            let range = req.header("range").and_then(|v| v.as_str());
+            let page = match parse_page(req.query(), range) {
+                Ok(p) => p,
+                Err(msg) => return error_response(ApiError::bad_request(msg)),
+            };
+            // Counted first so an unsatisfiable `Range` is answered before the list query
+            let paging = match page {
+                Some(page) => {
+                    let (where_clause, params) = filter.where_clause();
+                    let count_sql = format!("SELECT COUNT(*) FROM orders{}", where_clause);
+                    match page_headers(&conn, &count_sql, &params, &req, page)? {
+                        Ok(h) => Some(h),
+                        Err(resp) => return Ok(resp),
+                    }
+                }
+                None => None,
+            };
+            let resp = list_orders(&conn, conn_ms, &filter, page, fmt)?;
+            Ok(match paging {
+                Some(h) => with_page_headers(resp, h),
+                None => resp,
+            })
+        }
+        (&Method::Post, None) => {
+            let quick = match bool_param(req.query(), "quick") {
+                Ok(q) => q,
+                Err(msg) => return error_response(ApiError::bad_request(msg)),
+            };
+            create_order(&conn, conn_ms, &body, fmt.ids_as_strings, quick, &req).await
+        }
+        (&Method::Post, Some("reassign")) => reassign_orders(&conn, conn_ms, &req, &body).await,
+        (&Method::Post, Some("delete")) => delete_orders(&conn, conn_ms, &req, &body),
+        (&Method::Get, Some("count")) => count_orders(&conn, conn_ms, req.query()),
+        (&Method::Get, Some("export")) => export_orders(&conn, conn_ms, req.query()),
+        (&Method::Get, Some(id)) => get_order(&conn, conn_ms, id, fmt),
         _ => json_response(405, r#"{"error":"Method not allowed"}"#),
     }
 }
 
-fn parse_path(uri: &str) -> (&str, Option<&str>) {
-    let path = uri.split('?').next().unwrap_or(uri);
-    let path = path.trim_end_matches('/');
-    let parts: Vec<&str> = path.split('/').collect();
-    if parts.len() >= 3 && !parts[2].is_empty() {
-        ("/orders", Some(parts[2]))
-    } else {
-        ("/orders", None)
+/// Schema version check; a missing `schema_version` table reports `current: null`.
+fn schemaz(conn: &Db, conn_ms: f64) -> Result<Response> {
+    let t_query = Instant::now();
+    let current = match conn.query(schema::VERSION_QUERY, &[]) {
+        Ok(rowset) => rowset.rows.first().and_then(|row| i64::decode(&row[0]).ok()),
+        Err(PgError::QueryFailed(QueryError::DbError(db))) if db.code == schema::UNDEFINED_TABLE => {
+            None
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let query_ms = t_query.elapsed().as_secs_f64() * 1000.0;
+
+    timed_response(200, &schema::schemaz_body(current), conn_ms, query_ms, 0.0)
+}
+
+/// Matches `/customers/{id}/orders` and returns the raw customer id segment.
+fn parse_customer_orders_path(path: &str) -> Option<&str> {
+    path.trim_end_matches('/')
+        .strip_prefix("/customers/")?
+        .strip_suffix("/orders")
+}
+
+/// Matches `/orders/{id}/increment` and returns the raw order id segment.
+fn parse_increment_path(path: &str) -> Option<&str> {
+    path.trim_end_matches('/')
+        .strip_prefix("/orders/")?
+        .strip_suffix("/increment")
+}
+
+/// Matches `/orders/{id}/history` and returns the raw order id segment.
+fn parse_history_path(path: &str) -> Option<&str> {
+    path.trim_end_matches('/')
+        .strip_prefix("/orders/")?
+        .strip_suffix("/history")
+}
+
+/// How a `limit` above `max_page_size` is handled: clamped by default, 400 when `strict`.
+struct PageLimits {
+    max_page_size: i64,
+    strict: bool,
+}
+
+fn page_limits() -> PageLimits {
+    PageLimits {
+        max_page_size: variables::get("max_page_size")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|&n| n > 0)
+            .unwrap_or(DEFAULT_MAX_PAGE_SIZE),
+        strict: variables::get("strict_limits")
+            .map(|v| v == "true")
+            .unwrap_or(false),
     }
 }
 
-async fn verify_customer_exists(customer_id: i64) -> Result<bool> {
-    let customer_url = variables::get("customer_service_url")?;
+/// Longest accepted `product`, counted in characters as a `VARCHAR(n)` column would.
+fn max_product_len() -> usize {
+    variables::get("max_product_len")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(DEFAULT_MAX_PRODUCT_LEN)
+}
+
+/// `dedup_orders_window_ms`: a repeat (customer, product) order inside the window gets 409.
+/// 0 or unparsable disables the check.
+fn dedup_window_ms() -> i64 {
+    variables::get("dedup_orders_window_ms")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(0)
+}
+
+/// `max_orders_per_customer`: creates beyond this many orders for one customer get 409.
+/// 0 or unparsable means unlimited.
+fn max_orders_per_customer() -> i64 {
+    variables::get("max_orders_per_customer")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(0)
+}
+
+/// Row cap for unpaged lists; queries fetch one row past it to detect truncation.
+fn max_rows() -> i64 {
+    variables::get("max_rows")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(DEFAULT_MAX_ROWS)
+}
+
+/// Drops the probe row fetched past `cap` (queries ask for `cap + 1`). Returns whether
+/// the cap was hit, i.e. the table holds more rows than were returned.
+fn truncate_to_cap<T>(rows: &mut Vec<T>, cap: Option<i64>) -> bool {
+    match cap {
+        Some(cap) if rows.len() as i64 > cap => {
+            rows.truncate(cap as usize);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Parses `Range: items=<first>-<last>` or, without one, optional `?limit=&offset=` into
+/// `(limit, offset)`, or `None` when the request is unpaginated. Omitting both returns
+/// every row.
+fn parse_page(query: &str, range: Option<&str>) -> Result<Option<(i64, i64)>, String> {
+    let limits = page_limits();
+    let (limit, offset) = match range {
+        Some(raw) => parse_items_range(raw)?,
+        None => {
+            let limit = query_param(query, "limit");
+            let offset = query_param(query, "offset");
+            if limit.is_none() && offset.is_none() {
+                return Ok(None);
+            }
+            let offset = match offset {
+                Some(v) => parse_offset(v)?,
+                None => 0,
+            };
+            let limit = match limit {
+                Some(v) => v.parse::<i64>().map_err(|_| "limit must be an integer")?,
+                None => limits.max_page_size,
+            };
+            (limit, offset)
+        }
+    };
+    if limit < 1 {
+        return Err("limit must be positive".to_string());
+    }
+    if limit > limits.max_page_size {
+        if limits.strict {
+            return Err(format!("limit exceeds maximum of {}", limits.max_page_size));
+        }
+        return Ok(Some((limits.max_page_size, offset)));
+    }
+    Ok(Some((limit, offset)))
+}
+
+fn parse_order_filter(query: &str) -> Result<OrderFilter, &'static str> {
+    let min_quantity = match query_param(query, "min_quantity") {
+        Some(v) => Some(v.parse().map_err(|_| "min_quantity must be an integer")?),
+        None => None,
+    };
+    let max_quantity = match query_param(query, "max_quantity") {
+        Some(v) => Some(v.parse().map_err(|_| "max_quantity must be an integer")?),
+        None => None,
+    };
+    let filter = OrderFilter {
+        min_quantity,
+        max_quantity,
+    };
+    filter.validate()?;
+    Ok(filter)
+}
+
+/// Parses `?customer_ids=` as comma-separated integers, at most [`MAX_CUSTOMER_IDS`].
+/// The grouped response isn't paged or quantity-filtered, so those parameters are rejected.
+fn parse_customer_ids(raw: &str, query: &str) -> Result<Vec<i64>, &'static str> {
+    let combined = ["min_quantity", "max_quantity", "limit", "offset"]
+        .iter()
+        .any(|key| query_param(query, key).is_some());
+    if combined {
+        return Err("customer_ids cannot be combined with quantity filters or paging");
+    }
+    let ids = raw
+        .split(',')
+        .map(|id| id.trim().parse::<i64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| "customer_ids must be comma-separated integers")?;
+    if ids.len() > MAX_CUSTOMER_IDS {
+        return Err("too many customer_ids (max 100)");
+    }
+    Ok(ids)
+}
+
+/// A customer check that could not be answered either way.
+enum VerifyError {
+    ServiceUnavailable,
+    Db,
+}
+
+/// Whether the customer exists: `GET /customers/{id}` on the customer-service, or with
+/// `verify_via = "db"` a `SELECT` on the shared database. `None` when the caller's
+/// `X-Request-Deadline` has already passed; the header is forwarded otherwise, though a
+/// `send` already in flight can't be cut short. `X-Hop-Count` is passed on unchanged.
+async fn verify_customer_exists(
+    conn: &Db,
+    customer_id: i64,
+    req: &Request,
+) -> std::result::Result<Option<bool>, VerifyError> {
+    let deadline = req.header(deadline::HEADER).and_then(|v| v.as_str());
+    if deadline::remaining(deadline).is_some_and(|left| left.is_zero()) {
+        return Ok(None);
+    }
+    if verify_via_db() {
+        return conn
+            .query(
+                "SELECT 1 FROM customers WHERE id = $1",
+                &[ParameterValue::Int64(customer_id)],
+            )
+            .map(|rowset| Some(!rowset.rows.is_empty()))
+            .map_err(|_| VerifyError::Db);
+    }
+    let customer_url =
+        variables::get("customer_service_url").map_err(|_| VerifyError::ServiceUnavailable)?;
     let url = format!("{}/customers/{}", customer_url, customer_id);
 
-    let outbound = Request::get(&url).build();
-    let resp: Response = send(outbound).await?;
-    Ok(*resp.status() == 200)
+    let mut outbound = Request::builder();
+    outbound.method(Method::Get).uri(&url);
+    let hop_count = req.header(hops::HEADER).and_then(|v| v.as_str());
+    for (name, raw) in [(deadline::HEADER, deadline), (hops::HEADER, hop_count)] {
+        if let Some(raw) = raw {
+            outbound.header(name, raw);
+        }
+    }
+    let resp: Response = send(outbound.build())
+        .await
+        .map_err(|_| VerifyError::ServiceUnavailable)?;
+    Ok(Some(*resp.status() == 200))
+}
+
+/// `verify_via = "db"`: customers live in the same database, so skip the HTTP hop.
+fn verify_via_db() -> bool {
+    variables::get("verify_via").map(|v| v == "db").unwrap_or(false)
+}
+
+/// Readiness including the customer-service dependency: 503 when its `/healthz`
+/// is unreachable, since orders cannot be created without verifying customers.
+async fn readyz() -> Result<Response> {
+    let customer_url = variables::get("customer_service_url")?;
+    let outbound = Request::get(format!("{}/healthz", customer_url)).build();
+    match send::<_, Response>(outbound).await {
+        Ok(resp) if *resp.status() == 200 => {
+            json_response(200, r#"{"status":"ok","customer_service":"ok"}"#)
+        }
+        _ => json_response(503, r#"{"status":"degraded","customer_service":"unreachable"}"#),
+    }
+}
+
+/// Paging headers for a list: the RFC 8288 `Link` header and, for a `Range` request,
+/// `Content-Range`. `Err` carries the 416 for a range starting past the last row.
+struct PageHeaders {
+    link: String,
+    content_range: Option<String>,
+}
+
+fn page_headers(
+    conn: &Db,
+    count_sql: &str,
+    params: &[ParameterValue],
+    req: &Request,
+    (limit, offset): (i64, i64),
+) -> Result<std::result::Result<PageHeaders, Response>> {
+    let rowset = conn.query(count_sql, params)?;
+    let total = rowset
+        .rows
+        .first()
+        .and_then(|row| i64::decode(&row[0]).ok())
+        .unwrap_or(0);
+    let content_range = match req.header("range") {
+        Some(_) => match content_range(limit, offset, total) {
+            Some(v) => Some(v),
+            None => return range_not_satisfiable(total).map(Err),
+        },
+        None => None,
+    };
+    Ok(Ok(PageHeaders {
+        link: link_header(req.path(), req.query(), limit, offset, total),
+        content_range,
+    }))
+}
+
+/// Sets `Link` and, for a `Range` request, turns the list into 206 with `Content-Range`.
+fn with_page_headers(mut resp: Response, headers: PageHeaders) -> Response {
+    resp.set_header("link", headers.link);
+    match headers.content_range {
+        Some(v) => resp.into_builder().status(206).header("content-range", v).build(),
+        None => resp,
+    }
+}
+
+fn range_not_satisfiable(total: i64) -> Result<Response> {
+    let mut resp = json_response(416, r#"{"error":"Range not satisfiable"}"#)?;
+    resp.set_header("content-range", format!("items */{}", total));
+    Ok(resp)
 }
 
-fn list_orders(conn: &Connection, conn_ms: f64) -> Result<Response> {
+fn list_orders(
+    conn: &Db,
+    conn_ms: f64,
+    filter: &OrderFilter,
+    page: Option<(i64, i64)>,
+    fmt: OutputFormat,
+) -> Result<Response> {
+    let (where_clause, mut params) = filter.where_clause();
+    let mut sql = format!(
+        "SELECT id, customer_id, product, quantity FROM orders{}",
+        where_clause
+    );
+    let row_cap = max_rows();
+    match page {
+        Some((limit, offset)) => {
+            params.extend([ParameterValue::Int64(limit), ParameterValue::Int64(offset)]);
+            sql.push_str(&format!(
+                " ORDER BY id LIMIT ${} OFFSET ${}",
+                params.len() - 1,
+                params.len()
+            ));
+        }
+        // Unpaged reads stop at `max_rows`; the extra row only tells whether the cap was hit
+        None => {
+            params.push(ParameterValue::Int64(row_cap + 1));
+            sql.push_str(&format!(" ORDER BY id LIMIT ${}", params.len()));
+        }
+    }
+    let cap = page.is_none().then_some(row_cap);
+
     let t_query = Instant::now();
-    let rowset = conn.query(
-        "SELECT id, customer_id, product, quantity FROM orders",
-        &[],
-    )?;
+    let rowset = conn.query(&sql, &params)?;
     let query_ms = t_query.elapsed().as_secs_f64() * 1000.0;
 
-    let orders: Vec<Order> = rowset
+    let mut orders: Vec<Order> = rowset
         .rows
         .iter()
         .map(|row| Order {
-            id: i64::decode(&row[0]).unwrap_or(0),
+            id: OrderId::decode(&row[0]).unwrap_or_default(),
             customer_id: i64::decode(&row[1]).unwrap_or(0),
             product: String::decode(&row[2]).unwrap_or_default(),
             quantity: i64::decode(&row[3]).unwrap_or(0),
         })
         .collect();
+    let truncated = truncate_to_cap(&mut orders, cap);
 
     let t_ser = Instant::now();
-    let body = serde_json::to_string(&orders)?;
+    let body = read_json(&orders, Some(orders.len()), fmt)?;
+    let ser_ms = t_ser.elapsed().as_secs_f64() * 1000.0;
+
+    let mut resp = timed_response(200, &body, conn_ms, query_ms, ser_ms)?;
+    if truncated {
+        resp.set_header("x-truncated", "true");
+    }
+    set_debug_sql(&mut resp, &sql, params.len());
+    let empty = empty_list_204() && orders.is_empty() && !fmt.envelope;
+    Ok(no_content_if_empty(resp, empty))
+}
+
+/// Orders of every customer in `ids` in one `= ANY($1)` query, keyed by customer id
+/// (`{"1":[...],"2":[...]}`). Customers without orders are omitted.
+fn list_orders_by_customer(
+    conn: &Db,
+    conn_ms: f64,
+    ids: &[i64],
+    fmt: OutputFormat,
+) -> Result<Response> {
+    let t_query = Instant::now();
+    let rowset = conn.query(
+        ORDERS_BY_CUSTOMER_SQL,
+        &[ParameterValue::ArrayInt64(ids.iter().copied().map(Some).collect())],
+    )?;
+    let query_ms = t_query.elapsed().as_secs_f64() * 1000.0;
+
+    let t_ser = Instant::now();
+    let mut grouped: BTreeMap<i64, Vec<Order>> = BTreeMap::new();
+    for row in &rowset.rows {
+        let order = Order {
+            id: OrderId::decode(&row[0]).unwrap_or_default(),
+            customer_id: i64::decode(&row[1]).unwrap_or(0),
+            product: String::decode(&row[2]).unwrap_or_default(),
+            quantity: i64::decode(&row[3]).unwrap_or(0),
+        };
+        grouped.entry(order.customer_id).or_default().push(order);
+    }
+    let body = read_json(&grouped, Some(rowset.rows.len()), fmt)?;
+    let ser_ms = t_ser.elapsed().as_secs_f64() * 1000.0;
+
+    let mut resp = timed_response(200, &body, conn_ms, query_ms, ser_ms)?;
+    set_debug_sql(&mut resp, ORDERS_BY_CUSTOMER_SQL, 1);
+    Ok(resp)
+}
+
+/// Lists the product catalog consulted by `validate_product`.
+fn list_products(conn: &Db, conn_ms: f64, fmt: OutputFormat) -> Result<Response> {
+    let t_query = Instant::now();
+    let rowset = conn.query("SELECT name FROM products ORDER BY name", &[])?;
+    let query_ms = t_query.elapsed().as_secs_f64() * 1000.0;
+
+    let products: Vec<Product> = rowset
+        .rows
+        .iter()
+        .map(|row| Product {
+            name: String::decode(&row[0]).unwrap_or_default(),
+        })
+        .collect();
+
+    let t_ser = Instant::now();
+    let body = read_json(&products, Some(products.len()), fmt)?;
     let ser_ms = t_ser.elapsed().as_secs_f64() * 1000.0;
 
     timed_response(200, &body, conn_ms, query_ms, ser_ms)
 }
 
-async fn create_order(conn: &Connection, conn_ms: f64, body: &[u8]) -> Result<Response> {
+async fn create_order(
+    conn: &Db,
+    conn_ms: f64,
+    body: &[u8],
+    ids_as_strings: bool,
+    quick: bool,
+    req: &Request,
+) -> Result<Response> {
+    let req_size = body.len();
     let body_str = std::str::from_utf8(body)?;
-    let input: CreateOrderRequest = match serde_json::from_str(body_str) {
-        Ok(v) => v,
-        Err(_) => return json_response(400, r#"{"error":"Invalid JSON"}"#),
-    };
+    let input: CreateOrderRequest =
+        match parse_body(body_str.as_bytes(), CREATE_ORDER_FIELDS, strict_json()) {
+            Ok(v) => v,
+            Err(msg) => return error_response(ApiError::bad_request(msg)),
+        };
 
     let customer_id = match input.customer_id {
         Some(id) if id > 0 => id,
@@ -109,11 +853,25 @@ async fn create_order(conn: &Connection, conn_ms: f64, body: &[u8]) -> Result<Re
             )
         }
     };
-    let product = match &input.product {
-        Some(p) if !p.is_empty() && p.len() <= 255 => p.clone(),
-        Some(p) if p.len() > 255 => {
-            return json_response(400, r#"{"error":"product must be 255 characters or less"}"#)
+    // ?quick=true: fill omitted product/quantity from the customer's latest order
+    let (input_product, input_quantity) =
+        if quick && (input.product.is_none() || input.quantity.is_none()) {
+            match last_order_defaults(conn, customer_id)? {
+                Some((p, q)) => (input.product.or(Some(p)), input.quantity.or(Some(q))),
+                None => return json_response(400, r#"{"error":"No previous order to copy"}"#),
+            }
+        } else {
+            (input.product, input.quantity)
+        };
+    let max_product_len = max_product_len();
+    let product = match &input_product {
+        Some(p) if p.chars().count() > max_product_len => {
+            return error_response(ApiError::bad_request(format!(
+                "product must be {} characters or less",
+                max_product_len
+            )))
         }
+        Some(p) if !p.is_empty() => p.clone(),
         _ => {
             return json_response(
                 400,
@@ -121,7 +879,10 @@ async fn create_order(conn: &Connection, conn_ms: f64, body: &[u8]) -> Result<Re
             )
         }
     };
-    let quantity = match input.quantity {
+    let quantity = match input_quantity {
+        Some(q) if q > max_quantity() => {
+            return error_response(ApiError::bad_request("quantity out of range"))
+        }
         Some(q) if q > 0 => q,
         Some(_) => {
             return json_response(400, r#"{"error":"quantity must be positive"}"#)
@@ -134,30 +895,93 @@ async fn create_order(conn: &Connection, conn_ms: f64, body: &[u8]) -> Result<Re
         }
     };
 
-    // Verify customer exists via Customer Service
+    // Verify customer exists via Customer Service (or the shared DB with verify_via = "db")
     let t_verify = Instant::now();
-    match verify_customer_exists(customer_id).await {
-        Ok(true) => {}
-        Ok(false) => return json_response(400, r#"{"error":"Customer not found"}"#),
-        Err(_) => {
-            return json_response(502, r#"{"error":"Customer service unavailable"}"#)
+    match verify_customer_exists(conn, customer_id, req).await {
+        Ok(None) => return verify_failed(504, deadline::EXCEEDED_BODY, "error"),
+        Ok(Some(true)) => {}
+        Ok(Some(false)) => {
+            return verify_failed(400, r#"{"error":"Customer not found"}"#, "not_found")
+        }
+        Err(VerifyError::ServiceUnavailable) => {
+            return verify_failed(502, r#"{"error":"Customer service unavailable"}"#, "error")
         }
+        Err(VerifyError::Db) => return db_error(),
     }
     let verify_ms = t_verify.elapsed().as_secs_f64() * 1000.0;
 
     let t_query = Instant::now();
-    let rowset = conn.query(
-        "INSERT INTO orders (customer_id, product, quantity) VALUES ($1, $2, $3) RETURNING id, customer_id, product, quantity",
+    if validate_product() {
+        let known = conn.query(
+            "SELECT 1 FROM products WHERE name = $1",
+            &[ParameterValue::Str(product.clone())],
+        )?;
+        if known.rows.is_empty() {
+            return json_response(400, r#"{"error":"unknown product"}"#);
+        }
+    }
+    let window_ms = dedup_window_ms();
+    if window_ms > 0 {
+        let recent = conn.query(
+            RECENT_DUPLICATE_SQL,
+            &[
+                ParameterValue::Int64(customer_id),
+                ParameterValue::Str(product.clone()),
+                ParameterValue::Int64(window_ms),
+            ],
+        )?;
+        if !recent.rows.is_empty() {
+            return json_response(409, r#"{"error":"duplicate order"}"#);
+        }
+    }
+    // With a cap the count and the insert share a transaction, serialized per customer
+    let max_orders = max_orders_per_customer();
+    let limit_ms = if max_orders > 0 {
+        let t_limit = Instant::now();
+        conn.execute("BEGIN", &[])?;
+        match order_count_locked(conn, customer_id) {
+            Ok(n) if n >= max_orders => {
+                let _ = conn.execute("ROLLBACK", &[]);
+                return json_response(409, r#"{"error":"order limit reached"}"#);
+            }
+            Ok(_) => {}
+            Err(_) => {
+                let _ = conn.execute("ROLLBACK", &[]);
+                return db_error();
+            }
+        }
+        Some(t_limit.elapsed().as_secs_f64() * 1000.0)
+    } else {
+        None
+    };
+    let inserted = conn.query(
+        CREATE_ORDER_SQL,
         &[
             ParameterValue::Int64(customer_id),
             ParameterValue::Str(product),
             ParameterValue::Int64(quantity),
         ],
-    )?;
-    let query_ms = t_query.elapsed().as_secs_f64() * 1000.0;
+    );
+    if limit_ms.is_some() {
+        let end = if inserted.is_ok() { "COMMIT" } else { "ROLLBACK" };
+        if conn.execute(end, &[]).is_err() {
+            let _ = conn.execute("ROLLBACK", &[]);
+            return db_error();
+        }
+    }
+    let rowset = match inserted {
+        Ok(r) => r,
+        // The customer may have been deleted between verify and insert
+        Err(e) if is_foreign_key_violation(&e) => {
+            return json_response(400, r#"{"error":"referenced customer does not exist"}"#)
+        }
+        Err(e) => return Err(e.into()),
+    };
+    // `limit` is reported on its own rather than inside `query`
+    let query_ms = t_query.elapsed().as_secs_f64() * 1000.0 - limit_ms.unwrap_or(0.0);
 
     let order = rowset.rows.first().map(|row| Order {
-        id: i64::decode(&row[0]).unwrap_or(0),
+        id: OrderId::decode(&row[0]).unwrap_or_default(),
         customer_id: i64::decode(&row[1]).unwrap_or(0),
         product: String::decode(&row[2]).unwrap_or_default(),
         quantity: i64::decode(&row[3]).unwrap_or(0),
@@ -166,18 +990,24 @@ async fn create_order(conn: &Connection, conn_ms: f64, body: &[u8]) -> Result<Re
     match order {
         Some(o) => {
             let t_ser = Instant::now();
-            let body = serde_json::to_string(&o)?;
+            let body = to_json(&o, ids_as_strings)?;
             let ser_ms = t_ser.elapsed().as_secs_f64() * 1000.0;
+            let mut timing = ServerTiming::new()
+                .dur("conn", conn_ms)
+                .dur("verify", verify_ms);
+            if let Some(ms) = limit_ms {
+                timing = timing.dur("limit", ms);
+            }
+            let timing = timing
+                .dur("query", query_ms)
+                .dur("ser", ser_ms)
+                .size("body", body.len())
+                .size("req", req_size);
             Ok(Response::builder()
                 .status(201)
                 .header("content-type", "application/json")
-                .header(
-                    "server-timing",
-                    format!(
-                        "conn;dur={:.1}, verify;dur={:.1}, query;dur={:.1}, ser;dur={:.1}",
-                        conn_ms, verify_ms, query_ms, ser_ms
-                    ),
-                )
+                .header("x-verify-outcome", "ok")
+                .header("server-timing", timing.to_string())
                 .body(body)
                 .build())
         }
@@ -185,21 +1015,548 @@ async fn create_order(conn: &Connection, conn_ms: f64, body: &[u8]) -> Result<Re
     }
 }
 
-fn get_order(conn: &Connection, conn_ms: f64, id_str: &str) -> Result<Response> {
-    let id: i64 = match id_str.parse() {
+/// Error response for a failed customer check, tagged with `x-verify-outcome`. Spin has no
+/// metrics exporter, so the header stands in for the Axum service's `verify_*` counters.
+fn verify_failed(status: u16, body: &str, outcome: &str) -> Result<Response> {
+    let mut resp = json_response(status, body)?;
+    resp.set_header("x-verify-outcome", outcome);
+    Ok(resp)
+}
+
+/// Deletes every order of a customer in one statement. Returns 200 with `deleted:0`
+/// when nothing matched, since the customer itself may or may not exist.
+fn delete_customer_orders(
+    conn: &Db,
+    conn_ms: f64,
+    req: &Request,
+    id_str: &str,
+) -> Result<Response> {
+    if let Some((status, body)) = admin_denied(req) {
+        return json_response(status, body);
+    }
+    let customer_id: i64 = match id_str.parse() {
+        Ok(v) => v,
+        Err(_) => return json_response(400, r#"{"error":"Invalid customer ID"}"#),
+    };
+
+    let t_query = Instant::now();
+    let deleted = conn.execute(
+        "WITH deleted AS (DELETE FROM orders WHERE customer_id = $1 RETURNING id) \
+         INSERT INTO order_audit (order_id, change) SELECT id, $2 FROM deleted",
+        &[
+            ParameterValue::Int64(customer_id),
+            ParameterValue::Str(format!("deleted with all orders of customer {}", customer_id)),
+        ],
+    )?;
+    let query_ms = t_query.elapsed().as_secs_f64() * 1000.0;
+
+    let body = format!(r#"{{"deleted":{}}}"#, deleted);
+    timed_response(200, &body, conn_ms, query_ms, 0.0)
+}
+
+/// Deletes the listed orders with one `id = ANY($1)` statement in a transaction, auditing
+/// each. Ids that match nothing are skipped, so a fully stale list answers `deleted:0`.
+fn delete_orders(conn: &Db, conn_ms: f64, req: &Request, body: &[u8]) -> Result<Response> {
+    if let Some((status, body)) = admin_denied(req) {
+        return json_response(status, body);
+    }
+    let input: DeleteOrdersRequest = match json::from_slice(body) {
+        Ok(v) => v,
+        Err(_) => return json_response(400, r#"{"error":"Invalid JSON"}"#),
+    };
+    let ids = match parse_delete_ids(input.ids) {
+        Ok(ids) => ids,
+        Err(msg) => return error_response(ApiError::bad_request(msg)),
+    };
+
+    let sql = format!(
+        "WITH deleted AS (DELETE FROM orders WHERE id = ANY($1::{}) RETURNING id) \
+         INSERT INTO order_audit (order_id, change) SELECT id, 'deleted' FROM deleted",
+        ids.array_type()
+    );
+
+    let t_query = Instant::now();
+    conn.execute("BEGIN", &[])?;
+    let deleted = match conn.execute(&sql, &[ids.into_param()]) {
+        Ok(n) => n,
+        Err(_) => {
+            let _ = conn.execute("ROLLBACK", &[]);
+            return db_error();
+        }
+    };
+    if conn.execute("COMMIT", &[]).is_err() {
+        let _ = conn.execute("ROLLBACK", &[]);
+        return db_error();
+    }
+    let query_ms = t_query.elapsed().as_secs_f64() * 1000.0;
+
+    let body = format!(r#"{{"deleted":{}}}"#, deleted);
+    timed_response(200, &body, conn_ms, query_ms, 0.0)
+}
+
+/// `ids` for `POST /orders/delete`: required, at most [`MAX_DELETE_IDS`], and every entry
+/// an id of the configured `id_strategy`, see [`OrderIds::parse`].
+fn parse_delete_ids(ids: Option<Vec<Value>>) -> Result<OrderIds, String> {
+    let ids = ids.ok_or("ids is required")?;
+    if ids.len() > MAX_DELETE_IDS {
+        return Err(format!("too many ids (max {})", MAX_DELETE_IDS));
+    }
+    OrderIds::parse(&ids).map_err(str::to_string)
+}
+
+/// Moves every order of `from_customer_id` to `to_customer_id` in one UPDATE, after
+/// verifying the target through the customer-service (409 when it does not exist).
+async fn reassign_orders(
+    conn: &Db,
+    conn_ms: f64,
+    req: &Request,
+    body: &[u8],
+) -> Result<Response> {
+    if let Some((status, body)) = admin_denied(req) {
+        return json_response(status, body);
+    }
+    let input: ReassignRequest = match json::from_slice(body) {
         Ok(v) => v,
-        Err(_) => return json_response(400, r#"{"error":"Invalid order ID"}"#),
+        Err(_) => return json_response(400, r#"{"error":"Invalid JSON"}"#),
+    };
+    let (from, to) = match (input.from_customer_id, input.to_customer_id) {
+        (Some(f), Some(t)) if f > 0 && t > 0 && f != t => (f, t),
+        (Some(_), Some(_)) => {
+            return json_response(
+                400,
+                r#"{"error":"from_customer_id and to_customer_id must be distinct positive IDs"}"#,
+            )
+        }
+        _ => {
+            return json_response(
+                400,
+                r#"{"error":"from_customer_id and to_customer_id are required"}"#,
+            )
+        }
     };
 
+    let t_verify = Instant::now();
+    match verify_customer_exists(conn, to, req).await {
+        Ok(None) => return json_response(504, deadline::EXCEEDED_BODY),
+        Ok(Some(true)) => {}
+        Ok(Some(false)) => return json_response(409, r#"{"error":"Target customer not found"}"#),
+        Err(VerifyError::ServiceUnavailable) => {
+            return json_response(502, r#"{"error":"Customer service unavailable"}"#)
+        }
+        Err(VerifyError::Db) => return db_error(),
+    }
+    let verify_ms = t_verify.elapsed().as_secs_f64() * 1000.0;
+
     let t_query = Instant::now();
+    let reassigned = conn.execute(
+        "WITH moved AS (UPDATE orders SET customer_id = $1 WHERE customer_id = $2 RETURNING id) \
+         INSERT INTO order_audit (order_id, change) SELECT id, $3 FROM moved",
+        &[
+            ParameterValue::Int64(to),
+            ParameterValue::Int64(from),
+            ParameterValue::Str(format!("customer_id {} -> {}", from, to)),
+        ],
+    )?;
+    let query_ms = t_query.elapsed().as_secs_f64() * 1000.0;
+
+    let body = format!(r#"{{"reassigned":{}}}"#, reassigned);
+    Ok(Response::builder()
+        .status(200)
+        .header("content-type", "application/json")
+        .header(
+            "server-timing",
+            ServerTiming::new()
+                .dur("conn", conn_ms)
+                .dur("verify", verify_ms)
+                .dur("query", query_ms)
+                .size("body", body.len())
+                .to_string(),
+        )
+        .body(body)
+        .build())
+}
+
+/// Seeds `count` random orders for one customer in transactions of [`GENERATE_BATCH_SIZE`],
+/// for benchmarking without an external load generator. Batches commit independently, so
+/// a failure part-way keeps the batches before it.
+async fn generate_orders(conn: &Db, conn_ms: f64, req: &Request) -> Result<Response> {
+    if let Some((status, body)) = admin_denied(req) {
+        return json_response(status, body);
+    }
+    let query = req.query();
+    let (count, customer_id) = match parse_generate_params(
+        query_param(query, "count"),
+        query_param(query, "customer_id"),
+    ) {
+        Ok(v) => v,
+        Err(msg) => return error_response(ApiError::bad_request(msg)),
+    };
+
+    let t_verify = Instant::now();
+    match verify_customer_exists(conn, customer_id, req).await {
+        Ok(None) => return json_response(504, deadline::EXCEEDED_BODY),
+        Ok(Some(true)) => {}
+        Ok(Some(false)) => return json_response(400, r#"{"error":"Customer not found"}"#),
+        Err(VerifyError::ServiceUnavailable) => {
+            return json_response(502, r#"{"error":"Customer service unavailable"}"#)
+        }
+        Err(VerifyError::Db) => return db_error(),
+    }
+    let verify_ms = t_verify.elapsed().as_secs_f64() * 1000.0;
+
+    let t_query = Instant::now();
+    let mut batches = Vec::new();
+    let mut generated = 0;
+    while generated < count {
+        let rows = GENERATE_BATCH_SIZE.min(count - generated);
+        let t_batch = Instant::now();
+        conn.execute("BEGIN", &[])?;
+        let inserted = match conn.query(
+            GENERATE_ORDERS_SQL,
+            &[ParameterValue::Int64(customer_id), ParameterValue::Int64(rows)],
+        ) {
+            Ok(rowset) => rowset
+                .rows
+                .first()
+                .and_then(|row| i64::decode(&row[0]).ok())
+                .unwrap_or(0),
+            Err(_) => {
+                let _ = conn.execute("ROLLBACK", &[]);
+                return db_error();
+            }
+        };
+        if conn.execute("COMMIT", &[]).is_err() {
+            let _ = conn.execute("ROLLBACK", &[]);
+            return db_error();
+        }
+        generated += inserted;
+        batches.push(BatchTiming {
+            rows: inserted,
+            ms: round_ms(t_batch.elapsed().as_secs_f64() * 1000.0),
+        });
+    }
+    let query_ms = t_query.elapsed().as_secs_f64() * 1000.0;
+    eprintln!(
+        "generated {} orders for customer {} in {:.1}ms",
+        generated, customer_id, query_ms
+    );
+
+    let report = GenerateReport {
+        generated,
+        customer_id,
+        total_ms: round_ms(query_ms),
+        inserts_per_sec: (generated as f64 / (query_ms / 1000.0)).round(),
+        batches,
+    };
+    let body = serde_json::to_string(&report)?;
+    Ok(Response::builder()
+        .status(200)
+        .header("content-type", "application/json")
+        .header(
+            "server-timing",
+            ServerTiming::new()
+                .dur("conn", conn_ms)
+                .dur("verify", verify_ms)
+                .dur("query", query_ms)
+                .size("body", body.len())
+                .to_string(),
+        )
+        .body(body)
+        .build())
+}
+
+/// Milliseconds to microsecond precision, for the JSON report.
+fn round_ms(ms: f64) -> f64 {
+    (ms * 1000.0).round() / 1000.0
+}
+
+/// `count` (1 to [`MAX_GENERATE_COUNT`]) and `customer_id`, both required.
+fn parse_generate_params(
+    count: Option<&str>,
+    customer_id: Option<&str>,
+) -> Result<(i64, i64), String> {
+    let count = match count.map(str::parse::<i64>) {
+        Some(Ok(n)) if (1..=MAX_GENERATE_COUNT).contains(&n) => n,
+        Some(_) => return Err(format!("count must be between 1 and {}", MAX_GENERATE_COUNT)),
+        None => return Err("count is required".to_string()),
+    };
+    let customer_id = match customer_id.map(str::parse::<i64>) {
+        Some(Ok(id)) if id > 0 => id,
+        Some(_) => return Err("Invalid customer ID".to_string()),
+        None => return Err("customer_id is required".to_string()),
+    };
+    Ok((count, customer_id))
+}
+
+/// Audits every order's customer against the customer-service, in batches of
+/// [`EXISTS_BATCH_SIZE`], and reports orders whose customer no longer exists. Read-only:
+/// `?fix=mark` would need an order status column, which the schema does not have.
+async fn reverify_orders(
+    conn: &Db,
+    conn_ms: f64,
+    req: &Request,
+    ids_as_strings: bool,
+) -> Result<Response> {
+    if let Some((status, body)) = admin_denied(req) {
+        return json_response(status, body);
+    }
+    if query_param(req.query(), "fix").is_some() {
+        return json_response(
+            400,
+            r#"{"error":"fix is not supported: orders have no status column"}"#,
+        );
+    }
+
+    let t_query = Instant::now();
+    let rowset = conn.query(
+        "SELECT DISTINCT customer_id FROM orders ORDER BY customer_id",
+        &[],
+    )?;
+    let mut query_ms = t_query.elapsed().as_secs_f64() * 1000.0;
+    let customer_ids: Vec<i64> = rowset
+        .rows
+        .iter()
+        .filter_map(|row| i64::decode(&row[0]).ok())
+        .collect();
+
+    let t_verify = Instant::now();
+    let customer_url = variables::get("customer_service_url")?;
+    let url = format!("{}/customers/exists", customer_url);
+    let mut missing = Vec::new();
+    for batch in customer_ids.chunks(EXISTS_BATCH_SIZE) {
+        let outbound = Request::post(&url, serde_json::json!({ "ids": batch }).to_string())
+            .header("content-type", "application/json")
+            .build();
+        let exists: Option<ExistsResponse> = match send::<_, Response>(outbound).await {
+            Ok(resp) if *resp.status() == 200 => serde_json::from_slice(resp.body()).ok(),
+            _ => None,
+        };
+        match exists {
+            Some(e) => missing.extend(e.missing),
+            None => return json_response(502, r#"{"error":"Customer service unavailable"}"#),
+        }
+    }
+    let verify_ms = t_verify.elapsed().as_secs_f64() * 1000.0;
+
+    let orphaned: Vec<Order> = if missing.is_empty() {
+        Vec::new()
+    } else {
+        let t_query = Instant::now();
+        let ids: Vec<Option<i64>> = missing.into_iter().map(Some).collect();
+        let rowset = conn.query(
+            "SELECT id, customer_id, product, quantity FROM orders \
+             WHERE customer_id = ANY($1) ORDER BY id",
+            &[ParameterValue::ArrayInt64(ids)],
+        )?;
+        query_ms += t_query.elapsed().as_secs_f64() * 1000.0;
+        rowset
+            .rows
+            .iter()
+            .map(|row| Order {
+                id: OrderId::decode(&row[0]).unwrap_or_default(),
+                customer_id: i64::decode(&row[1]).unwrap_or(0),
+                product: String::decode(&row[2]).unwrap_or_default(),
+                quantity: i64::decode(&row[3]).unwrap_or(0),
+            })
+            .collect()
+    };
+
+    let t_ser = Instant::now();
+    let body = format!(
+        r#"{{"checked_customers":{},"orphaned":{}}}"#,
+        customer_ids.len(),
+        to_json(&orphaned, ids_as_strings)?
+    );
+    let ser_ms = t_ser.elapsed().as_secs_f64() * 1000.0;
+
+    Ok(Response::builder()
+        .status(200)
+        .header("content-type", "application/json")
+        .header(
+            "server-timing",
+            ServerTiming::new()
+                .dur("conn", conn_ms)
+                .dur("query", query_ms)
+                .dur("verify", verify_ms)
+                .dur("ser", ser_ms)
+                .size("body", body.len())
+                .to_string(),
+        )
+        .body(body)
+        .build())
+}
+
+/// Checks the `x-admin-token` header against the `admin_token` variable. Admin
+/// endpoints are disabled entirely when the variable is empty.
+fn admin_denied(req: &Request) -> Option<(u16, &'static str)> {
+    let expected = variables::get("admin_token").unwrap_or_default();
+    if expected.is_empty() {
+        return Some((403, r#"{"error":"Admin endpoints disabled"}"#));
+    }
+    match req.header("x-admin-token").and_then(|v| v.as_str()) {
+        Some(token) if token == expected => None,
+        _ => Some((401, r#"{"error":"Invalid admin token"}"#)),
+    }
+}
+
+/// Parses a JSON body. Integer fields ([`INTEGER_FIELDS`]) holding another JSON type are
+/// reported by name instead of the generic `Invalid JSON`. With `strict`, also rejects
+/// object keys outside `allowed` (checked per element for arrays) so typos like `nmae`
+/// surface as 400s.
+fn parse_body<T: DeserializeOwned>(body: &[u8], allowed: &[&str], strict: bool) -> Result<T, String> {
+    let value: Value = json::from_slice(body).map_err(|_| "Invalid JSON".to_string())?;
+    let objects: Vec<&Value> = match &value {
+        Value::Array(items) => items.iter().collect(),
+        other => vec![other],
+    };
+    if let Some((field, v)) = objects
+        .iter()
+        .filter_map(|v| v.as_object())
+        .find_map(|map| {
+            INTEGER_FIELDS.iter().find_map(|f| {
+                map.get(*f)
+                    .filter(|v| !v.is_null() && !v.is_i64())
+                    .map(|v| (f, v))
+            })
+        })
+    {
+        // Fractional numbers, `1.0` included, are rejected rather than rounded
+        if v.is_f64() {
+            return Err(format!("{} must be a whole number", field));
+        }
+        return Err(format!("{} must be an integer", field));
+    }
+    if !strict {
+        return serde_json::from_value(value).map_err(|_| "Invalid JSON".to_string());
+    }
+    let mut unknown: Vec<&str> = objects
+        .iter()
+        .filter_map(|v| v.as_object())
+        .flat_map(|map| map.keys())
+        .map(String::as_str)
+        .filter(|key| !allowed.contains(key))
+        .collect();
+    unknown.sort_unstable();
+    unknown.dedup();
+    if !unknown.is_empty() {
+        return Err(format!("unknown fields: {}", unknown.join(", ")));
+    }
+    serde_json::from_value(value).map_err(|_| "Invalid JSON".to_string())
+}
+
+/// `empty_list_204 = "true"`: turns an empty bare-array list into 204 with no body,
+/// keeping `server-timing` and the other headers. `?envelope=true` lists stay 200.
+fn no_content_if_empty(resp: Response, empty: bool) -> Response {
+    if !empty {
+        return resp;
+    }
+    let mut builder = Response::builder();
+    builder.status(204);
+    for (name, value) in resp.headers() {
+        if let Some(value) = value.as_str().filter(|_| !name.eq_ignore_ascii_case("content-type")) {
+            builder.header(name, value);
+        }
+    }
+    builder.build()
+}
+
+/// `debug_sql=true`: `X-Debug-SQL` with the read's statement and bound-parameter count.
+/// Exposes the schema to any client, so it is for local debugging only.
+fn set_debug_sql(resp: &mut Response, sql: &str, params: usize) {
+    let enabled = variables::get("debug_sql")
+        .map(|v| v == "true")
+        .unwrap_or(false);
+    if enabled {
+        resp.set_header(debug_sql::HEADER, debug_sql::describe(sql, params));
+    }
+}
+
+fn empty_list_204() -> bool {
+    variables::get("empty_list_204")
+        .map(|v| v == "true")
+        .unwrap_or(false)
+}
+
+fn validate_product() -> bool {
+    variables::get("validate_product")
+        .map(|v| v == "true")
+        .unwrap_or(false)
+}
+
+/// The one quantity bound, held on create and after an increment: what the `quantity`
+/// column can store, `i32::MAX` for `INTEGER` (`quantity_is_int32`, the default) and
+/// `i64::MAX` for `BIGINT`.
+fn max_quantity() -> i64 {
+    let int32 = variables::get("quantity_is_int32")
+        .map(|v| v != "false")
+        .unwrap_or(true);
+    if int32 {
+        i64::from(i32::MAX)
+    } else {
+        i64::MAX
+    }
+}
+
+fn strict_json() -> bool {
+    variables::get("strict_json")
+        .map(|v| v == "true")
+        .unwrap_or(false)
+}
+
+/// Counts the customer's orders after taking a transaction-scoped advisory lock on the
+/// customer id, so concurrent creates for one customer cannot both pass the cap. The lock
+/// and the count are separate statements: the count's snapshot must be taken after the
+/// lock is granted to see orders committed by the previous holder.
+fn order_count_locked(conn: &Db, customer_id: i64) -> Result<i64> {
+    conn.execute(
+        "SELECT pg_advisory_xact_lock($1)",
+        &[ParameterValue::Int64(customer_id)],
+    )?;
     let rowset = conn.query(
-        "SELECT id, customer_id, product, quantity FROM orders WHERE id = $1",
-        &[ParameterValue::Int64(id)],
+        "SELECT COUNT(*) FROM orders WHERE customer_id = $1",
+        &[ParameterValue::Int64(customer_id)],
     )?;
+    Ok(rowset
+        .rows
+        .first()
+        .and_then(|row| i64::decode(&row[0]).ok())
+        .unwrap_or(0))
+}
+
+fn last_order_defaults(conn: &Db, customer_id: i64) -> Result<Option<(String, i64)>> {
+    let rowset = conn.query(
+        "SELECT product, quantity FROM orders WHERE customer_id = $1 ORDER BY id DESC LIMIT 1",
+        &[ParameterValue::Int64(customer_id)],
+    )?;
+    Ok(rowset.rows.first().map(|row| {
+        (
+            String::decode(&row[0]).unwrap_or_default(),
+            i64::decode(&row[1]).unwrap_or(0),
+        )
+    }))
+}
+
+fn is_foreign_key_violation(err: &PgError) -> bool {
+    matches!(err, PgError::QueryFailed(QueryError::DbError(db)) if db.code == "23503")
+}
+
+fn get_order(
+    conn: &Db,
+    conn_ms: f64,
+    id_str: &str,
+    fmt: OutputFormat,
+) -> Result<Response> {
+    let id = match OrderId::parse(id_str) {
+        Some(v) => v,
+        None => return json_response(400, r#"{"error":"Invalid order ID"}"#),
+    };
+
+    let t_query = Instant::now();
+    let rowset = conn.query(GET_ORDER_SQL, &[id.to_param()])?;
     let query_ms = t_query.elapsed().as_secs_f64() * 1000.0;
 
     let order = rowset.rows.first().map(|row| Order {
-        id: i64::decode(&row[0]).unwrap_or(0),
+        id: OrderId::decode(&row[0]).unwrap_or_default(),
         customer_id: i64::decode(&row[1]).unwrap_or(0),
         product: String::decode(&row[2]).unwrap_or_default(),
         quantity: i64::decode(&row[3]).unwrap_or(0),
@@ -208,14 +1565,292 @@ fn get_order(conn: &Connection, conn_ms: f64, id_str: &str) -> Result<Response>
     match order {
         Some(o) => {
             let t_ser = Instant::now();
-            let body = serde_json::to_string(&o)?;
+            let body = read_json(&o, None, fmt)?;
             let ser_ms = t_ser.elapsed().as_secs_f64() * 1000.0;
-            timed_response(200, &body, conn_ms, query_ms, ser_ms)
+            let mut resp = timed_response(200, &body, conn_ms, query_ms, ser_ms)?;
+            set_debug_sql(&mut resp, GET_ORDER_SQL, 1);
+            Ok(resp)
         }
         None => json_response(404, r#"{"error":"Order not found"}"#),
     }
 }
 
+/// `GET /orders/{id}/history`: the order's `order_audit` rows, oldest first. Rows outlive
+/// the order, so a deleted order still has a history; 404 only when neither exists.
+fn order_history(conn: &Db, conn_ms: f64, id_str: &str) -> Result<Response> {
+    let id = match OrderId::parse(id_str) {
+        Some(v) => v,
+        None => return json_response(400, r#"{"error":"Invalid order ID"}"#),
+    };
+
+    let t_query = Instant::now();
+    let rowset = conn.query(ORDER_HISTORY_SQL, &[id.to_param()])?;
+    let entries: Vec<AuditEntry> = rowset
+        .rows
+        .iter()
+        .map(|row| AuditEntry {
+            changed_at: String::decode(&row[0]).unwrap_or_default(),
+            change: String::decode(&row[1]).unwrap_or_default(),
+        })
+        .collect();
+    if entries.is_empty() {
+        let existing = conn.query("SELECT id FROM orders WHERE id = $1", &[id.to_param()])?;
+        if existing.rows.is_empty() {
+            return json_response(404, r#"{"error":"Order not found"}"#);
+        }
+    }
+    let query_ms = t_query.elapsed().as_secs_f64() * 1000.0;
+
+    let t_ser = Instant::now();
+    let body = serde_json::to_string(&entries)?;
+    let ser_ms = t_ser.elapsed().as_secs_f64() * 1000.0;
+    let mut resp = timed_response(200, &body, conn_ms, query_ms, ser_ms)?;
+    set_debug_sql(&mut resp, ORDER_HISTORY_SQL, 1);
+    Ok(resp)
+}
+
+/// `GET /orders/count?customer_id=N`: one customer's order count, as
+/// `{"customer_id":N,"count":M}`. customer-service calls it for `with_order_count=true`.
+fn count_orders(conn: &Db, conn_ms: f64, query: &str) -> Result<Response> {
+    let customer_id: i64 = match query_param(query, "customer_id").map(str::parse) {
+        Some(Ok(id)) => id,
+        Some(Err(_)) => return error_response(ApiError::bad_request("Invalid customer ID")),
+        None => return error_response(ApiError::bad_request("customer_id is required")),
+    };
+
+    let t_query = Instant::now();
+    let rowset = conn.query(
+        "SELECT COUNT(*) FROM orders WHERE customer_id = $1",
+        &[ParameterValue::Int64(customer_id)],
+    )?;
+    let count = rowset
+        .rows
+        .first()
+        .and_then(|row| i64::decode(&row[0]).ok())
+        .unwrap_or(0);
+    let query_ms = t_query.elapsed().as_secs_f64() * 1000.0;
+
+    let body = serde_json::json!({ "customer_id": customer_id, "count": count }).to_string();
+    timed_response(200, &body, conn_ms, query_ms, 0.0)
+}
+
+/// `GET /orders/export`: all orders (optionally `?customer_id=`) as NDJSON. Unlike the Axum
+/// service the body is built in full before responding, so `ser;dur` covers every line.
+fn export_orders(conn: &Db, conn_ms: f64, query: &str) -> Result<Response> {
+    let customer_id: Option<i64> = match query_param(query, "customer_id").map(str::parse) {
+        Some(Ok(id)) => Some(id),
+        Some(Err(_)) => return error_response(ApiError::bad_request("Invalid customer ID")),
+        None => None,
+    };
+
+    let t_query = Instant::now();
+    let rowset = match customer_id {
+        Some(id) => conn.query(
+            "SELECT id, customer_id, product, quantity FROM orders WHERE customer_id = $1 ORDER BY id",
+            &[ParameterValue::Int64(id)],
+        )?,
+        None => conn.query(
+            "SELECT id, customer_id, product, quantity FROM orders ORDER BY id",
+            &[],
+        )?,
+    };
+    let query_ms = t_query.elapsed().as_secs_f64() * 1000.0;
+
+    let t_ser = Instant::now();
+    let mut body = String::new();
+    for row in &rowset.rows {
+        let order = Order {
+            id: OrderId::decode(&row[0]).unwrap_or_default(),
+            customer_id: i64::decode(&row[1]).unwrap_or(0),
+            product: String::decode(&row[2]).unwrap_or_default(),
+            quantity: i64::decode(&row[3]).unwrap_or(0),
+        };
+        body.push_str(&json::to_string(&order)?);
+        body.push('\n');
+    }
+    let ser_ms = t_ser.elapsed().as_secs_f64() * 1000.0;
+
+    let mut resp = timed_response(200, &body, conn_ms, query_ms, ser_ms)?;
+    resp.set_header("content-type", "application/x-ndjson");
+    Ok(resp)
+}
+
+/// Adds `by` (may be negative) to an order's quantity in a single UPDATE, so concurrent
+/// increments never lose updates. Only the bound in the direction of the change is checked:
+/// an increase may not pass [`max_quantity`] and a decrease may not go below 1, so an order
+/// stored above the bound can still be reduced. Comparing against `$3 - $1` / `-$1` rather
+/// than `quantity + $1` keeps the check itself from overflowing.
+fn increment_order(
+    conn: &Db,
+    conn_ms: f64,
+    id_str: &str,
+    body: &[u8],
+    ids_as_strings: bool,
+) -> Result<Response> {
+    let id = match OrderId::parse(id_str) {
+        Some(v) => v,
+        None => return json_response(400, r#"{"error":"Invalid order ID"}"#),
+    };
+    let input: IncrementRequest = match json::from_slice(body) {
+        Ok(v) => v,
+        Err(_) => return json_response(400, r#"{"error":"Invalid JSON"}"#),
+    };
+    let max = max_quantity();
+    let by = match input.by {
+        Some(b) if b != 0 && b.unsigned_abs() <= max as u64 => b,
+        Some(_) => {
+            return json_response(
+                400,
+                r#"{"error":"by must be non-zero and within the quantity cap"}"#,
+            )
+        }
+        None => return json_response(400, r#"{"error":"by is required"}"#),
+    };
+
+    let t_query = Instant::now();
+    let rowset = conn.query(
+        "WITH updated AS ( \
+             UPDATE orders SET quantity = quantity + $1 \
+             WHERE id = $2 \
+               AND CASE WHEN $1 > 0 THEN quantity <= $3 - $1 ELSE quantity - 1 >= -$1 END \
+             RETURNING id, customer_id, product, quantity \
+         ), audit AS ( \
+             INSERT INTO order_audit (order_id, change) SELECT id, $4 FROM updated \
+         ) \
+         SELECT id, customer_id, product, quantity FROM updated",
+        &[
+            ParameterValue::Int64(by),
+            id.to_param(),
+            ParameterValue::Int64(max),
+            ParameterValue::Str(format!("quantity {:+}", by)),
+        ],
+    )?;
+
+    let order = match rowset.rows.first() {
+        Some(row) => Order {
+            id: OrderId::decode(&row[0]).unwrap_or_default(),
+            customer_id: i64::decode(&row[1]).unwrap_or(0),
+            product: String::decode(&row[2]).unwrap_or_default(),
+            quantity: i64::decode(&row[3]).unwrap_or(0),
+        },
+        None => {
+            // Nothing updated: tell a missing order apart from an out-of-range result
+            let existing = conn.query(
+                "SELECT id FROM orders WHERE id = $1",
+                &[id.to_param()],
+            )?;
+            return if existing.rows.is_empty() {
+                json_response(404, r#"{"error":"Order not found"}"#)
+            } else {
+                json_response(
+                    400,
+                    r#"{"error":"quantity must stay between 1 and the quantity cap"}"#,
+                )
+            };
+        }
+    };
+    let query_ms = t_query.elapsed().as_secs_f64() * 1000.0;
+
+    let t_ser = Instant::now();
+    let body = to_json(&order, ids_as_strings)?;
+    let ser_ms = t_ser.elapsed().as_secs_f64() * 1000.0;
+
+    timed_response(200, &body, conn_ms, query_ms, ser_ms)
+}
+
+/// Response shaping requested via `?ids_as_strings=true` and `?envelope=true`.
+#[derive(Clone, Copy)]
+struct OutputFormat {
+    ids_as_strings: bool,
+    envelope: bool,
+}
+
+impl OutputFormat {
+    fn from_query(query: &str) -> Result<Self, String> {
+        Ok(OutputFormat {
+            ids_as_strings: bool_param(query, "ids_as_strings")?,
+            envelope: bool_param(query, "envelope")?,
+        })
+    }
+}
+
+/// [`to_json`] for read endpoints: additionally applies `envelope`, wrapping lists
+/// (`count` is `Some`) in `{"data":[...],"meta":{"count":N}}` and single resources in
+/// `{"data":{...}}`.
+fn read_json<T: Serialize>(value: &T, count: Option<usize>, fmt: OutputFormat) -> Result<String> {
+    let body = to_json(value, fmt.ids_as_strings)?;
+    if !fmt.envelope {
+        return Ok(body);
+    }
+    Ok(match count {
+        Some(n) => envelope::wrap_list(&body, n),
+        None => envelope::wrap_one(&body),
+    })
+}
+
+/// Fields rendered as JSON strings when `?ids_as_strings=true` is requested.
+const ID_FIELDS: &[&str] = &["id", "customer_id"];
+
+/// Serializes `value`, rendering [`ID_FIELDS`] as JSON strings when `ids_as_strings` is set
+/// so JavaScript clients keep full i64 precision. The default path goes straight through
+/// [`json::to_string`] (serde_json, or simd-json with the `fast-json` feature).
+fn to_json<T: Serialize>(value: &T, ids_as_strings: bool) -> Result<String> {
+    if !ids_as_strings {
+        return Ok(json::to_string(value)?);
+    }
+    let mut value = serde_json::to_value(value)?;
+    stringify_ids(&mut value);
+    Ok(json::to_string(&value)?)
+}
+
+fn stringify_ids(value: &mut Value) {
+    match value {
+        Value::Array(items) => items.iter_mut().for_each(stringify_ids),
+        Value::Object(map) => {
+            for (key, field) in map.iter_mut() {
+                match field {
+                    Value::Number(_) if ID_FIELDS.contains(&key.as_str()) => {
+                        *field = Value::String(field.to_string());
+                    }
+                    // Nested lists, e.g. the per-customer groups of `?customer_ids=`
+                    _ => stringify_ids(field),
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// 500 for a failed `Db::open` or statement; reported as `x-db-healthy: false`, see
+/// [`db_health`].
+fn db_error() -> Result<Response> {
+    db_health::record(false);
+    Ok(Response::builder()
+        .status(500)
+        .header("content-type", "application/json")
+        .body(r#"{"error":"Database error"}"#)
+        .build())
+}
+
+/// Returns the first required variable that is not configured, so the 500 can name it
+/// instead of surfacing an opaque `variables::get` error mid-request.
+fn missing_variable(names: &[&'static str]) -> Option<&'static str> {
+    names.iter().copied().find(|name| variables::get(name).is_err())
+}
+
+/// 500 for an error propagated out of `route`. Returned as-is, Spin would put the raw
+/// error text in the body, and a connection error can quote `db_url` with its password.
+fn internal_error(e: &anyhow::Error) -> Result<Response> {
+    let msg = mask_secrets(&format!("{:#}", e));
+    eprintln!("Handler returned an error: {}", msg);
+    error_response(ApiError::new(500, msg))
+}
+
+/// Spin adapter for the shared [`ApiError`].
+fn error_response(e: ApiError) -> Result<Response> {
+    json_response(e.status, &e.body())
+}
+
 fn json_response(status: u16, body: &str) -> Result<Response> {
     Ok(Response::builder()
         .status(status)
@@ -236,10 +1871,9 @@ fn timed_response(
         .header("content-type", "application/json")
         .header(
             "server-timing",
-            format!(
-                "conn;dur={:.1}, query;dur={:.1}, ser;dur={:.1}",
-                conn_ms, query_ms, ser_ms
-            ),
+            ServerTiming::db(conn_ms, query_ms, ser_ms)
+                .size("body", body.len())
+                .to_string(),
         )
         .body(body.to_owned())
         .build())
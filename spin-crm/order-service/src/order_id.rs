@@ -0,0 +1,98 @@
+//! Order primary keys: `BIGSERIAL` by default, or `UUID` with `id_strategy = "uuid"`
+//! once `k8s/migrations/orders-uuid.sql` has been applied.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use spin_sdk::pg4::{DbValue, Decode, Error, ParameterValue};
+use spin_sdk::variables;
+
+/// Serial ids stay bare JSON numbers; UUIDs are kept in their hyphenated text form.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum OrderId {
+    Serial(i64),
+    Uuid(String),
+}
+
+impl OrderId {
+    /// Parses an `/orders/{id}` segment for the configured strategy.
+    pub fn parse(raw: &str) -> Option<Self> {
+        if uuid_strategy() {
+            uuid::Uuid::parse_str(raw)
+                .ok()
+                .map(|id| OrderId::Uuid(id.hyphenated().to_string()))
+        } else {
+            raw.parse().ok().map(OrderId::Serial)
+        }
+    }
+
+    pub fn to_param(&self) -> ParameterValue {
+        match self {
+            OrderId::Serial(id) => ParameterValue::Int64(*id),
+            OrderId::Uuid(id) => ParameterValue::Uuid(id.clone()),
+        }
+    }
+}
+
+impl Default for OrderId {
+    fn default() -> Self {
+        OrderId::Serial(0)
+    }
+}
+
+/// A batch of ids for `id = ANY(...)`. pg4 has no uuid array parameter, so UUIDs are
+/// sent as `text[]` and cast in SQL, see [`OrderIds::array_type`].
+pub enum OrderIds {
+    Serial(Vec<i64>),
+    Uuid(Vec<String>),
+}
+
+impl OrderIds {
+    /// Parses a JSON id list for the configured strategy: positive integers (`1.0` and
+    /// `"1"` are rejected rather than coerced) or UUID strings. `Err` names what was expected.
+    pub fn parse(ids: &[Value]) -> Result<Self, &'static str> {
+        if uuid_strategy() {
+            ids.iter()
+                .map(|v| v.as_str().and_then(|s| uuid::Uuid::parse_str(s).ok()))
+                .map(|id| id.map(|id| id.hyphenated().to_string()))
+                .collect::<Option<_>>()
+                .map(OrderIds::Uuid)
+                .ok_or("ids must be UUID strings")
+        } else {
+            ids.iter()
+                .map(|v| v.as_i64().filter(|&id| id > 0))
+                .collect::<Option<_>>()
+                .map(OrderIds::Serial)
+                .ok_or("ids must be positive integers")
+        }
+    }
+
+    /// SQL array type the parameter is cast to: `bigint[]` or `uuid[]`.
+    pub fn array_type(&self) -> &'static str {
+        match self {
+            OrderIds::Serial(_) => "bigint[]",
+            OrderIds::Uuid(_) => "uuid[]",
+        }
+    }
+
+    pub fn into_param(self) -> ParameterValue {
+        match self {
+            OrderIds::Serial(ids) => ParameterValue::ArrayInt64(ids.into_iter().map(Some).collect()),
+            OrderIds::Uuid(ids) => ParameterValue::ArrayStr(ids.into_iter().map(Some).collect()),
+        }
+    }
+}
+
+/// Follows the column type rather than the variable, so a half-migrated setup still reads.
+impl Decode for OrderId {
+    fn decode(value: &DbValue) -> Result<Self, Error> {
+        match value {
+            DbValue::Uuid(id) => Ok(OrderId::Uuid(id.clone())),
+            other => i64::decode(other).map(OrderId::Serial),
+        }
+    }
+}
+
+fn uuid_strategy() -> bool {
+    variables::get("id_strategy").map(|v| v == "uuid").unwrap_or(false)
+}
@@ -49,6 +49,67 @@ export function errorPathScenario() {
     errorRate.add(res.status !== 400);
   });
 
+  group('Blank name', () => {
+    for (const name of ['   ', '\t\t']) {
+      const res = http.post(`${BASE_URL}/customers`, JSON.stringify({
+        name,
+        email: 'test@example.com',
+      }), { headers: { 'Content-Type': 'application/json' } });
+      check(res, {
+        'blank name returns 400': (r) => r.status === 400,
+        'blank name error message': (r) => JSON.parse(r.body).error === 'name cannot be blank',
+      });
+      errorRate.add(res.status !== 400);
+    }
+  });
+
+  group('Name is trimmed', () => {
+    const res = http.post(`${BASE_URL}/customers`, JSON.stringify({
+      name: ' Alice ',
+      email: 'alice@example.com',
+    }), { headers: { 'Content-Type': 'application/json' } });
+    check(res, {
+      'padded name returns 201': (r) => r.status === 201,
+      'stored name is trimmed': (r) => JSON.parse(r.body).name === 'Alice',
+    });
+    errorRate.add(res.status !== 201);
+  });
+
+  group('Bulk customers validation', () => {
+    const params = { headers: { 'Content-Type': 'application/json' } };
+    const empty = http.post(`${BASE_URL}/customers/bulk`, '[]', params);
+    const invalid = http.post(`${BASE_URL}/customers/bulk`, JSON.stringify([
+      { name: 'Bulk OK', email: 'bulk-ok@example.com' },
+      { name: 'Bulk NG', email: 'no-at-sign' },
+    ]), params);
+    const duplicate = http.post(`${BASE_URL}/customers/bulk`, JSON.stringify([
+      { name: 'Bulk A', email: 'bulk-dup@example.com' },
+      { name: 'Bulk B', email: 'bulk-dup@example.com' },
+    ]), params);
+    check(null, {
+      'empty batch returns 400': () => empty.status === 400,
+      'invalid item reports its index': () => invalid.status === 400 && JSON.parse(invalid.body).index === 1,
+      'in-batch duplicate returns 409': () => duplicate.status === 409 && JSON.parse(duplicate.body).index === 1,
+    });
+    errorRate.add(empty.status !== 400 || invalid.status !== 400 || duplicate.status !== 409);
+  });
+
+  group('Gzip request body', () => {
+    const payload = JSON.stringify({ name: 'Gzip', email: 'gzip@example.com' });
+    const gzipped = http.post(`${BASE_URL}/customers`, payload, {
+      headers: { 'Content-Type': 'application/json' },
+      compression: 'gzip',
+    });
+    const unsupported = http.post(`${BASE_URL}/customers`, payload, {
+      headers: { 'Content-Type': 'application/json', 'Content-Encoding': 'br' },
+    });
+    check(null, {
+      'gzipped body returns 201': () => gzipped.status === 201,
+      'unsupported encoding returns 415': () => unsupported.status === 415,
+    });
+    errorRate.add(gzipped.status !== 201 || unsupported.status !== 415);
+  });
+
   group('Invalid email (no @)', () => {
     const res = http.post(`${BASE_URL}/customers`, JSON.stringify({
       name: 'Test',
@@ -82,6 +143,28 @@ export function errorPathScenario() {
       'invalid ID returns 400': (r) => r.status === 400,
     });
     errorRate.add(res.status !== 400);
+
+    // Both runtimes answer with the same JSON body, not a framework rejection
+    for (const method of ['GET', 'DELETE']) {
+      const abc = http.request(method, `${BASE_URL}/customers/abc`);
+      check(abc, {
+        [`${method} /customers/abc returns 400`]: (r) => r.status === 400,
+        [`${method} /customers/abc has JSON error body`]: (r) =>
+          r.status === 400 && r.json('error') === 'Invalid customer ID',
+      });
+      errorRate.add(abc.status !== 400);
+    }
+  });
+
+  group('Malformed resource id', () => {
+    for (const path of ['/customers//', '/customers/%20', '/orders/abc']) {
+      const res = http.get(`${BASE_URL}${path}`);
+      // Spin answers 400; Axum's router may reject empty segments with its own 4xx
+      check(res, {
+        'malformed id is rejected, not listed': (r) => r.status >= 400 && r.status < 500,
+      });
+      errorRate.add(res.status < 400 || res.status >= 500);
+    }
   });
 
   group('Order with invalid customer', () => {
@@ -92,6 +175,23 @@ export function errorPathScenario() {
     }), { headers: { 'Content-Type': 'application/json' } });
     check(res, {
       'order with bad customer returns 400': (r) => r.status === 400,
+      // Only the Spin order-service sets this; Axum counts the outcome as a metric instead
+      'verify outcome is not_found when reported': (r) =>
+        !r.headers['X-Verify-Outcome'] || r.headers['X-Verify-Outcome'] === 'not_found',
+    });
+    errorRate.add(res.status !== 400);
+  });
+
+  group('Order with zero customer_id', () => {
+    // 検証呼び出しの 404 ではなく、入力検証の 400 になること（Axum / Spin 共通）
+    const res = http.post(`${BASE_URL}/orders`, JSON.stringify({
+      customer_id: 0,
+      product: 'Test Product',
+      quantity: 1,
+    }), { headers: { 'Content-Type': 'application/json' } });
+    check(res, {
+      'zero customer_id returns 400': (r) => r.status === 400,
+      'zero customer_id is a validation error': (r) => r.body.includes('customer_id must be positive'),
     });
     errorRate.add(res.status !== 400);
   });
@@ -120,6 +220,26 @@ export function errorPathScenario() {
     errorRate.add(res.status !== 400);
   });
 
+  group('Invalid quantity range', () => {
+    for (const query of ['min_quantity=10&max_quantity=5', 'min_quantity=-1']) {
+      const res = http.get(`${BASE_URL}/orders?${query}`);
+      check(res, {
+        'invalid quantity range returns 400': (r) => r.status === 400,
+      });
+      errorRate.add(res.status !== 400);
+    }
+  });
+
+  group('Invalid page parameters', () => {
+    for (const path of ['/customers?limit=0', '/orders?offset=-1']) {
+      const res = http.get(`${BASE_URL}${path}`);
+      check(res, {
+        'invalid limit/offset returns 400': (r) => r.status === 400,
+      });
+      errorRate.add(res.status !== 400);
+    }
+  });
+
   group('Order not found', () => {
     const res = http.get(`${BASE_URL}/orders/999999`);
     check(res, {
@@ -128,6 +248,25 @@ export function errorPathScenario() {
     errorRate.add(res.status !== 404);
   });
 
+  group('Increment order', () => {
+    const params = { headers: { 'Content-Type': 'application/json' } };
+    const zero = http.post(`${BASE_URL}/orders/999999/increment`, JSON.stringify({ by: 0 }), params);
+    const missing = http.post(`${BASE_URL}/orders/999999/increment`, JSON.stringify({ by: 1 }), params);
+    check(null, {
+      'zero increment returns 400': () => zero.status === 400,
+      'missing order returns 404': () => missing.status === 404,
+    });
+    errorRate.add(zero.status !== 400 || missing.status !== 404);
+  });
+
+  group('Unknown sparse field', () => {
+    const res = http.get(`${BASE_URL}/customers?fields=id,password`);
+    check(res, {
+      'unknown field returns 400': (r) => r.status === 400,
+    });
+    errorRate.add(res.status !== 400);
+  });
+
   group('Unknown route', () => {
     const res = http.get(`${BASE_URL}/unknown`);
     check(res, {
@@ -136,6 +275,613 @@ export function errorPathScenario() {
     errorRate.add(res.status !== 404);
   });
 
+  group('Gateway server-timing', () => {
+    const res = http.get(`${BASE_URL}/customers`);
+    const timing = res.headers['Server-Timing'] || '';
+    check(res, {
+      'upstream segments forwarded': () => /query;dur=[\d.]+/.test(timing),
+      'gateway segment appended': () => /gateway;dur=[\d.]+/.test(timing),
+      'body size survives gateway': () => /body;size=\d+/.test(timing),
+      'no dur added to size-only segments': () => !/body;dur=/.test(timing),
+    });
+    errorRate.add(!/gateway;dur=/.test(timing) || !/body;size=\d+/.test(timing));
+  });
+
+  group('Invalid compute format', () => {
+    const res = http.get(`${BASE_URL}/compute?n=10&format=oct`);
+    check(res, {
+      'unknown format returns 400': (r) => r.status === 400,
+      'format error echoes n and format': (r) => r.json('n') === 10 && r.json('format') === 'oct',
+    });
+    errorRate.add(res.status !== 400);
+  });
+
+  group('Compute n limits', () => {
+    const large = http.get(`${BASE_URL}/compute?n=99999999`);
+    const invalid = http.get(`${BASE_URL}/compute?n=abc`);
+    check(large, {
+      'n above maximum returns 400': (r) => r.status === 400,
+      'error echoes n and max': (r) => r.json('n') === 99999999 && r.json('max') === 1000000,
+    });
+    check(invalid, {
+      'non-numeric n returns 400 instead of the default': (r) => r.status === 400,
+      'error echoes the raw n': (r) => r.json('n') === 'abc',
+    });
+    errorRate.add(large.status !== 400 || invalid.status !== 400);
+  });
+
+  group('Plain-text errors', () => {
+    const res = http.get(`${BASE_URL}/customers/999999?error_format=text`);
+    check(res, {
+      'text error keeps status': (r) => r.status === 404,
+      'text error is plain message': (r) =>
+        (r.headers['Content-Type'] || '').startsWith('text/plain') && r.body === 'Customer not found',
+    });
+    errorRate.add(res.status !== 404);
+  });
+
+  group('Misspelled body field', () => {
+    // STRICT_JSON=true なら unknown fields、既定では name 欠落としてどちらも 400
+    const res = http.post(`${BASE_URL}/customers`, JSON.stringify({
+      nmae: 'Typo', email: 'typo@example.com',
+    }), { headers: { 'Content-Type': 'application/json' } });
+    check(res, {
+      'misspelled field returns 400': (r) => r.status === 400,
+    });
+    errorRate.add(res.status !== 400);
+  });
+
+  group('Non-integer order field', () => {
+    const res = http.post(`${BASE_URL}/orders`, JSON.stringify({
+      customer_id: 1, product: 'Widget', quantity: 'five',
+    }), { headers: { 'Content-Type': 'application/json' } });
+    check(res, {
+      'string quantity returns 400': (r) => r.status === 400,
+      'string quantity names the field': (r) => r.body.includes('quantity must be an integer'),
+    });
+    errorRate.add(res.status !== 400);
+  });
+
+  group('Fractional quantity', () => {
+    const customer = http.post(`${BASE_URL}/customers`, JSON.stringify({
+      name: 'Fraction', email: 'fraction@example.com',
+    }), { headers: { 'Content-Type': 'application/json' } });
+    const customerId = customer.status === 201 ? JSON.parse(customer.body).id : 1;
+    // Raw bodies: JSON.stringify(1.0) would send 1
+    const order = (quantity) => http.post(
+      `${BASE_URL}/orders`,
+      `{"customer_id":${customerId},"product":"Widget","quantity":${quantity}}`,
+      { headers: { 'Content-Type': 'application/json' } },
+    );
+    const half = order('1.5');
+    const whole = order('1.0');
+    const two = order('2');
+    check(null, {
+      'quantity 1.5 returns 400': () => half.status === 400,
+      'quantity 1.5 message': () => half.body.includes('quantity must be a whole number'),
+      'quantity 1.0 is rejected, not coerced': () =>
+        whole.status === 400 && whole.body.includes('quantity must be a whole number'),
+      'quantity 2 is accepted': () => two.status === 201,
+    });
+    errorRate.add(half.status !== 400 || whole.status !== 400 || two.status !== 201);
+  });
+
+  group('Product catalog', () => {
+    const res = http.get(`${BASE_URL}/products`);
+    check(res, {
+      'products returns 200': (r) => r.status === 200,
+      'products is an array': (r) => Array.isArray(JSON.parse(r.body)),
+    });
+    errorRate.add(res.status !== 200);
+  });
+
+  group('Offset range', () => {
+    const max = http.get(`${BASE_URL}/customers?limit=1&offset=9223372036854775807`);
+    const over = http.get(`${BASE_URL}/orders?limit=1&offset=99999999999999999999`);
+    check(max, {
+      'offset at i64::MAX returns 200': (r) => r.status === 200,
+      'offset past end is empty': (r) => r.status === 200 && JSON.parse(r.body).length === 0,
+    });
+    check(over, {
+      'offset beyond i64 returns 400': (r) => r.status === 400,
+      'offset beyond i64 message': (r) => r.body.includes('offset out of range'),
+    });
+    errorRate.add(max.status !== 200 || over.status !== 400);
+  });
+
+  group('Pagination Link header', () => {
+    const res = http.get(`${BASE_URL}/customers?limit=1&offset=0&ids_as_strings=true`);
+    const link = res.headers['Link'] || '';
+    check(res, {
+      'paged list returns 200': (r) => r.status === 200,
+      'first link keeps other params': () =>
+        link.includes('</customers?ids_as_strings=true&limit=1&offset=0>; rel="first"'),
+      'no prev on first page': () => !link.includes('rel="prev"'),
+      'last link present': () => /offset=\d+>; rel="last"/.test(link),
+    });
+    errorRate.add(res.status !== 200 || !link.includes('rel="first"'));
+  });
+
+  group('Reverify requires admin token', () => {
+    const res = http.post(`${BASE_URL}/admin/orders/reverify`, null);
+    check(res, {
+      'reverify without token is rejected': (r) => r.status === 401 || r.status === 403,
+    });
+    errorRate.add(res.status !== 401 && res.status !== 403);
+  });
+
+  group('HEAD compute', () => {
+    const res = http.head(`${BASE_URL}/compute?n=10`);
+    check(res, {
+      'HEAD compute returns 200': (r) => r.status === 200,
+      'HEAD compute keeps server-timing': (r) => /compute;dur=/.test(r.headers['Server-Timing'] || ''),
+      'HEAD compute has no body': (r) => !r.body,
+    });
+    errorRate.add(res.status !== 200);
+  });
+
+  group('Envelope wrapping', () => {
+    const res = http.get(`${BASE_URL}/customers?envelope=true&limit=1`);
+    let body = null;
+    try { body = JSON.parse(res.body); } catch (e) { /* checked below */ }
+    check(res, {
+      'envelope list returns 200': (r) => r.status === 200,
+      'envelope data is an array': () => body !== null && Array.isArray(body.data),
+      'envelope meta.count matches data': () =>
+        body !== null && body.meta && body.meta.count === body.data.length,
+    });
+    errorRate.add(res.status !== 200 || body === null || !Array.isArray(body.data));
+  });
+
+  group('Orders for multiple customers', () => {
+    const ok = http.get(`${BASE_URL}/orders?customer_ids=1,2`);
+    const bad = http.get(`${BASE_URL}/orders?customer_ids=1,abc`);
+    check(ok, {
+      'customer_ids returns 200': (r) => r.status === 200,
+      'customer_ids groups by customer': (r) => {
+        const body = JSON.parse(r.body);
+        return !Array.isArray(body) && Object.keys(body).every((k) => k === '1' || k === '2');
+      },
+    });
+    check(bad, {
+      'non-numeric customer_ids returns 400': (r) => r.status === 400,
+    });
+    errorRate.add(ok.status !== 200 || bad.status !== 400);
+  });
+
+  group('Customer order count', () => {
+    const res = http.get(`${BASE_URL}/customers?with_order_count=true&limit=1`);
+    const plain = http.get(`${BASE_URL}/customers?limit=1`);
+    const bad = http.get(`${BASE_URL}/customers?with_order_count=true&fields=id`);
+    check(res, {
+      'with_order_count returns 200': (r) => r.status === 200,
+      'order_count is a number': (r) => {
+        const body = JSON.parse(r.body);
+        return body.every((c) => typeof c.order_count === 'number');
+      },
+    });
+    check(plain, {
+      'order_count omitted by default': (r) =>
+        r.status === 200 && JSON.parse(r.body).every((c) => !('order_count' in c)),
+    });
+    check(bad, {
+      'with_order_count plus fields returns 400': (r) => r.status === 400,
+    });
+    errorRate.add(res.status !== 200 || bad.status !== 400);
+  });
+
+  group('PATCH passthrough', () => {
+    // 更新エンドポイントは未実装のため上流の 405 が返れば転送されている（gateway 自身は 404/502）
+    const res = http.patch(`${BASE_URL}/customers/1`, JSON.stringify({ name: 'Patched' }), {
+      headers: { 'Content-Type': 'application/json' },
+    });
+    check(res, {
+      'PATCH reaches the upstream': (r) => r.status === 405,
+      'PATCH goes through the proxy': (r) => r.headers['X-Retry-Count'] !== undefined,
+    });
+    errorRate.add(res.status !== 405);
+  });
+
+  group('Schema version', () => {
+    const res = http.get(`${BASE_URL}/schemaz`);
+    let body = null;
+    try { body = JSON.parse(res.body); } catch (e) { /* checked below */ }
+    check(res, {
+      'schemaz returns 200': (r) => r.status === 200,
+      'schemaz reports both upstreams': () =>
+        body !== null && 'customer-service' in body && 'order-service' in body,
+      'schema matches the build': () => body !== null && body.mismatch === false,
+    });
+    errorRate.add(res.status !== 200 || body === null || body.mismatch !== false);
+  });
+
+  group('Compute memory stats', () => {
+    const res = http.get(`${BASE_URL}/compute?n=10&mem=true`);
+    check(res, {
+      'compute mem returns 200': (r) => r.status === 200,
+      'compute mem reports memory_bytes': (r) => 'memory_bytes' in JSON.parse(r.body),
+    });
+    errorRate.add(res.status !== 200);
+  });
+
+  group('Quantity int32 range', () => {
+    const customer = http.post(`${BASE_URL}/customers`, JSON.stringify({
+      name: 'Int32', email: 'int32@example.com',
+    }), { headers: { 'Content-Type': 'application/json' } });
+    const customerId = customer.status === 201 ? JSON.parse(customer.body).id : 1;
+    const order = (quantity) => http.post(`${BASE_URL}/orders`, JSON.stringify({
+      customer_id: customerId, product: 'Widget', quantity,
+    }), { headers: { 'Content-Type': 'application/json' } });
+    const max = order(2147483647);
+    const over = order(2147483648);
+    check(null, {
+      'quantity at i32::MAX is accepted': () => max.status === 201,
+      'quantity above i32::MAX returns 400': () => over.status === 400,
+      'quantity above i32::MAX message': () => over.body.includes('quantity out of range'),
+    });
+    errorRate.add(max.status !== 201 || over.status !== 400);
+  });
+
+  group('NDJSON order export', () => {
+    const res = http.get(`${BASE_URL}/orders/export?customer_id=1`);
+    const bad = http.get(`${BASE_URL}/orders/export?customer_id=abc`);
+    check(res, {
+      'export returns 200': (r) => r.status === 200,
+      'export is ndjson': (r) => (r.headers['Content-Type'] || '').includes('application/x-ndjson'),
+      'every line is an order of customer 1': (r) =>
+        r.body.split('\n').filter((l) => l !== '').every((l) => JSON.parse(l).customer_id === 1),
+    });
+    check(bad, {
+      'non-numeric export customer_id returns 400': (r) => r.status === 400,
+    });
+    errorRate.add(res.status !== 200 || bad.status !== 400);
+  });
+
+  group('Request count headers', () => {
+    const res = http.get(`${BASE_URL}/customers?limit=1`);
+    check(res, {
+      'gateway reports x-request-count': (r) => Number(r.headers['X-Request-Count']) >= 1,
+      'upstream count is forwarded': (r) => Number(r.headers['X-Upstream-Request-Count']) >= 1,
+      // Only the first WARMUP_REQUESTS (Axum) or an instance's first request (Spin) carry it
+      'x-warmup is absent or true': (r) =>
+        r.headers['X-Warmup'] === undefined || r.headers['X-Warmup'] === 'true',
+    });
+    errorRate.add(res.headers['X-Request-Count'] === undefined);
+  });
+
+  group('Boolean query params', () => {
+    const yes = http.get(`${BASE_URL}/customers?limit=1&envelope=YES`);
+    const one = http.get(`${BASE_URL}/compute?n=10&raw=1`);
+    const bad = http.get(`${BASE_URL}/customers?limit=1&envelope=maybe`);
+    check(yes, {
+      'envelope=YES is accepted as true': (r) => r.status === 200 && JSON.parse(r.body).data !== undefined,
+    });
+    check(one, {
+      'raw=1 is accepted as true': (r) => r.status === 200 && r.body === '55',
+    });
+    check(bad, {
+      'unknown boolean spelling returns 400': (r) => r.status === 400,
+    });
+    errorRate.add(yes.status !== 200 || one.status !== 200 || bad.status !== 400);
+  });
+
+  group('Merge requires admin token', () => {
+    const res = http.post(`${BASE_URL}/customers/1/merge`, JSON.stringify({ into: 2 }), {
+      headers: { 'Content-Type': 'application/json' },
+    });
+    check(res, {
+      'merge without token is rejected': (r) => r.status === 401 || r.status === 403,
+    });
+    errorRate.add(res.status !== 401 && res.status !== 403);
+  });
+
+  group('Reassign requires admin token', () => {
+    const res = http.post(
+      `${BASE_URL}/orders/reassign`,
+      JSON.stringify({ from_customer_id: 1, to_customer_id: 2 }),
+      { headers: { 'Content-Type': 'application/json' } },
+    );
+    check(res, {
+      'reassign without token is rejected': (r) => r.status === 401 || r.status === 403,
+    });
+    errorRate.add(res.status !== 401 && res.status !== 403);
+  });
+
+  group('Bulk order delete requires admin token', () => {
+    const res = http.post(`${BASE_URL}/orders/delete`, JSON.stringify({ ids: [1, 2] }), {
+      headers: { 'Content-Type': 'application/json' },
+    });
+    check(res, {
+      'bulk delete without token is rejected': (r) => r.status === 401 || r.status === 403,
+    });
+    errorRate.add(res.status !== 401 && res.status !== 403);
+  });
+
+  group('Order generator requires admin token', () => {
+    const res = http.post(`${BASE_URL}/admin/orders/generate?count=10&customer_id=1`, null);
+    check(res, {
+      'generate without token is rejected': (r) => r.status === 401 || r.status === 403,
+    });
+    errorRate.add(res.status !== 401 && res.status !== 403);
+  });
+
+  group('Oversized request headers', () => {
+    // Axum only: the Spin host applies its own header limits
+    const runtime = http.get(`${BASE_URL}/healthz`).headers['X-Gateway-Runtime'];
+    if (runtime !== 'axum') {
+      return;
+    }
+    const res = http.get(`${BASE_URL}/healthz`, { headers: { Cookie: 'a'.repeat(20000) } });
+    check(res, {
+      'oversized headers return 431': (r) => r.status === 431,
+    });
+    errorRate.add(res.status !== 431);
+  });
+
+  group('Debug SQL header', () => {
+    // Off unless DEBUG_SQL / debug_sql is enabled; when on, only the statement and count show
+    const res = http.get(`${BASE_URL}/customers?limit=1&offset=0`);
+    const debug = res.headers['X-Debug-Sql'];
+    check(res, {
+      'x-debug-sql is absent or carries only the parameter count': () =>
+        debug === undefined || (debug.startsWith('SELECT ') && debug.endsWith('; params=2')),
+    });
+    errorRate.add(debug !== undefined && !debug.endsWith('; params=2'));
+  });
+
+  group('Gateway loop detection', () => {
+    // A request that already went through MAX_HOPS (default 3) forwards is not forwarded again
+    const res = http.get(`${BASE_URL}/customers`, { headers: { 'X-Hop-Count': '3' } });
+    check(res, {
+      'exhausted hop count returns 508': (r) => r.status === 508,
+      'loop detected message': (r) => r.json('error') === 'Loop detected',
+    });
+    errorRate.add(res.status !== 508);
+  });
+
+  group('Customers CSV export', () => {
+    const created = http.post(`${BASE_URL}/customers`, JSON.stringify({
+      name: "O'Brien, Inc", email: `csv-${Date.now()}@example.com`,
+    }), { headers: { 'Content-Type': 'application/json' } });
+    const res = http.get(`${BASE_URL}/customers?format=csv`);
+    const viaAccept = http.get(`${BASE_URL}/customers`, { headers: { Accept: 'text/csv' } });
+    const invalid = http.get(`${BASE_URL}/customers?format=xml`);
+    check(res, {
+      'csv returns 200': (r) => r.status === 200,
+      'csv content type': (r) => (r.headers['Content-Type'] || '').startsWith('text/csv'),
+      'csv is an attachment': (r) =>
+        r.headers['Content-Disposition'] === 'attachment; filename="customers.csv"',
+      'csv starts with a header row': (r) => r.body.startsWith('id,name,email'),
+      'comma in name is quoted': (r) => r.body.includes(`"O'Brien, Inc"`),
+      'Accept: text/csv selects csv': () => viaAccept.body.startsWith('id,name,email'),
+      'unknown format returns 400': () => invalid.status === 400,
+    });
+    errorRate.add(res.status !== 200 || invalid.status !== 400);
+    if (created.status === 201) {
+      http.del(`${BASE_URL}/customers/${JSON.parse(created.body).id}`);
+    }
+  });
+
+  group('Route listing', () => {
+    const res = http.get(`${BASE_URL}/routez`);
+    const body = res.status === 200 ? JSON.parse(res.body) : {};
+    const lists = (key) => (Array.isArray(body[key]) ? body[key] : []);
+    const has = (key, path, method) =>
+      lists(key).some((r) => r.path === path && r.methods.includes(method));
+    check(res, {
+      'routez returns 200': (r) => r.status === 200,
+      'gateway lists /compute': () => has('gateway', '/compute', 'GET'),
+      'customer-service lists POST /customers': () => has('customer-service', '/customers', 'POST'),
+      'order-service lists GET /orders/{id}': () => has('order-service', '/orders/{id}', 'GET'),
+      'prefixes name their upstream': () =>
+        (body.prefixes || []).some((p) => p.prefix === '/customers' && p.upstream === 'customer-service'),
+    });
+    errorRate.add(res.status !== 200);
+  });
+
+  group('Delete with representation', () => {
+    const create = () => http.post(`${BASE_URL}/customers`, JSON.stringify({
+      name: 'Delete Me', email: `delete-${Date.now()}-${Math.random()}@example.com`,
+    }), { headers: { 'Content-Type': 'application/json' } });
+    const withBody = create();
+    const plain = create();
+    const prefer = { headers: { Prefer: 'return=representation' } };
+    const idOf = (r) => (r.status === 201 ? JSON.parse(r.body).id : 999999);
+    const res = http.del(`${BASE_URL}/customers/${idOf(withBody)}`, null, prefer);
+    const noPrefer = http.del(`${BASE_URL}/customers/${idOf(plain)}`);
+    check(res, {
+      'prefer representation returns 200': (r) => r.status === 200,
+      'deleted customer is returned': (r) => r.status === 200 && r.json('name') === 'Delete Me',
+      'preference-applied is set': (r) =>
+        r.headers['Preference-Applied'] === 'return=representation',
+      'without prefer returns 204': () => noPrefer.status === 204,
+    });
+    errorRate.add(res.status !== 200 || noPrefer.status !== 204);
+  });
+
+  group('Runtime headers', () => {
+    const res = http.get(`${BASE_URL}/compute?n=10`);
+    const runtime = res.headers['X-Runtime'];
+    check(res, {
+      'x-runtime names the framework': () => runtime === 'axum' || runtime === 'spin',
+      'gateway adds x-gateway-runtime': (r) => r.headers['X-Gateway-Runtime'] === runtime,
+    });
+    errorRate.add(runtime !== 'axum' && runtime !== 'spin');
+  });
+
+  group('Validate emails', () => {
+    const params = { headers: { 'Content-Type': 'application/json' } };
+    const res = http.post(`${BASE_URL}/customers/validate`, JSON.stringify({
+      emails: ['preflight@example.com', 'no-at-sign'],
+    }), params);
+    const tooMany = http.post(`${BASE_URL}/customers/validate`, JSON.stringify({
+      emails: Array(1001).fill('a@example.com'),
+    }), params);
+    const body = res.status === 200 ? JSON.parse(res.body) : [];
+    check(null, {
+      'validate returns 200': () => res.status === 200,
+      'results keep request order': () => body.length === 2 && body[1].email === 'no-at-sign',
+      'invalid email is flagged': () => body.length === 2 && body[1].valid === false,
+      'over 1000 emails returns 400': () => tooMany.status === 400,
+    });
+    errorRate.add(res.status !== 200 || tooMany.status !== 400);
+  });
+
+  group('Gateway keeps upstream content-type', () => {
+    // customer-service answers this 404 as text/plain; the gateway must not relabel it as JSON
+    const res = http.get(`${BASE_URL}/customers/999999?error_format=text`);
+    check(res, {
+      'text error returns 404': (r) => r.status === 404,
+      'content-type is text/plain': (r) => (r.headers['Content-Type'] || '').startsWith('text/plain'),
+      'body is the bare message': (r) => r.body === 'Customer not found',
+    });
+    errorRate.add(res.status !== 404 || !(res.headers['Content-Type'] || '').startsWith('text/plain'));
+  });
+
+  group('Expired request deadline', () => {
+    const res = http.get(`${BASE_URL}/customers?limit=1`, {
+      headers: { 'X-Request-Deadline': String(Date.now() - 1000) },
+    });
+    const later = http.get(`${BASE_URL}/customers?limit=1`, {
+      headers: { 'X-Request-Deadline': String(Date.now() + 10000) },
+    });
+    check(null, {
+      'past deadline returns 504': () => res.status === 504,
+      'future deadline is served': () => later.status === 200,
+    });
+    errorRate.add(res.status !== 504 || later.status !== 200);
+  });
+
+  group('Empty list page', () => {
+    // Past the last page: 200 [] by default, 204 with EMPTY_LIST_204 / empty_list_204
+    const res = http.get(`${BASE_URL}/orders?limit=10&offset=100000000`);
+    check(res, {
+      'empty page is 200 [] or 204': (r) =>
+        (r.status === 200 && r.body === '[]') || (r.status === 204 && !r.body),
+      'empty page keeps server-timing': (r) => !!r.headers['Server-Timing'],
+    });
+    errorRate.add(res.status !== 200 && res.status !== 204);
+  });
+
+  group('Duplicate order window', () => {
+    // -e DEDUP_ORDERS_WINDOW_MS=N must match the order-service setting
+    // (DEDUP_ORDERS_WINDOW_MS / dedup_orders_window_ms). Unset means the default: the
+    // repeat is accepted (201). With N set: the repeat inside the window is 409.
+    const windowMs = Number(__ENV.DEDUP_ORDERS_WINDOW_MS || 0);
+    const customer = http.post(`${BASE_URL}/customers`, JSON.stringify({
+      name: 'Dedup', email: `dedup-${Date.now()}@example.com`,
+    }), { headers: { 'Content-Type': 'application/json' } });
+    const customerId = customer.status === 201 ? JSON.parse(customer.body).id : 0;
+    const payload = { customer_id: customerId, product: `Dedup ${Date.now()}`, quantity: 1 };
+    const send = () => http.post(`${BASE_URL}/orders`, JSON.stringify(payload), {
+      headers: { 'Content-Type': 'application/json' },
+    });
+    const first = send();
+    const second = send();
+    const expected = windowMs > 0 ? 409 : 201;
+    check(second, {
+      'first order is 201': () => first.status === 201,
+      [`repeat order is ${expected}`]: (r) => r.status === expected,
+      'rejected repeat says duplicate order': (r) =>
+        r.status !== 409 || r.json('error') === 'duplicate order',
+    });
+    errorRate.add(first.status !== 201 || second.status !== expected);
+  });
+
+  group('Per-customer order limit', () => {
+    // -e MAX_ORDERS_PER_CUSTOMER=N must match the order-service setting
+    // (MAX_ORDERS_PER_CUSTOMER / max_orders_per_customer). Unset means the default,
+    // unlimited: every create is 201. With N set: exactly N × 201, then 409.
+    const cap = Number(__ENV.MAX_ORDERS_PER_CUSTOMER || 0);
+    const customer = http.post(`${BASE_URL}/customers`, JSON.stringify({
+      name: 'Order Limit', email: `order-limit-${Date.now()}@example.com`,
+    }), { headers: { 'Content-Type': 'application/json' } });
+    const customerId = customer.status === 201 ? JSON.parse(customer.body).id : 0;
+    const attempts = cap > 0 ? cap + 2 : 5;
+    const responses = [];
+    for (let i = 0; i < attempts; i++) {
+      responses.push(http.post(`${BASE_URL}/orders`, JSON.stringify({
+        customer_id: customerId, product: `Limit ${i}`, quantity: 1,
+      }), { headers: { 'Content-Type': 'application/json' } }));
+    }
+    const expected = responses.map((_, i) => (cap > 0 && i >= cap ? 409 : 201));
+    const ok = customer.status === 201 && responses.every((r, i) => r.status === expected[i]);
+    check(null, {
+      'customer for limit test created': () => customer.status === 201,
+      'orders up to the limit are 201': () =>
+        responses.every((r, i) => expected[i] !== 201 || r.status === 201),
+      'orders past the limit are 409': () =>
+        responses.every((r, i) => expected[i] !== 409 || r.status === 409),
+      'limit message': () =>
+        responses.every((r) => r.status !== 409 || r.json('error') === 'order limit reached'),
+    });
+    errorRate.add(!ok);
+  });
+
+  group('Range header paging', () => {
+    // 206 with Content-Range for a satisfiable range; 416 only when the table is empty
+    const ok = http.get(`${BASE_URL}/customers`, { headers: { Range: 'items=0-0' } });
+    check(ok, {
+      'items range is 206 (or 416 when empty)': (r) => r.status === 206 || r.status === 416,
+      'content-range reports the total': (r) =>
+        /^items (\d+-\d+|\*)\/\d+$/.test(r.headers['Content-Range'] || ''),
+    });
+    errorRate.add(ok.status !== 206 && ok.status !== 416);
+
+    const bad = http.get(`${BASE_URL}/customers`, { headers: { Range: 'items=5-1' } });
+    check(bad, {
+      'malformed range returns 400': (r) => r.status === 400,
+      'malformed range has error field': (r) => JSON.parse(r.body).error !== undefined,
+    });
+    errorRate.add(bad.status !== 400);
+  });
+
+  group('Product length boundary', () => {
+    // Assumes the default MAX_PRODUCT_LEN / max_product_len of 255
+    const customer = http.post(`${BASE_URL}/customers`, JSON.stringify({
+      name: 'Product Len', email: 'product-len@example.com',
+    }), { headers: { 'Content-Type': 'application/json' } });
+    const customerId = customer.status === 201 ? JSON.parse(customer.body).id : 1;
+    const order = (product) => http.post(`${BASE_URL}/orders`, JSON.stringify({
+      customer_id: customerId, product, quantity: 1,
+    }), { headers: { 'Content-Type': 'application/json' } });
+    const atLimit = order('p'.repeat(255));
+    const over = order('p'.repeat(256));
+    check(null, {
+      'product at the limit is accepted': () => atLimit.status === 201,
+      'product over the limit returns 400': () => over.status === 400,
+      'product over the limit message': () =>
+        over.body.includes('product must be 255 characters or less'),
+    });
+    errorRate.add(atLimit.status !== 201 || over.status !== 400);
+  });
+
+  group('Order history', () => {
+    const customer = http.post(`${BASE_URL}/customers`, JSON.stringify({
+      name: 'History', email: 'history@example.com',
+    }), { headers: { 'Content-Type': 'application/json' } });
+    const customerId = customer.status === 201 ? JSON.parse(customer.body).id : 1;
+    const order = http.post(`${BASE_URL}/orders`, JSON.stringify({
+      customer_id: customerId, product: 'Widget', quantity: 1,
+    }), { headers: { 'Content-Type': 'application/json' } });
+    const orderId = order.status === 201 ? JSON.parse(order.body).id : 1;
+    http.post(`${BASE_URL}/orders/${orderId}/increment`, JSON.stringify({ by: 2 }), {
+      headers: { 'Content-Type': 'application/json' },
+    });
+    const res = http.get(`${BASE_URL}/orders/${orderId}/history`);
+    const missing = http.get(`${BASE_URL}/orders/999999999/history`);
+    check(res, {
+      'history returns 200': (r) => r.status === 200,
+      'history starts with creation': (r) => JSON.parse(r.body)[0].change === 'created',
+      'history records the increment': (r) =>
+        JSON.parse(r.body).some((e) => e.change === 'quantity +2' && !!e.changed_at),
+    });
+    check(missing, {
+      'history of a missing order returns 404': (r) => r.status === 404,
+    });
+    errorRate.add(res.status !== 200 || missing.status !== 404);
+  });
+
   group('Health check', () => {
     const res = http.get(`${BASE_URL}/healthz`);
     check(res, {